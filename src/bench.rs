@@ -0,0 +1,260 @@
+//! `bench`, which runs the shader repeatedly and reports its timings, and with `--soak`, how
+//! stable the machine is.
+
+use std::{
+    error::Error,
+    num::{NonZeroU32, NonZeroUsize},
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use gpu_scratch::{
+    RunError,
+    hash::{Sha256, to_hex},
+    plan::{Budget, DispatchSize, Plan},
+};
+
+use crate::{
+    CheckError, PersistedPipelineCache, cli, math_profile, output_size, power, read_inputs,
+    read_params, read_push_constants, reflect, run_context, set_constants, shader_source,
+};
+
+const DEFAULT_SOAK: Duration = Duration::from_secs(10);
+
+/// The number of windows the soak is split into when reporting drift.
+const WINDOWS: usize = 10;
+
+/// How much slower the second half of a soak must be than the first to look like throttling.
+const THROTTLE_THRESHOLD: f64 = 0.10;
+
+/// A duration such as `500ms`, `30s`, `10min`, or `1h`.
+pub struct HumanDuration(pub Duration);
+
+impl FromStr for HumanDuration {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let split = value
+            .find(|char: char| !char.is_ascii_digit())
+            .unwrap_or(value.len());
+        let (amount, unit) = value.split_at(split);
+
+        let amount: u64 = amount
+            .parse()
+            .map_err(|_| String::from("expected a number followed by a unit"))?;
+        let duration = match unit {
+            "ms" => Duration::from_millis(amount),
+            "s" | "" => Duration::from_secs(amount),
+            "min" => Duration::from_secs(amount * 60),
+            "h" => Duration::from_secs(amount * 60 * 60),
+            _ => return Err(format!("unknown unit `{unit}`, expected ms, s, min, or h")),
+        };
+
+        Ok(Self(duration))
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+fn mean_millis(timings: &[Duration]) -> f64 {
+    timings.iter().copied().map(millis).sum::<f64>() / timings.len() as f64
+}
+
+/// The timing `fraction` of the way through `sorted`, by the nearest rank.
+fn percentile(sorted: &[Duration], fraction: f64) -> Option<Duration> {
+    let rank = (sorted.len() as f64 * fraction).ceil() as usize;
+    sorted.get(rank.saturating_sub(1)).copied()
+}
+
+/// Prints the distribution of `timings`, with the rate of invocations at the median.
+fn report_timings(what: &str, timings: &[Duration], invocations: Option<u64>) {
+    let mut sorted = timings.to_vec();
+    sorted.sort_unstable();
+
+    let stat = |fraction| percentile(&sorted, fraction).map_or(f64::NAN, millis);
+    println!(
+        "{what}: min {:.3} ms, median {:.3} ms, p95 {:.3} ms, mean {:.3} ms, max {:.3} ms",
+        stat(0.0),
+        stat(0.5),
+        stat(0.95),
+        mean_millis(timings),
+        stat(1.0),
+    );
+
+    if let (Some(invocations), Some(median)) = (invocations, percentile(&sorted, 0.5))
+        && !median.is_zero()
+    {
+        let rate = invocations as f64 / median.as_secs_f64();
+        println!("  throughput: {rate:.3e} invocations/s at the median");
+    }
+}
+
+/// How long a bench runs for.
+enum Length {
+    Soak(Duration),
+    Iterations(usize),
+}
+
+impl Length {
+    fn done(&self, start: Instant, iterations: usize) -> bool {
+        match *self {
+            Self::Soak(soak) => start.elapsed() >= soak,
+            Self::Iterations(count) => iterations >= count,
+        }
+    }
+}
+
+/// Runs the shader for `--iterations`, or until `--soak` has elapsed, after `--warmup` runs, then
+/// reports timing statistics, drift, likely throttling, and any iteration whose output differed
+/// from the first warm-up run.
+pub async fn bench(matches: &cli::Matches) -> Result<(), Box<dyn Error>> {
+    let profile = math_profile(matches)?;
+    matches.check_conflict("soak", "iterations")?;
+    let length = match matches.parse_value::<NonZeroUsize>("iterations")? {
+        Some(iterations) => Length::Iterations(iterations.get()),
+        None => Length::Soak(
+            matches
+                .parse_value::<HumanDuration>("soak")?
+                .map_or(DEFAULT_SOAK, |soak| soak.0),
+        ),
+    };
+    let warmup = matches
+        .parse_value::<NonZeroUsize>("warmup")?
+        .map_or(1, NonZeroUsize::get);
+
+    let gpu = run_context(matches).await?;
+    let source = shader_source(matches)?;
+    let reflection = reflect(matches, &source)?;
+    let mut plan = Plan::new(&reflection, output_size(matches)?);
+    plan.set_entry_point(&reflection, matches.value("entry-point"))?;
+    set_constants(matches, &reflection, &mut plan)?;
+    plan.init = matches.parse_value("init")?;
+    for contents in read_inputs(matches)? {
+        plan.add_input(&reflection, contents);
+    }
+    if let Some(params) = read_params(matches)? {
+        plan.set_params_bytes(&reflection, params);
+    }
+    if let Some(push_constants) = read_push_constants(matches)? {
+        plan.set_push_constants(&reflection, push_constants)?;
+    }
+    plan.bind_remaining(&reflection)?;
+    plan.dispatch = matches
+        .parse_value("dispatch")?
+        .unwrap_or(DispatchSize::ONE);
+    plan.slice_workgroups = matches
+        .parse_value::<NonZeroU32>("time-slice")?
+        .map(NonZeroU32::get);
+    plan.zero_copy = gpu.supports_zero_copy();
+    plan.check(&gpu.device.limits(), &Budget::default())?;
+
+    match length {
+        Length::Soak(soak) => eprintln!("Soaking for {soak:?}"),
+        Length::Iterations(iterations) => eprintln!("Running {iterations} iterations"),
+    }
+
+    // The shader is compiled once, so every iteration only pays for encoding and submitting it.
+    let pipeline_cache = PersistedPipelineCache::open(matches, &gpu, &source, &plan, profile);
+    let prepared = gpu.prepare(&source, &plan, profile, pipeline_cache.get())?;
+    pipeline_cache.save();
+    // Each output buffer is recycled once read, so iterations do not allocate one each.
+    let run_once = || -> Result<(String, Duration, Option<Duration>), RunError> {
+        let shader_run = gpu.run_prepared(&prepared)?;
+        let data = gpu.read_buffer(&shader_run.output)?;
+        let (elapsed, gpu_elapsed) = (shader_run.elapsed, shader_run.gpu_elapsed);
+        gpu.recycle(shader_run);
+
+        let mut hasher = Sha256::default();
+        hasher.update(&data);
+        Ok((to_hex(&hasher.finish()), elapsed, gpu_elapsed))
+    };
+
+    // The warm-up runs let caches and clocks settle, so only use them for their output.
+    let (reference_hash, ..) = run_once()?;
+    for _ in 1..warmup {
+        run_once()?;
+    }
+
+    let sensor = matches
+        .is_present("energy")
+        .then(|| power::Sensor::find().ok_or(CheckError::NoPowerSensor))
+        .transpose()?;
+
+    let mut timings = Vec::new();
+    let mut gpu_timings = Vec::new();
+    let mut diverged = 0;
+
+    let meter = sensor.as_ref().map(power::Sensor::start);
+    let start = Instant::now();
+    while !length.done(start, timings.len()) {
+        let (hash, elapsed, gpu_elapsed) = run_once()?;
+        if hash != reference_hash {
+            log::warn!("Iteration {} produced sha256 {hash}", timings.len());
+            diverged += 1;
+        }
+
+        timings.push(elapsed);
+        gpu_timings.extend(gpu_elapsed);
+    }
+
+    let joules = meter.map(power::Meter::stop);
+
+    let iterations = timings.len();
+    let invocations = plan.cost().invocations;
+    println!("{iterations} iterations after {warmup} warm-up");
+    report_timings("Wall time", &timings, invocations);
+    if gpu_timings.len() == iterations && iterations > 0 {
+        report_timings("GPU time", &gpu_timings, invocations);
+    }
+
+    // This includes the time between submissions, so idle power counts towards every run too.
+    if let (Some(sensor), Some(joules)) = (&sensor, joules) {
+        let per_run = joules / iterations as f64;
+        let words = plan.output_size / size_of::<u32>() as u64;
+        println!("Energy: {joules:.3} J from {}", sensor.driver);
+        println!("  {per_run:.3e} J per run");
+        println!("  {:.3e} J per output word", per_run / words as f64);
+        if let Some(invocations) = invocations {
+            println!("  {:.3e} J per invocation", per_run / invocations as f64);
+        }
+    }
+
+    let window_size = iterations.div_ceil(WINDOWS).max(1);
+    let windows: Vec<_> = timings.chunks(window_size).map(mean_millis).collect();
+    for (index, mean) in windows.iter().enumerate() {
+        println!("  window {:>2}: mean {mean:.3} ms", index + 1);
+    }
+
+    if let (Some(first), Some(last)) = (windows.first(), windows.last()) {
+        println!(
+            "Drift: {:+.1}% from the first to the last window",
+            (last / first - 1.0) * 100.0
+        );
+    }
+
+    let (first_half, second_half) = timings.split_at(iterations / 2);
+    if !first_half.is_empty() {
+        let slowdown = mean_millis(second_half) / mean_millis(first_half) - 1.0;
+        if slowdown > THROTTLE_THRESHOLD {
+            println!(
+                "Throttling: the second half ran {:.1}% slower than the first",
+                slowdown * 100.0
+            );
+        } else {
+            println!("Throttling: none detected");
+        }
+    }
+
+    if diverged > 0 {
+        return Err(CheckError::Diverged {
+            diverged,
+            iterations,
+        }
+        .into());
+    }
+
+    println!("Divergence: every output matched the warm-up run");
+    Ok(())
+}
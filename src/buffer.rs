@@ -0,0 +1,194 @@
+//! Buffer descriptions built from the roles a buffer plays, rather than raw usage flags.
+
+use std::fmt::{self, Display};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BufferRole {
+    /// Read by shaders, and written by uploads or clears before the pass.
+    Input,
+    /// Written by shaders, then copied out of.
+    Output,
+    /// Mapped by the host to read back data copied into it.
+    Readback,
+    /// Mapped by the host to read back data shaders wrote to it directly, which needs
+    /// `MAPPABLE_PRIMARY_BUFFERS`.
+    Mapped,
+    /// Filled at creation by the host, then copied out of.
+    Staging,
+    /// Read by shaders as `var<uniform>`, and written by uploads.
+    Uniform,
+    /// Written by resolving a query set, then copied out of.
+    QueryResolve,
+}
+
+impl BufferRole {
+    fn usages(self) -> wgpu::BufferUsages {
+        use wgpu::BufferUsages as U;
+
+        match self {
+            Self::Input => U::STORAGE | U::COPY_DST,
+            Self::Output => U::STORAGE | U::COPY_SRC,
+            Self::Readback => U::MAP_READ | U::COPY_DST,
+            Self::Mapped => U::MAP_READ,
+            Self::Staging => U::COPY_SRC,
+            Self::Uniform => U::UNIFORM | U::COPY_DST,
+            Self::QueryResolve => U::QUERY_RESOLVE | U::COPY_SRC,
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum BufferSpecError {
+    #[error("Buffer `{label}` has no roles")]
+    NoRoles { label: String },
+    #[error("Buffer `{label}` is read back, so it cannot also be used as {role:?}")]
+    MappedWith { label: String, role: BufferRole },
+    #[error("Buffer `{label}` is {size} bytes, which is not a multiple of {align} as copies need")]
+    Unaligned {
+        label: String,
+        size: u64,
+        align: u64,
+    },
+}
+
+/// Describes a buffer by its label, size, and roles, deriving the `wgpu::BufferUsages` it needs.
+#[derive(Clone)]
+pub struct BufferSpec {
+    pub label: String,
+    pub size: u64,
+    roles: Vec<BufferRole>,
+}
+
+impl BufferSpec {
+    pub fn new(label: impl Into<String>, size: u64) -> Self {
+        Self {
+            label: label.into(),
+            size,
+            roles: Vec::new(),
+        }
+    }
+
+    pub fn role(mut self, role: BufferRole) -> Self {
+        if !self.roles.contains(&role) {
+            self.roles.push(role);
+        }
+
+        self
+    }
+
+    pub fn usages(&self) -> wgpu::BufferUsages {
+        let usages = self.roles.iter().map(|role| role.usages());
+        usages.fold(wgpu::BufferUsages::empty(), |a, b| a | b)
+    }
+
+    /// Checks for usage combinations wgpu would reject when the buffer is created or copied.
+    pub fn validate(&self) -> Result<(), BufferSpecError> {
+        let label = self.label.clone();
+        if self.roles.is_empty() {
+            return Err(BufferSpecError::NoRoles { label });
+        }
+
+        // Without `MAPPABLE_PRIMARY_BUFFERS`, a mapped buffer can only be a copy destination.
+        if self.roles.contains(&BufferRole::Readback)
+            && let Some(&role) = self.roles.iter().find(|&&r| r != BufferRole::Readback)
+        {
+            return Err(BufferSpecError::MappedWith { label, role });
+        }
+
+        let copies = wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST;
+        let align = wgpu::COPY_BUFFER_ALIGNMENT;
+        if self.usages().intersects(copies) && !self.size.is_multiple_of(align) {
+            return Err(BufferSpecError::Unaligned {
+                label,
+                size: self.size,
+                align,
+            });
+        }
+
+        Ok(())
+    }
+
+    pub fn descriptor(&self) -> wgpu::BufferDescriptor<'_> {
+        wgpu::BufferDescriptor {
+            label: Some(&self.label),
+            size: self.size,
+            usage: self.usages(),
+            mapped_at_creation: false,
+        }
+    }
+}
+
+impl Display for BufferSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let usages: Vec<_> = self.usages().iter_names().map(|(name, _)| name).collect();
+        write!(
+            f,
+            "{:<20} {:>10} bytes  {}",
+            self.label,
+            self.size,
+            usages.join(" | ")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BufferRole, BufferSpec, BufferSpecError};
+
+    #[test]
+    fn usages() {
+        let spec = BufferSpec::new("working", 64)
+            .role(BufferRole::Input)
+            .role(BufferRole::Output)
+            .role(BufferRole::Input);
+        assert_eq!(
+            spec.usages(),
+            wgpu::BufferUsages::STORAGE
+                | wgpu::BufferUsages::COPY_SRC
+                | wgpu::BufferUsages::COPY_DST
+        );
+        assert!(spec.validate().is_ok());
+    }
+
+    #[test]
+    fn no_roles() {
+        assert!(matches!(
+            BufferSpec::new("empty", 64).validate(),
+            Err(BufferSpecError::NoRoles { label }) if label == "empty"
+        ));
+    }
+
+    #[test]
+    fn mapped_with() {
+        let spec = BufferSpec::new("readback", 64)
+            .role(BufferRole::Readback)
+            .role(BufferRole::Uniform);
+        assert!(matches!(
+            spec.validate(),
+            Err(BufferSpecError::MappedWith {
+                role: BufferRole::Uniform,
+                ..
+            })
+        ));
+
+        let spec = BufferSpec::new("readback", 64).role(BufferRole::Readback);
+        assert!(spec.validate().is_ok());
+    }
+
+    #[test]
+    fn unaligned() {
+        let spec = BufferSpec::new("output", 6).role(BufferRole::Output);
+        assert!(matches!(
+            spec.validate(),
+            Err(BufferSpecError::Unaligned {
+                size: 6,
+                align: wgpu::COPY_BUFFER_ALIGNMENT,
+                ..
+            })
+        ));
+
+        // Buffers that are never copied need no alignment.
+        let spec = BufferSpec::new("mapped", 6).role(BufferRole::Mapped);
+        assert!(spec.validate().is_ok());
+    }
+}
@@ -0,0 +1,387 @@
+//! Content-addressed on-disk cache for compiled shader artifacts.
+//!
+//! Entries are keyed by a hash of the shader source, the adapter, and the compile options, so
+//! separate invocations on the same machine can share work. Pipeline binaries are stored whole.
+//! naga cannot serialize modules without serde, so a validated module is recorded by an empty
+//! entry instead, which lets [`Reflection::with_cache`] skip checking it again.
+//!
+//! The cache is trimmed back under its size limit after every store, evicting the least
+//! recently used entries first. Only files named like entries count towards the limit, so other
+//! files in the directory are left alone.
+//!
+//! [`Reflection::with_cache`]: crate::reflect::Reflection::with_cache
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{
+    hash::{Sha256, to_hex},
+    shader::Shader,
+};
+
+const DEFAULT_MAX_SIZE: u64 = 64 * 1024 * 1024;
+
+pub struct ArtifactCache {
+    dir: PathBuf,
+    max_size: u64,
+}
+
+impl ArtifactCache {
+    /// Opens the cache in `dir`, or if not provided, `$GPU_SCRATCH_CACHE_DIR`, falling back to
+    /// `$XDG_CACHE_HOME/gpu-scratch` then `$HOME/.cache/gpu-scratch`.
+    ///
+    /// The size limit in bytes can be overridden with `$GPU_SCRATCH_CACHE_SIZE`.
+    pub fn open(dir: Option<&Path>) -> io::Result<Self> {
+        let dir = if let Some(dir) = dir {
+            dir.to_owned()
+        } else if let Some(dir) = std::env::var_os("GPU_SCRATCH_CACHE_DIR") {
+            PathBuf::from(dir)
+        } else if let Some(dir) = std::env::var_os("XDG_CACHE_HOME") {
+            Path::new(&dir).join("gpu-scratch")
+        } else if let Some(home) = std::env::var_os("HOME") {
+            Path::new(&home).join(".cache").join("gpu-scratch")
+        } else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "no cache directory could be determined",
+            ));
+        };
+
+        let max_size = std::env::var("GPU_SCRATCH_CACHE_SIZE")
+            .ok()
+            .and_then(|size| size.parse().ok())
+            .unwrap_or(DEFAULT_MAX_SIZE);
+
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir, max_size })
+    }
+
+    /// Derives the cache key for an artifact compiled from `source` on `adapter`.
+    ///
+    /// `options` must describe everything else that affects compilation, such as the entry
+    /// point and pipeline compilation options.
+    pub fn key(source: &[u8], adapter: &wgpu::AdapterInfo, options: &str) -> String {
+        Self::hash(&[
+            source,
+            adapter.name.as_bytes(),
+            adapter.driver.as_bytes(),
+            adapter.driver_info.as_bytes(),
+            &adapter.vendor.to_le_bytes(),
+            &adapter.device.to_le_bytes(),
+            adapter.backend.to_str().as_bytes(),
+            options.as_bytes(),
+        ])
+    }
+
+    /// Derives the cache key for the validation of `shader`, which does not depend on the
+    /// adapter, but does on the version of naga validating it.
+    pub fn module_key(shader: &Shader) -> String {
+        Self::hash(&[
+            shader.as_bytes(),
+            shader.language().name().as_bytes(),
+            env!("CARGO_PKG_VERSION").as_bytes(),
+        ])
+    }
+
+    fn hash(parts: &[&[u8]]) -> String {
+        let mut hasher = Sha256::default();
+        for part in parts {
+            // Length-prefix each part so that moving bytes between parts changes the key.
+            hasher.update(&(part.len() as u64).to_le_bytes());
+            hasher.update(part);
+        }
+
+        to_hex(&hasher.finish())
+    }
+
+    fn path(&self, kind: &str, key: &str) -> PathBuf {
+        self.dir.join(format!("{key}.{kind}"))
+    }
+
+    /// Loads a cached artifact, marking it as recently used.
+    pub fn load(&self, kind: &str, key: &str) -> Option<Vec<u8>> {
+        let path = self.path(kind, key);
+        let data = fs::read(&path).ok()?;
+
+        if let Err(err) = fs::File::options()
+            .write(true)
+            .open(&path)
+            .and_then(|file| file.set_modified(SystemTime::now()))
+        {
+            log::debug!("Unable to refresh {}: {err}", path.display());
+        }
+
+        Some(data)
+    }
+
+    /// Stores an artifact, then evicts old entries until the cache is within its size limit.
+    pub fn store(&self, kind: &str, key: &str, data: &[u8]) -> io::Result<()> {
+        let path = self.path(kind, key);
+
+        // Write then rename, so concurrent readers never observe a partial artifact.
+        let temp_path = path.with_extension(format!("{kind}.{}.tmp", std::process::id()));
+        fs::write(&temp_path, data)?;
+        fs::rename(&temp_path, &path)?;
+
+        self.evict()
+    }
+
+    /// Whether `name` is a file the cache wrote, named `<hex key>.<kind>`, rather than a
+    /// temporary file or something else that shares the directory.
+    fn is_entry(name: &str) -> bool {
+        name.split_once('.').is_some_and(|(key, kind)| {
+            key.len() == 64
+                && key
+                    .bytes()
+                    .all(|byte| matches!(byte, b'0'..=b'9' | b'a'..=b'f'))
+                && !kind.is_empty()
+                && kind
+                    .bytes()
+                    .all(|byte| byte.is_ascii_alphanumeric() || byte == b'-')
+        })
+    }
+
+    fn evict(&self) -> io::Result<()> {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            if !entry.file_name().to_str().is_some_and(Self::is_entry) {
+                continue;
+            }
+
+            // Another process may evict the entry first.
+            let metadata = match entry.metadata() {
+                Ok(metadata) => metadata,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(err),
+            };
+            if metadata.is_file() {
+                entries.push((metadata.modified()?, metadata.len(), entry.path()));
+            }
+        }
+
+        let mut total_size: u64 = entries.iter().map(|(_, size, _)| size).sum();
+        entries.sort_unstable_by_key(|(modified, _, _)| *modified);
+
+        for (_, size, path) in entries {
+            if total_size <= self.max_size {
+                break;
+            }
+
+            log::debug!("Evicting {} from the artifact cache", path.display());
+            match fs::remove_file(&path) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                _ => total_size -= size,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        path::PathBuf,
+        time::{Duration, SystemTime},
+    };
+
+    use super::ArtifactCache;
+    use crate::{reflect::Reflection, shader::Shader};
+
+    /// A cache in a fresh directory, removed when the cache is dropped.
+    struct TestCache(ArtifactCache);
+
+    impl TestCache {
+        fn new(name: &str, max_size: u64) -> Self {
+            let dir = std::env::temp_dir()
+                .join(format!("gpu-scratch-cache-{name}-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            Self(ArtifactCache { dir, max_size })
+        }
+
+        fn file(&self, name: &str) -> PathBuf {
+            self.0.dir.join(name)
+        }
+
+        /// Writes `name` with `size` bytes, last modified `age` seconds ago.
+        fn write(&self, name: &str, size: usize, age: u64) {
+            fs::write(self.file(name), vec![0; size]).unwrap();
+            fs::File::options()
+                .write(true)
+                .open(self.file(name))
+                .unwrap()
+                .set_modified(SystemTime::now() - Duration::from_secs(age))
+                .unwrap();
+        }
+    }
+
+    impl Drop for TestCache {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0.dir);
+        }
+    }
+
+    fn key(index: u8) -> String {
+        format!("{index:064x}")
+    }
+
+    fn adapter() -> wgpu::AdapterInfo {
+        wgpu::AdapterInfo {
+            name: String::from("Test GPU"),
+            vendor: 0x10de,
+            device: 0x2204,
+            device_type: wgpu::DeviceType::DiscreteGpu,
+            driver: String::from("test"),
+            driver_info: String::from("1.0"),
+            backend: wgpu::Backend::Vulkan,
+        }
+    }
+
+    #[test]
+    fn key_stability() {
+        let key = ArtifactCache::key(b"source", &adapter(), "entry=main");
+        // Keys changing would invalidate every existing cache.
+        assert_eq!(
+            key,
+            "6406983474765f5c72e69f0c233c93f761528c86dc9a8fcfbff4e2085abe1675"
+        );
+        assert_eq!(key, ArtifactCache::key(b"source", &adapter(), "entry=main"));
+        assert!(ArtifactCache::is_entry(&format!("{key}.pipeline")));
+
+        let mut other = adapter();
+        other.driver_info = String::from("1.1");
+        assert_ne!(key, ArtifactCache::key(b"source", &other, "entry=main"));
+        assert_ne!(
+            key,
+            ArtifactCache::key(b"source", &adapter(), "entry=other")
+        );
+        // Moving bytes between parts changes the key.
+        assert_ne!(
+            ArtifactCache::key(b"source", &adapter(), "entry=main"),
+            ArtifactCache::key(b"sourc", &adapter(), "eentry=main")
+        );
+
+        let wgsl = Shader::Wgsl("source".into());
+        let glsl = Shader::Glsl("source".into());
+        assert_eq!(
+            ArtifactCache::module_key(&wgsl),
+            ArtifactCache::module_key(&wgsl.clone())
+        );
+        assert_ne!(
+            ArtifactCache::module_key(&wgsl),
+            ArtifactCache::module_key(&glsl)
+        );
+    }
+
+    #[test]
+    fn store_and_load() {
+        let cache = TestCache::new("round-trip", 1024);
+        let key = key(1);
+        assert_eq!(cache.0.load("pipeline", &key), None);
+
+        cache.0.store("pipeline", &key, b"binary").unwrap();
+        assert_eq!(
+            cache.0.load("pipeline", &key).as_deref(),
+            Some(&b"binary"[..])
+        );
+        assert_eq!(cache.0.load("validated", &key), None);
+
+        cache.0.store("pipeline", &key, b"replaced").unwrap();
+        assert_eq!(
+            cache.0.load("pipeline", &key).as_deref(),
+            Some(&b"replaced"[..])
+        );
+    }
+
+    #[test]
+    fn load_refreshes_entries() {
+        let cache = TestCache::new("refresh", 250);
+        cache.write(&format!("{}.pipeline", key(1)), 100, 30);
+        cache.write(&format!("{}.pipeline", key(2)), 100, 20);
+        assert!(cache.0.load("pipeline", &key(1)).is_some());
+
+        // Storing a third entry evicts the least recently used one, which is now the second.
+        cache.0.store("pipeline", &key(3), &[0; 100]).unwrap();
+        assert!(cache.file(&format!("{}.pipeline", key(1))).exists());
+        assert!(!cache.file(&format!("{}.pipeline", key(2))).exists());
+        assert!(cache.file(&format!("{}.pipeline", key(3))).exists());
+    }
+
+    #[test]
+    fn validated_modules() {
+        let cache = TestCache::new("validated", 1024);
+        let source = "@group(0) @binding(0) var<storage, read_write> out: array<u32>;\n\
+                      @compute @workgroup_size(64)\n\
+                      fn main(@builtin(global_invocation_id) id: vec3<u32>) {\n\
+                      out[id.x] = id.x;\n\
+                      }\n";
+        let shader = Shader::Wgsl(source.into());
+
+        let first = Reflection::with_cache(&shader, &cache.0).unwrap();
+        let key = ArtifactCache::module_key(&shader);
+        assert_eq!(cache.0.load("validated", &key).as_deref(), Some(&[][..]));
+
+        // A cached module skips the checks, but is reflected the same.
+        let second = Reflection::with_cache(&shader, &cache.0).unwrap();
+        let names = |reflection: &Reflection| {
+            (reflection.bindings().into_iter())
+                .map(|binding| binding.name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&first), names(&second));
+
+        // Invalid modules are not recorded.
+        let invalid =
+            Shader::Wgsl("@compute @workgroup_size(1) fn main() { let x: u32 = 1.5; }".into());
+        assert!(Reflection::with_cache(&invalid, &cache.0).is_err());
+        let key = ArtifactCache::module_key(&invalid);
+        assert_eq!(cache.0.load("validated", &key), None);
+    }
+
+    #[test]
+    fn is_entry() {
+        assert!(ArtifactCache::is_entry(&format!("{}.pipeline", key(1))));
+        for name in [
+            format!("{}.pipeline.42.tmp", key(1)),
+            format!("{}.", key(1)),
+            format!("{}.pipeline", &key(1)[1..]),
+            format!("{}.pipeline", "A".repeat(64)),
+            String::from("notes.txt"),
+        ] {
+            assert!(!ArtifactCache::is_entry(&name), "{name}");
+        }
+    }
+
+    #[test]
+    fn evicts_oldest_entries() {
+        let cache = TestCache::new("evict", 250);
+        for (index, age) in [(1, 30), (2, 10), (3, 20)] {
+            cache.write(&format!("{}.pipeline", key(index)), 100, age);
+        }
+
+        cache.0.evict().unwrap();
+        assert!(!cache.file(&format!("{}.pipeline", key(1))).exists());
+        assert!(cache.file(&format!("{}.pipeline", key(2))).exists());
+        assert!(cache.file(&format!("{}.pipeline", key(3))).exists());
+    }
+
+    #[test]
+    fn eviction_skips_other_files() {
+        let cache = TestCache::new("foreign", 50);
+        let temp = format!("{}.pipeline.1.tmp", key(1));
+        cache.write("notes.txt", 100, 60);
+        cache.write(&temp, 100, 60);
+        cache.write(&format!("{}.pipeline", key(2)), 40, 30);
+
+        cache.0.evict().unwrap();
+        assert!(cache.file("notes.txt").exists());
+        assert!(cache.file(&temp).exists());
+        assert!(cache.file(&format!("{}.pipeline", key(2))).exists());
+    }
+}
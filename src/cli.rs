@@ -0,0 +1,1241 @@
+//! Command line parsing, driven by a static description of every command and flag.
+//!
+//! The same description generates `--help`, the shell completion scripts, and the JSON schema
+//! printed by `--cli-schema`, so adding a flag here is enough to expose it everywhere.
+
+use std::fmt::Write as _;
+
+use crate::{exit::ExitStatus, json::Json};
+
+pub const BINARY_NAME: &str = env!("CARGO_PKG_NAME");
+
+/// What kind of value a flag or positional argument accepts, used to drive completions.
+#[derive(Clone, Copy)]
+pub enum ValueKind {
+    /// Free-form text.
+    Text,
+    /// A path on the filesystem.
+    Path,
+    /// One of a fixed set of values.
+    OneOf(&'static [&'static str]),
+}
+
+impl ValueKind {
+    fn to_json(self) -> Json {
+        match self {
+            Self::Text => Json::Object(vec![("kind", "text".into())]),
+            Self::Path => Json::Object(vec![("kind", "path".into())]),
+            Self::OneOf(values) => Json::Object(vec![
+                ("kind", "one-of".into()),
+                (
+                    "values",
+                    Json::Array(values.iter().map(|&v| v.into()).collect()),
+                ),
+            ]),
+        }
+    }
+}
+
+pub struct FlagSpec {
+    /// The flag name, without the leading `--`.
+    pub long: &'static str,
+    pub about: &'static str,
+    /// The placeholder name and kind of the flag's value, if it takes one.
+    pub value: Option<(&'static str, ValueKind)>,
+}
+
+pub struct PositionalSpec {
+    pub name: &'static str,
+    pub about: &'static str,
+    pub kind: ValueKind,
+}
+
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub about: &'static str,
+    pub flags: &'static [FlagSpec],
+    pub positionals: &'static [PositionalSpec],
+}
+
+pub const SHELLS: &[&str] = &["bash", "zsh", "fish", "powershell"];
+
+/// Flags accepted by every command.
+pub static GLOBAL_FLAGS: &[FlagSpec] = &[
+    FlagSpec {
+        long: "help",
+        about: "Print usage information",
+        value: None,
+    },
+    FlagSpec {
+        long: "cli-schema",
+        about: "Print a JSON description of every command and flag",
+        value: None,
+    },
+];
+
+const SHADER_FLAG: FlagSpec = FlagSpec {
+    long: "shader",
+    about: "Run the WGSL, SPIR-V, or GLSL shader in this file instead of the built-in one",
+    value: Some(("file", ValueKind::Path)),
+};
+
+const LANG_FLAG: FlagSpec = FlagSpec {
+    long: "lang",
+    about: "The language of shader files, instead of guessing from the extension (`.spv`, `.comp`)",
+    value: Some((
+        "lang",
+        ValueKind::OneOf(gpu_scratch::shader::Language::NAMES),
+    )),
+};
+
+const OUTPUT_SIZE_FLAG: FlagSpec = FlagSpec {
+    long: "output-size",
+    about: "The size of the working buffer read back as output, in bytes (default 48)",
+    value: Some(("bytes", ValueKind::Text)),
+};
+
+const INIT_FLAG: FlagSpec = FlagSpec {
+    long: "init",
+    about: "Initialize the working buffer first: `zero`, `iota`, or `fill:<u32>`",
+    value: Some(("mode", ValueKind::Text)),
+};
+
+const INPUT_FLAG: FlagSpec = FlagSpec {
+    long: "input",
+    about: "Upload a file's bytes, or a `.npy` array's data, into a storage buffer bound after the output, may be repeated",
+    value: Some(("file", ValueKind::Path)),
+};
+
+const PARAM_FLAG: FlagSpec = FlagSpec {
+    long: "param",
+    about: "Append a `u32:`, `i32:`, or `f32:` value to the shader's uniform buffer, may be repeated",
+    value: Some(("type:value", ValueKind::Text)),
+};
+
+const PUSH_FLAG: FlagSpec = FlagSpec {
+    long: "push",
+    about: "Append a `u32:`, `i32:`, or `f32:` value to the shader's `var<push_constant>`, may be repeated",
+    value: Some(("type:value", ValueKind::Text)),
+};
+
+const CONST_FLAG: FlagSpec = FlagSpec {
+    long: "const",
+    about: "Set an `override` constant, like `WORKGROUP_SIZE=256`, may be repeated",
+    value: Some(("name=value", ValueKind::Text)),
+};
+
+const ENTRY_POINT_FLAG: FlagSpec = FlagSpec {
+    long: "entry-point",
+    about: "The compute entry point to dispatch, if the shader has more than one",
+    value: Some(("name", ValueKind::Text)),
+};
+
+const DISPATCH_FLAG: FlagSpec = FlagSpec {
+    long: "dispatch",
+    about: "The number of workgroups to dispatch, like `64x1x1` (default 1x1x1)",
+    value: Some(("XxYxZ", ValueKind::Text)),
+};
+
+const TIME_SLICE_FLAG: FlagSpec = FlagSpec {
+    long: "time-slice",
+    about: "Submit at most this many workgroups in x at a time, offset by `var<uniform> slice_offset`",
+    value: Some(("workgroups", ValueKind::Text)),
+};
+
+/// The backends `--backend` accepts.
+const BACKENDS: &[&str] = &["vulkan", "dx12", "metal", "gl"];
+
+const BACKEND_FLAG: FlagSpec = FlagSpec {
+    long: "backend",
+    about: "Only consider adapters on this graphics backend",
+    value: Some(("backend", ValueKind::OneOf(BACKENDS))),
+};
+
+const ADAPTER_FLAG: FlagSpec = FlagSpec {
+    long: "adapter",
+    about: "Run on this adapter, by its index in `list-adapters` or part of its name",
+    value: Some(("index|name", ValueKind::Text)),
+};
+
+const EVENTS_FLAG: FlagSpec = FlagSpec {
+    long: "events",
+    about: "Stream each compile, submission, pass and readback as it happens, one JSON object per line",
+    value: Some(("format", ValueKind::OneOf(crate::events::FORMATS))),
+};
+
+const EVENTS_TO_FLAG: FlagSpec = FlagSpec {
+    long: "events-to",
+    about: "Write `--events` to this file, like `/dev/fd/3`, instead of stderr",
+    value: Some(("file", ValueKind::Path)),
+};
+
+const POLL_FLAG: FlagSpec = FlagSpec {
+    long: "poll",
+    about: "How to wait for the GPU: `block`, `low-latency` to spin then back off (the default), or `low-cpu`",
+    value: Some((
+        "strategy",
+        ValueKind::OneOf(gpu_scratch::poll::PollStrategy::NAMES),
+    )),
+};
+
+const CACHE_DIR_FLAG: FlagSpec = FlagSpec {
+    long: "cache-dir",
+    about: "Directory to cache compiled shader artifacts in",
+    value: Some(("dir", ValueKind::Path)),
+};
+
+const STRICT_MATH_FLAG: FlagSpec = FlagSpec {
+    long: "strict-math",
+    about: "Bounds-check accesses and zero workgroup memory (the default)",
+    value: None,
+};
+
+const FAST_MATH_FLAG: FlagSpec = FlagSpec {
+    long: "fast-math",
+    about: "Skip runtime shader checks and workgroup memory zeroing",
+    value: None,
+};
+
+/// Every command, with the first being run if no command is given.
+pub static COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "run",
+        about: "Run the compute shader and print its output",
+        flags: &[
+            SHADER_FLAG,
+            LANG_FLAG,
+            CACHE_DIR_FLAG,
+            FlagSpec {
+                long: "memory-report",
+                about: "Print the workgroup and private memory used by each entry point",
+                value: None,
+            },
+            FlagSpec {
+                long: "occupancy-report",
+                about: "Print subgroup occupancy hints for each entry point",
+                value: None,
+            },
+            FlagSpec {
+                long: "watch",
+                about: "Rerun the --shader file every time it changes, until interrupted",
+                value: None,
+            },
+            FlagSpec {
+                long: "explain",
+                about: "Print the planned buffers, bindings, passes and copies before running",
+                value: None,
+            },
+            FlagSpec {
+                long: "dry-run",
+                about: "Print the plan like --explain, but do not run it",
+                value: None,
+            },
+            FlagSpec {
+                long: "emit-graph",
+                about: "Write the plan's passes as a Graphviz DOT graph, with timings once run",
+                value: Some(("file", ValueKind::Path)),
+            },
+            FlagSpec {
+                long: "max-invocations",
+                about: "Refuse to run plans with more shader invocations than this",
+                value: Some(("count", ValueKind::Text)),
+            },
+            FlagSpec {
+                long: "max-vram",
+                about: "Refuse to run plans that allocate more GPU memory than this, in bytes",
+                value: Some(("bytes", ValueKind::Text)),
+            },
+            OUTPUT_SIZE_FLAG,
+            INIT_FLAG,
+            INPUT_FLAG,
+            PARAM_FLAG,
+            PUSH_FLAG,
+            CONST_FLAG,
+            ENTRY_POINT_FLAG,
+            DISPATCH_FLAG,
+            TIME_SLICE_FLAG,
+            BACKEND_FLAG,
+            ADAPTER_FLAG,
+            EVENTS_FLAG,
+            EVENTS_TO_FLAG,
+            POLL_FLAG,
+            STRICT_MATH_FLAG,
+            FAST_MATH_FLAG,
+            FlagSpec {
+                long: "mesh",
+                about: "Upload an OBJ or PLY mesh's positions and triangle indices as the first two inputs",
+                value: Some(("file", ValueKind::Path)),
+            },
+            FlagSpec {
+                long: "write-mesh",
+                about: "Size the output to the --mesh positions, and write it with the mesh's faces as OBJ or PLY",
+                value: Some(("file", ValueKind::Path)),
+            },
+            FlagSpec {
+                long: "expect-hash",
+                about: "Fail unless the output's SHA-256 matches this hex digest",
+                value: Some(("sha256", ValueKind::Text)),
+            },
+            FlagSpec {
+                long: "scalar",
+                about: "Only read back the first output word, and print it as this type",
+                value: Some(("type", ValueKind::OneOf(crate::ScalarType::NAMES))),
+            },
+            FlagSpec {
+                long: "output-format",
+                about: "Print the output as `debug` bytes (the default), a `json` array, `csv` rows, raw `bin`, `hex`, or an `npy` array",
+                value: Some((
+                    "format",
+                    ValueKind::OneOf(crate::output::OutputFormat::NAMES),
+                )),
+            },
+            FlagSpec {
+                long: "output-file",
+                about: "Write the output to this file instead of stdout, in the format its extension implies unless --output-format is given",
+                value: Some(("file", ValueKind::Path)),
+            },
+            FlagSpec {
+                long: "element-type",
+                about: "The type to decode output words as for --output-format and --post (default u32)",
+                value: Some(("type", ValueKind::OneOf(crate::ScalarType::NAMES))),
+            },
+            FlagSpec {
+                long: "readback-chunk",
+                about: "Read the output back in chunks of at most this many bytes, streaming them to --output-to, and only keeping the whole output if it is decoded",
+                value: Some(("bytes", ValueKind::Text)),
+            },
+            FlagSpec {
+                long: "output-to",
+                about: "Also write the raw output bytes to this file, or `tcp:<host>:<port>`",
+                value: Some(("target", ValueKind::Path)),
+            },
+            FlagSpec {
+                long: "summarize",
+                about: "Print the min, max, mean and standard deviation of the output, and a histogram of it",
+                value: None,
+            },
+            FlagSpec {
+                long: "post",
+                about: "Evaluate an expression over the output, like `mean(out)`, may be repeated",
+                value: Some(("expr", ValueKind::Text)),
+            },
+        ],
+        positionals: &[],
+    },
+    CommandSpec {
+        name: "bench",
+        about: "Time repeated runs of the shader, reporting drift, throttling and divergence",
+        flags: &[
+            FlagSpec {
+                long: "soak",
+                about: "How long to run for, like `30s` or `10min` (default 10s)",
+                value: Some(("duration", ValueKind::Text)),
+            },
+            FlagSpec {
+                long: "iterations",
+                about: "Run exactly this many times instead of for a duration",
+                value: Some(("count", ValueKind::Text)),
+            },
+            FlagSpec {
+                long: "warmup",
+                about: "Runs to discard before timing, the first setting the expected output (default 1)",
+                value: Some(("count", ValueKind::Text)),
+            },
+            FlagSpec {
+                long: "energy",
+                about: "Measure the GPU's energy use from its hwmon sensor, per run and per result",
+                value: None,
+            },
+            SHADER_FLAG,
+            LANG_FLAG,
+            CACHE_DIR_FLAG,
+            OUTPUT_SIZE_FLAG,
+            INIT_FLAG,
+            INPUT_FLAG,
+            PARAM_FLAG,
+            PUSH_FLAG,
+            CONST_FLAG,
+            ENTRY_POINT_FLAG,
+            DISPATCH_FLAG,
+            TIME_SLICE_FLAG,
+            BACKEND_FLAG,
+            ADAPTER_FLAG,
+            EVENTS_FLAG,
+            EVENTS_TO_FLAG,
+            POLL_FLAG,
+            STRICT_MATH_FLAG,
+            FAST_MATH_FLAG,
+        ],
+        positionals: &[],
+    },
+    CommandSpec {
+        name: "compare",
+        about: "Run two shaders on the same input, diffing their outputs and GPU times",
+        flags: &[
+            OUTPUT_SIZE_FLAG,
+            INIT_FLAG,
+            INPUT_FLAG,
+            PARAM_FLAG,
+            CONST_FLAG,
+            ENTRY_POINT_FLAG,
+            DISPATCH_FLAG,
+            BACKEND_FLAG,
+            ADAPTER_FLAG,
+            LANG_FLAG,
+            FlagSpec {
+                long: "repeat",
+                about: "Dispatch each shader this many times and compare the fastest (default 5)",
+                value: Some(("count", ValueKind::Text)),
+            },
+            FlagSpec {
+                long: "element-type",
+                about: "The type to decode output words as before comparing them (default u32)",
+                value: Some(("type", ValueKind::OneOf(crate::ScalarType::NAMES))),
+            },
+            FlagSpec {
+                long: "tolerance",
+                about: "Allow output values to differ by up to this much (default 0)",
+                value: Some(("delta", ValueKind::Text)),
+            },
+            FlagSpec {
+                long: "relative-tolerance",
+                about: "Also allow output values to differ by up to this fraction of the larger one (default 0)",
+                value: Some(("fraction", ValueKind::Text)),
+            },
+            STRICT_MATH_FLAG,
+            FAST_MATH_FLAG,
+        ],
+        positionals: &[
+            PositionalSpec {
+                name: "a",
+                about: "The baseline shader",
+                kind: ValueKind::Path,
+            },
+            PositionalSpec {
+                name: "b",
+                about: "The shader to compare against the baseline",
+                kind: ValueKind::Path,
+            },
+        ],
+    },
+    CommandSpec {
+        name: "sweep",
+        about: "Run the shader with every combination of `override` constant values, timing each",
+        flags: &[
+            FlagSpec {
+                long: "constant",
+                about: "Sweep an `override` constant over these values, like `tile=8,16,32`, may be repeated",
+                value: Some(("name=values", ValueKind::Text)),
+            },
+            FlagSpec {
+                long: "repeat",
+                about: "Dispatch each variant this many times and report the fastest (default 3)",
+                value: Some(("count", ValueKind::Text)),
+            },
+            SHADER_FLAG,
+            LANG_FLAG,
+            OUTPUT_SIZE_FLAG,
+            INIT_FLAG,
+            INPUT_FLAG,
+            PARAM_FLAG,
+            CONST_FLAG,
+            ENTRY_POINT_FLAG,
+            DISPATCH_FLAG,
+            BACKEND_FLAG,
+            ADAPTER_FLAG,
+            STRICT_MATH_FLAG,
+            FAST_MATH_FLAG,
+        ],
+        positionals: &[],
+    },
+    CommandSpec {
+        name: "selftest",
+        about: "Run tiny built-in kernels on the adapter, reporting which capabilities work",
+        flags: &[BACKEND_FLAG, ADAPTER_FLAG],
+        positionals: &[],
+    },
+    CommandSpec {
+        name: "list-adapters",
+        about: "List the GPU adapters `--adapter` can pick from",
+        flags: &[BACKEND_FLAG],
+        positionals: &[],
+    },
+    CommandSpec {
+        name: "journal",
+        about: "List past run, bench, compare, and sweep commands recorded in the journal",
+        flags: &[],
+        positionals: &[],
+    },
+    CommandSpec {
+        name: "replay",
+        about: "Re-run a command from the journal, failing if its shaders or a run's output changed",
+        flags: &[],
+        positionals: &[PositionalSpec {
+            name: "id",
+            about: "The id of the run, as listed by the journal command",
+            kind: ValueKind::Text,
+        }],
+    },
+    CommandSpec {
+        name: "completions",
+        about: "Print a shell completion script",
+        flags: &[],
+        positionals: &[PositionalSpec {
+            name: "shell",
+            about: "The shell to generate completions for",
+            kind: ValueKind::OneOf(SHELLS),
+        }],
+    },
+];
+
+#[derive(Debug, thiserror::Error)]
+pub enum CliError {
+    #[error("Unknown flag `--{0}`, see `--help`")]
+    UnknownFlag(String),
+    #[error("Flag `--{0}` requires a value")]
+    MissingValue(&'static str),
+    #[error("Flag `--{0}` does not take a value")]
+    UnexpectedValue(&'static str),
+    #[error("Missing required argument <{0}>")]
+    MissingPositional(&'static str),
+    #[error("Unexpected argument `{0}`")]
+    UnexpectedPositional(String),
+    #[error("Flags `--{0}` and `--{1}` cannot be used together")]
+    Conflict(&'static str, &'static str),
+    #[error("Flag `--{0}` can only be used with `--{1}`")]
+    Requires(&'static str, &'static str),
+    #[error("Invalid value `{value}` for {name}, expected one of: {}", expected.join(", "))]
+    InvalidValue {
+        name: String,
+        value: String,
+        expected: &'static [&'static str],
+    },
+    #[error("Unable to parse `{value}` for {name}: {reason}")]
+    Unparsable {
+        name: String,
+        value: String,
+        reason: String,
+    },
+}
+
+/// The result of parsing the command line against [`COMMANDS`].
+pub struct Matches {
+    pub command: &'static CommandSpec,
+    flags: Vec<(&'static str, Option<String>)>,
+    positionals: Vec<String>,
+}
+
+impl Matches {
+    pub fn is_present(&self, long: &str) -> bool {
+        self.flags.iter().any(|(name, _)| *name == long)
+    }
+
+    /// Returns the value of the last occurrence of `--long`.
+    pub fn value(&self, long: &str) -> Option<&str> {
+        self.flags
+            .iter()
+            .rev()
+            .find(|(name, _)| *name == long)
+            .and_then(|(_, value)| value.as_deref())
+    }
+
+    /// Returns the values of every occurrence of `--long`, in order.
+    pub fn values<'a>(&'a self, long: &'a str) -> impl Iterator<Item = &'a str> {
+        self.flags
+            .iter()
+            .filter(move |(name, _)| *name == long)
+            .filter_map(|(_, value)| value.as_deref())
+    }
+
+    /// Parses the value of the last occurrence of `--long`, if it was passed.
+    pub fn parse_value<T>(&self, long: &str) -> Result<Option<T>, CliError>
+    where
+        T: std::str::FromStr,
+        T::Err: std::fmt::Display,
+    {
+        self.value(long)
+            .map(|value| parse_arg(format!("`--{long}`"), value))
+            .transpose()
+    }
+
+    /// Parses the value of every occurrence of `--long`, in order.
+    pub fn parse_values<T>(&self, long: &str) -> Result<Vec<T>, CliError>
+    where
+        T: std::str::FromStr,
+        T::Err: std::fmt::Display,
+    {
+        self.values(long)
+            .map(|value| parse_arg(format!("`--{long}`"), value))
+            .collect()
+    }
+
+    /// Fails if both `--first` and `--second` were passed.
+    pub fn check_conflict(
+        &self,
+        first: &'static str,
+        second: &'static str,
+    ) -> Result<(), CliError> {
+        if self.is_present(first) && self.is_present(second) {
+            return Err(CliError::Conflict(first, second));
+        }
+
+        Ok(())
+    }
+
+    /// Fails if `flag` is given without `required`.
+    pub fn check_requires(
+        &self,
+        flag: &'static str,
+        required: &'static str,
+    ) -> Result<(), CliError> {
+        if self.is_present(flag) && !self.is_present(required) {
+            return Err(CliError::Requires(flag, required));
+        }
+
+        Ok(())
+    }
+
+    pub fn positional(&self, index: usize) -> Option<&str> {
+        self.positionals.get(index).map(String::as_str)
+    }
+
+    /// Parses the positional argument at `index`, if it was passed.
+    pub fn parse_positional<T>(&self, index: usize) -> Result<Option<T>, CliError>
+    where
+        T: std::str::FromStr,
+        T::Err: std::fmt::Display,
+    {
+        let name = self
+            .command
+            .positionals
+            .get(index)
+            .map_or("", |spec| spec.name);
+        self.positional(index)
+            .map(|value| parse_arg(format!("<{name}>"), value))
+            .transpose()
+    }
+}
+
+fn parse_arg<T>(name: String, value: &str) -> Result<T, CliError>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    value.parse().map_err(|err: T::Err| CliError::Unparsable {
+        name,
+        value: value.to_owned(),
+        reason: err.to_string(),
+    })
+}
+
+fn check_value(name: String, kind: ValueKind, value: &str) -> Result<(), CliError> {
+    match kind {
+        ValueKind::OneOf(expected) if !expected.contains(&value) => Err(CliError::InvalidValue {
+            name,
+            value: value.to_owned(),
+            expected,
+        }),
+        _ => Ok(()),
+    }
+}
+
+fn find_flag(command: &'static CommandSpec, long: &str) -> Option<&'static FlagSpec> {
+    GLOBAL_FLAGS
+        .iter()
+        .chain(command.flags)
+        .find(|flag| flag.long == long)
+}
+
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Matches, CliError> {
+    let mut args = args.into_iter().peekable();
+
+    let command = match args.peek() {
+        Some(arg) => COMMANDS.iter().find(|command| command.name == arg),
+        None => None,
+    };
+
+    let command = match command {
+        Some(command) => {
+            args.next();
+            command
+        }
+        None => &COMMANDS[0],
+    };
+
+    let mut matches = Matches {
+        command,
+        flags: Vec::new(),
+        positionals: Vec::new(),
+    };
+
+    while let Some(arg) = args.next() {
+        let Some(flag) = arg.strip_prefix("--") else {
+            let Some(spec) = command.positionals.get(matches.positionals.len()) else {
+                return Err(CliError::UnexpectedPositional(arg));
+            };
+
+            check_value(format!("<{}>", spec.name), spec.kind, &arg)?;
+            matches.positionals.push(arg);
+            continue;
+        };
+
+        let (long, inline_value) = match flag.split_once('=') {
+            Some((long, value)) => (long, Some(value.to_owned())),
+            None => (flag, None),
+        };
+
+        let Some(spec) = find_flag(command, long) else {
+            return Err(CliError::UnknownFlag(long.to_owned()));
+        };
+
+        let value = match (spec.value, inline_value) {
+            (None, None) => None,
+            (None, Some(_)) => return Err(CliError::UnexpectedValue(spec.long)),
+            (Some((_, kind)), value) => {
+                let Some(value) = value.or_else(|| args.next()) else {
+                    return Err(CliError::MissingValue(spec.long));
+                };
+
+                check_value(format!("`--{}`", spec.long), kind, &value)?;
+                Some(value)
+            }
+        };
+
+        matches.flags.push((spec.long, value));
+    }
+
+    // `--help` and `--cli-schema` short-circuit, so don't insist on the command's arguments.
+    let short_circuit = GLOBAL_FLAGS
+        .iter()
+        .any(|flag| matches.is_present(flag.long));
+    if let Some(missing) = command.positionals.get(matches.positionals.len())
+        && !short_circuit
+    {
+        return Err(CliError::MissingPositional(missing.name));
+    }
+
+    Ok(matches)
+}
+
+fn flag_usage(flag: &FlagSpec) -> String {
+    match flag.value {
+        Some((name, _)) => format!("--{} <{name}>", flag.long),
+        None => format!("--{}", flag.long),
+    }
+}
+
+/// Renders `--help` output for `command`.
+pub fn help(command: &CommandSpec) -> String {
+    let mut out = format!("Usage: {BINARY_NAME} {}", command.name);
+    if !command.flags.is_empty() {
+        out.push_str(" [FLAGS]");
+    }
+    for positional in command.positionals {
+        write!(out, " <{}>", positional.name).unwrap();
+    }
+    writeln!(out, "\n\n{}", command.about).unwrap();
+
+    let flag_list = |out: &mut String, title: &str, flags: &[FlagSpec]| {
+        if !flags.is_empty() {
+            writeln!(out, "\n{title}:").unwrap();
+        }
+        for flag in flags {
+            writeln!(out, "  {:<28} {}", flag_usage(flag), flag.about).unwrap();
+        }
+    };
+
+    if !command.positionals.is_empty() {
+        writeln!(out, "\nArguments:").unwrap();
+    }
+    for positional in command.positionals {
+        let name = format!("<{}>", positional.name);
+        writeln!(out, "  {name:<28} {}", positional.about).unwrap();
+    }
+
+    flag_list(&mut out, "Flags", command.flags);
+    flag_list(&mut out, "Global flags", GLOBAL_FLAGS);
+
+    writeln!(out, "\nCommands:").unwrap();
+    for command in COMMANDS {
+        writeln!(out, "  {:<28} {}", command.name, command.about).unwrap();
+    }
+
+    out
+}
+
+fn flag_to_json(flag: &FlagSpec) -> Json {
+    Json::Object(vec![
+        ("name", format!("--{}", flag.long).into()),
+        ("about", flag.about.into()),
+        (
+            "value",
+            flag.value.map_or(Json::Null, |(name, kind)| {
+                Json::Object(vec![("name", name.into()), ("type", kind.to_json())])
+            }),
+        ),
+    ])
+}
+
+/// Renders the JSON description of the command line printed by `--cli-schema`.
+pub fn schema() -> Json {
+    let commands = COMMANDS.iter().map(|command| {
+        let positionals = command.positionals.iter().map(|positional| {
+            Json::Object(vec![
+                ("name", positional.name.into()),
+                ("about", positional.about.into()),
+                ("type", positional.kind.to_json()),
+            ])
+        });
+
+        Json::Object(vec![
+            ("name", command.name.into()),
+            ("about", command.about.into()),
+            (
+                "flags",
+                Json::Array(command.flags.iter().map(flag_to_json).collect()),
+            ),
+            ("positionals", Json::Array(positionals.collect())),
+        ])
+    });
+
+    let exit_codes = ExitStatus::ALL.into_iter().map(|status| {
+        Json::Object(vec![
+            ("code", Json::UInt((status as u8).into())),
+            ("name", status.name().into()),
+            ("about", status.about().into()),
+        ])
+    });
+
+    Json::Object(vec![
+        ("name", BINARY_NAME.into()),
+        ("version", env!("CARGO_PKG_VERSION").into()),
+        ("default_command", COMMANDS[0].name.into()),
+        (
+            "global_flags",
+            Json::Array(GLOBAL_FLAGS.iter().map(flag_to_json).collect()),
+        ),
+        ("commands", Json::Array(commands.collect())),
+        ("exit_codes", Json::Array(exit_codes.collect())),
+    ])
+}
+
+fn command_words(command: &CommandSpec) -> Vec<String> {
+    let flags = GLOBAL_FLAGS.iter().chain(command.flags);
+    flags.map(|flag| format!("--{}", flag.long)).collect()
+}
+
+fn bash_completions() -> String {
+    let mut out = String::from(
+        "_gpu_scratch() {\n    local cur=\"${COMP_WORDS[COMP_CWORD]}\" prev=\"${COMP_WORDS[COMP_CWORD-1]}\"\n",
+    );
+
+    let names: Vec<_> = COMMANDS.iter().map(|command| command.name).collect();
+    writeln!(out, "    local command=\"{}\" word", COMMANDS[0].name).unwrap();
+    writeln!(
+        out,
+        "    for word in \"${{COMP_WORDS[@]:1:COMP_CWORD-1}}\"; do"
+    )
+    .unwrap();
+    writeln!(
+        out,
+        "        case \"$word\" in {}) command=\"$word\"; break;; esac",
+        names.join("|")
+    )
+    .unwrap();
+    out.push_str("    done\n\n    case \"$prev\" in\n");
+
+    let flags = GLOBAL_FLAGS
+        .iter()
+        .chain(COMMANDS.iter().flat_map(|c| c.flags));
+    for flag in flags {
+        match flag.value {
+            Some((_, ValueKind::OneOf(values))) => writeln!(
+                out,
+                "        --{}) COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")); return;;",
+                flag.long,
+                values.join(" ")
+            ),
+            Some((_, ValueKind::Path)) => writeln!(
+                out,
+                "        --{}) COMPREPLY=($(compgen -f -- \"$cur\")); return;;",
+                flag.long
+            ),
+            Some((_, ValueKind::Text)) => writeln!(out, "        --{}) return;;", flag.long),
+            None => Ok(()),
+        }
+        .unwrap();
+    }
+
+    out.push_str("    esac\n\n    local words\n    case \"$command\" in\n");
+    for command in COMMANDS {
+        let mut words = command_words(command);
+        if let Some(ValueKind::OneOf(values)) = command.positionals.first().map(|p| p.kind) {
+            words.extend(values.iter().map(|&value| value.to_owned()));
+        }
+        if command.name == COMMANDS[0].name {
+            words.extend(names.iter().map(|&name| name.to_owned()));
+        }
+
+        writeln!(
+            out,
+            "        {}) words=\"{}\";;",
+            command.name,
+            words.join(" ")
+        )
+        .unwrap();
+    }
+
+    out.push_str("    esac\n\n    COMPREPLY=($(compgen -W \"$words\" -- \"$cur\"))\n}\n\n");
+    writeln!(out, "complete -F _gpu_scratch {BINARY_NAME}").unwrap();
+    out
+}
+
+fn zsh_escape(text: &str) -> String {
+    text.replace('\'', "'\\''")
+        .replace('[', "\\[")
+        .replace(']', "\\]")
+        .replace(':', "\\:")
+}
+
+fn zsh_flag(flag: &FlagSpec) -> String {
+    let about = zsh_escape(flag.about);
+    match flag.value {
+        None => format!("'--{}[{about}]'", flag.long),
+        Some((name, kind)) => {
+            let action = match kind {
+                ValueKind::Text => String::from(" "),
+                ValueKind::Path => String::from("_files"),
+                ValueKind::OneOf(values) => format!("({})", values.join(" ")),
+            };
+            format!("'--{}[{about}]:{name}:{action}'", flag.long)
+        }
+    }
+}
+
+fn zsh_completions() -> String {
+    let mut out = format!("#compdef {BINARY_NAME}\n\n_gpu_scratch() {{\n    local -a commands\n");
+
+    out.push_str("    commands=(\n");
+    for command in COMMANDS {
+        let about = zsh_escape(command.about);
+        writeln!(out, "        '{}:{about}'", command.name).unwrap();
+    }
+    out.push_str("    )\n\n    local -a global_flags\n    global_flags=(\n");
+    for flag in GLOBAL_FLAGS {
+        writeln!(out, "        {}", zsh_flag(flag)).unwrap();
+    }
+
+    out.push_str("    )\n\n    _arguments -C $global_flags '1: :->command' '*:: :->args'\n");
+    out.push_str("    case $state in\n        command) _describe 'command' commands ;;\n");
+    out.push_str("        args)\n            case $words[1] in\n");
+    for command in COMMANDS {
+        let mut specs: Vec<_> = command.flags.iter().map(zsh_flag).collect();
+        for (i, positional) in command.positionals.iter().enumerate() {
+            let action = match positional.kind {
+                ValueKind::Text => String::from(" "),
+                ValueKind::Path => String::from("_files"),
+                ValueKind::OneOf(values) => format!("({})", values.join(" ")),
+            };
+            specs.push(format!("'{}:{}:{action}'", i + 1, positional.name));
+        }
+
+        writeln!(
+            out,
+            "                {}) _arguments $global_flags {} ;;",
+            command.name,
+            specs.join(" ")
+        )
+        .unwrap();
+    }
+    out.push_str("            esac\n            ;;\n    esac\n}\n\n_gpu_scratch \"$@\"\n");
+    out
+}
+
+fn fish_escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\'', "\\'")
+}
+
+fn fish_flag(out: &mut String, condition: &str, flag: &FlagSpec) {
+    write!(out, "complete -c {BINARY_NAME}{condition} -l {}", flag.long).unwrap();
+    match flag.value {
+        None => {}
+        Some((_, ValueKind::Text)) => out.push_str(" -r"),
+        Some((_, ValueKind::Path)) => out.push_str(" -r -F"),
+        Some((_, ValueKind::OneOf(values))) => {
+            write!(out, " -r -f -a '{}'", values.join(" ")).unwrap();
+        }
+    }
+    writeln!(out, " -d '{}'", fish_escape(flag.about)).unwrap();
+}
+
+fn fish_completions() -> String {
+    let mut out = format!("complete -c {BINARY_NAME} -f\n");
+    for flag in GLOBAL_FLAGS {
+        fish_flag(&mut out, "", flag);
+    }
+
+    for command in COMMANDS {
+        writeln!(
+            out,
+            "complete -c {BINARY_NAME} -n __fish_use_subcommand -a {} -d '{}'",
+            command.name,
+            fish_escape(command.about)
+        )
+        .unwrap();
+
+        let condition = format!(" -n '__fish_seen_subcommand_from {}'", command.name);
+        for flag in command.flags {
+            fish_flag(&mut out, &condition, flag);
+        }
+        for positional in command.positionals {
+            match positional.kind {
+                ValueKind::Text => {}
+                ValueKind::Path => {
+                    writeln!(out, "complete -c {BINARY_NAME}{condition} -F").unwrap();
+                }
+                ValueKind::OneOf(values) => {
+                    let values = values.join(" ");
+                    writeln!(out, "complete -c {BINARY_NAME}{condition} -a '{values}'").unwrap();
+                }
+            }
+        }
+    }
+
+    out
+}
+
+fn powershell_completions() -> String {
+    let mut out = format!(
+        "Register-ArgumentCompleter -Native -CommandName '{BINARY_NAME}' -ScriptBlock {{\n    param($wordToComplete, $commandAst, $cursorPosition)\n\n    $commands = @{{\n"
+    );
+
+    for command in COMMANDS {
+        let mut words = command_words(command);
+        if let Some(ValueKind::OneOf(values)) = command.positionals.first().map(|p| p.kind) {
+            words.extend(values.iter().map(|&value| value.to_owned()));
+        }
+
+        let words: Vec<_> = words.iter().map(|word| format!("'{word}'")).collect();
+        writeln!(out, "        '{}' = @({})", command.name, words.join(", ")).unwrap();
+    }
+
+    writeln!(out, "    }}\n").unwrap();
+    out.push_str(
+        "    $words = $commandAst.CommandElements | Select-Object -Skip 1 | ForEach-Object { $_.ToString() }\n",
+    );
+    out.push_str(
+        "    $command = $words | Where-Object { $commands.ContainsKey($_) } | Select-Object -First 1\n",
+    );
+    writeln!(
+        out,
+        "    if ($command) {{\n        $candidates = $commands[$command]\n    }} else {{\n        $candidates = @($commands.Keys) + $commands['{}']\n    }}\n",
+        COMMANDS[0].name
+    )
+    .unwrap();
+    out.push_str(
+        "    $candidates | Where-Object { $_ -like \"$wordToComplete*\" } | ForEach-Object {\n",
+    );
+    out.push_str(
+        "        [System.Management.Automation.CompletionResult]::new($_, $_, 'ParameterValue', $_)\n",
+    );
+    out.push_str("    }\n}\n");
+    out
+}
+
+/// Renders the completion script for `shell`, which must be one of [`SHELLS`].
+pub fn completions(shell: &str) -> String {
+    match shell {
+        "bash" => bash_completions(),
+        "zsh" => zsh_completions(),
+        "fish" => fish_completions(),
+        "powershell" => powershell_completions(),
+        _ => unreachable!("shell should have been validated by the parser"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{COMMANDS, CliError, GLOBAL_FLAGS, Matches, parse, schema};
+    use crate::json::Json;
+
+    fn parse_args(args: &[&str]) -> Result<Matches, CliError> {
+        parse(args.iter().map(|&arg| arg.to_owned()))
+    }
+
+    #[test]
+    fn flag_values() {
+        let separate = parse_args(&["run", "--dispatch", "4x4"]).unwrap();
+        let inline = parse_args(&["run", "--dispatch=4x4"]).unwrap();
+        assert_eq!(separate.value("dispatch"), Some("4x4"));
+        assert_eq!(inline.value("dispatch"), Some("4x4"));
+
+        // Only the first `=` splits, so values may contain their own.
+        let matches = parse_args(&["run", "--const=TILE=16"]).unwrap();
+        assert_eq!(matches.value("const"), Some("TILE=16"));
+
+        assert!(matches!(
+            parse_args(&["run", "--dispatch"]),
+            Err(CliError::MissingValue("dispatch"))
+        ));
+        assert!(matches!(
+            parse_args(&["run", "--dry-run=yes"]),
+            Err(CliError::UnexpectedValue("dry-run"))
+        ));
+        assert!(matches!(
+            parse_args(&["run", "--dispatch", "x"])
+                .unwrap()
+                .parse_value::<gpu_scratch::plan::DispatchSize>("dispatch"),
+            Err(CliError::Unparsable { .. })
+        ));
+    }
+
+    #[test]
+    fn default_command() {
+        let matches = parse_args(&["--dry-run"]).unwrap();
+        assert_eq!(matches.command.name, COMMANDS[0].name);
+        assert!(matches.is_present("dry-run"));
+        assert!(!matches.is_present("explain"));
+
+        let matches = parse_args(&[]).unwrap();
+        assert_eq!(matches.command.name, COMMANDS[0].name);
+    }
+
+    #[test]
+    fn repeated_flags() {
+        let matches = parse_args(&[
+            "run",
+            "--post",
+            "sum(out)",
+            "--post=max(out)",
+            "--init",
+            "zero",
+            "--init",
+            "iota",
+        ])
+        .unwrap();
+        assert_eq!(
+            matches.values("post").collect::<Vec<_>>(),
+            ["sum(out)", "max(out)"]
+        );
+        // The last occurrence of a single valued flag wins.
+        assert_eq!(matches.value("init"), Some("iota"));
+        assert_eq!(matches.values("input").count(), 0);
+    }
+
+    #[test]
+    fn one_of() {
+        let matches = parse_args(&["run", "--element-type", "f32"]).unwrap();
+        assert_eq!(matches.value("element-type"), Some("f32"));
+
+        assert!(matches!(
+            parse_args(&["run", "--element-type", "f16"]),
+            Err(CliError::InvalidValue { value, .. }) if value == "f16"
+        ));
+        assert!(matches!(
+            parse_args(&["completions", "tcsh"]),
+            Err(CliError::InvalidValue { value, .. }) if value == "tcsh"
+        ));
+    }
+
+    #[test]
+    fn positionals() {
+        let matches = parse_args(&["compare", "a.wgsl", "--repeat", "3", "b.wgsl"]).unwrap();
+        assert_eq!(matches.positional(0), Some("a.wgsl"));
+        assert_eq!(matches.positional(1), Some("b.wgsl"));
+        assert_eq!(matches.parse_value::<u32>("repeat").unwrap(), Some(3));
+
+        assert!(matches!(
+            parse_args(&["compare", "a.wgsl"]),
+            Err(CliError::MissingPositional("b"))
+        ));
+        assert!(matches!(
+            parse_args(&["compare", "a.wgsl", "b.wgsl", "c.wgsl"]),
+            Err(CliError::UnexpectedPositional(arg)) if arg == "c.wgsl"
+        ));
+        assert!(matches!(
+            parse_args(&["replay"]),
+            Err(CliError::MissingPositional("id"))
+        ));
+
+        // `--help` short-circuits, so it needs no positionals.
+        assert!(parse_args(&["compare", "--help"]).is_ok());
+    }
+
+    #[test]
+    fn unknown_flags() {
+        assert!(matches!(
+            parse_args(&["run", "--no-such-flag"]),
+            Err(CliError::UnknownFlag(flag)) if flag == "no-such-flag"
+        ));
+        // Flags of other commands are unknown too.
+        assert!(matches!(
+            parse_args(&["selftest", "--dispatch", "4"]),
+            Err(CliError::UnknownFlag(flag)) if flag == "dispatch"
+        ));
+    }
+
+    fn field<'a>(object: &'a Json, key: &str) -> &'a Json {
+        let Json::Object(fields) = object else {
+            panic!("expected an object with `{key}`");
+        };
+        &fields.iter().find(|(name, _)| *name == key).unwrap().1
+    }
+
+    fn strings<'a>(array: &'a Json, key: &str) -> Vec<&'a str> {
+        let Json::Array(items) = array else {
+            panic!("expected an array");
+        };
+        (items.iter())
+            .map(|item| match field(item, key) {
+                Json::String(value) => value.as_str(),
+                _ => panic!("expected `{key}` to be a string"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn schema_matches_commands() {
+        let schema = schema();
+        let commands = field(&schema, "commands");
+        let names: Vec<_> = COMMANDS.iter().map(|command| command.name).collect();
+        assert_eq!(strings(commands, "name"), names);
+
+        let Json::Array(commands) = commands else {
+            panic!("expected an array of commands");
+        };
+        for (command, spec) in commands.iter().zip(COMMANDS) {
+            let flags: Vec<_> = (spec.flags.iter())
+                .map(|flag| format!("--{}", flag.long))
+                .collect();
+            assert_eq!(
+                strings(field(command, "flags"), "name"),
+                flags,
+                "{}",
+                spec.name
+            );
+
+            let positionals: Vec<_> = spec.positionals.iter().map(|p| p.name).collect();
+            assert_eq!(
+                strings(field(command, "positionals"), "name"),
+                positionals,
+                "{}",
+                spec.name
+            );
+
+            // Every flag is named once, so `--help` and completions list each once.
+            for (index, flag) in spec.flags.iter().enumerate() {
+                assert!(
+                    !(spec.flags[..index].iter().chain(GLOBAL_FLAGS)).any(|f| f.long == flag.long),
+                    "`--{}` is declared twice for {}",
+                    flag.long,
+                    spec.name
+                );
+            }
+        }
+
+        let global: Vec<_> = (GLOBAL_FLAGS.iter())
+            .map(|flag| format!("--{}", flag.long))
+            .collect();
+        assert_eq!(strings(field(&schema, "global_flags"), "name"), global);
+    }
+}
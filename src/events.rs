@@ -0,0 +1,93 @@
+//! `--events ndjson`, which streams each step of a run as one JSON object per line.
+
+use std::{
+    fs::File,
+    io::Write,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use gpu_scratch::{Event, GpuContext};
+
+use crate::{CheckError, cli, json::Json};
+
+/// The formats `--events` accepts.
+pub const FORMATS: &[&str] = &["ndjson"];
+
+fn nanos(duration: Duration) -> Json {
+    Json::UInt(duration.as_nanos().try_into().unwrap_or(u64::MAX))
+}
+
+/// Describes `event` as a JSON object, with `since_start` as the time since the run started.
+fn to_json(event: &Event, since_start: Duration) -> Json {
+    let (name, mut fields) = match *event {
+        Event::CompileStarted => ("compile-started", vec![]),
+        Event::CompileFinished { elapsed } => {
+            ("compile-finished", vec![("elapsed_ns", nanos(elapsed))])
+        }
+        Event::Submitted { index, count } => (
+            "submitted",
+            vec![
+                ("submission", Json::UInt(index as u64)),
+                ("submissions", Json::UInt(count as u64)),
+            ],
+        ),
+        Event::PassCompleted { index, elapsed } => (
+            "pass-completed",
+            vec![
+                ("submission", Json::UInt(index as u64)),
+                ("elapsed_ns", nanos(elapsed)),
+            ],
+        ),
+        Event::RunCompleted {
+            elapsed,
+            gpu_elapsed,
+        } => (
+            "run-completed",
+            vec![
+                ("elapsed_ns", nanos(elapsed)),
+                ("gpu_elapsed_ns", gpu_elapsed.map_or(Json::Null, nanos)),
+            ],
+        ),
+        Event::ReadbackDone { bytes, elapsed } => (
+            "readback-done",
+            vec![
+                ("bytes", Json::UInt(bytes as u64)),
+                ("elapsed_ns", nanos(elapsed)),
+            ],
+        ),
+    };
+
+    fields.insert(0, ("event", name.into()));
+    fields.insert(1, ("time_ns", nanos(since_start)));
+    Json::Object(fields)
+}
+
+/// Streams `gpu`'s events to `--events-to`, or stderr, if `--events` is given.
+///
+/// Each line is written and flushed as the event happens, so tools can follow a run live.
+pub fn stream(matches: &cli::Matches, gpu: &mut GpuContext) -> Result<(), CheckError> {
+    if matches.value("events").is_none() {
+        return Ok(());
+    }
+
+    let sink: Box<dyn Write + Send> = match matches.value("events-to") {
+        Some(path) => Box::new(File::create(path).map_err(|source| CheckError::WriteFile {
+            path: path.to_owned(),
+            source,
+        })?),
+        None => Box::new(std::io::stderr()),
+    };
+
+    let sink = Mutex::new(sink);
+    let start = Instant::now();
+    gpu.observe(move |event| {
+        let line = to_json(event, start.elapsed());
+        let mut sink = sink.lock().unwrap_or_else(|err| err.into_inner());
+        if let Err(err) = writeln!(sink, "{line}").and_then(|()| sink.flush()) {
+            log::warn!("Unable to write event: {err}");
+        }
+    });
+
+    Ok(())
+}
@@ -0,0 +1,137 @@
+//! Process exit codes, so that scripts can branch on the class of a failure.
+//!
+//! The codes are part of the command line interface: they are listed in the README and in
+//! `--cli-schema`, and must not be renumbered.
+
+use std::{error::Error, process::ExitCode};
+
+use gpu_scratch::{
+    InitializeError, RunError, buffer::BufferSpecError, plan::PlanError, reflect::ReflectError,
+};
+
+use crate::{CheckError, cli::CliError, journal::JournalError, post::PostError};
+
+#[derive(Clone, Copy)]
+pub enum ExitStatus {
+    Success = 0,
+    /// Any failure not covered by a more specific status.
+    Failure = 1,
+    Usage = 2,
+    NoAdapter = 3,
+    ShaderError = 4,
+    ValidationError = 5,
+    Mismatch = 6,
+    Timeout = 7,
+    OutOfMemory = 8,
+    OverBudget = 9,
+}
+
+impl ExitStatus {
+    pub const ALL: [Self; 10] = [
+        Self::Success,
+        Self::Failure,
+        Self::Usage,
+        Self::NoAdapter,
+        Self::ShaderError,
+        Self::ValidationError,
+        Self::Mismatch,
+        Self::Timeout,
+        Self::OutOfMemory,
+        Self::OverBudget,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Success => "success",
+            Self::Failure => "failure",
+            Self::Usage => "usage",
+            Self::NoAdapter => "no-adapter",
+            Self::ShaderError => "shader-error",
+            Self::ValidationError => "validation-error",
+            Self::Mismatch => "mismatch",
+            Self::Timeout => "timeout",
+            Self::OutOfMemory => "out-of-memory",
+            Self::OverBudget => "over-budget",
+        }
+    }
+
+    pub fn about(self) -> &'static str {
+        match self {
+            Self::Success => "The run completed and every check passed",
+            Self::Failure => "An error not covered by any other exit code",
+            Self::Usage => "The command line arguments were invalid",
+            Self::NoAdapter => "No suitable GPU adapter or device could be found",
+            Self::ShaderError => "The shader failed to parse or validate",
+            Self::ValidationError => "wgpu rejected an operation as invalid",
+            Self::Mismatch => "The output did not match what was expected",
+            Self::Timeout => "Waiting for the GPU timed out",
+            Self::OutOfMemory => "The GPU ran out of memory",
+            Self::OverBudget => "The plan exceeds a device limit or the configured budget",
+        }
+    }
+
+    /// Picks the exit status for an error returned from `real_main`.
+    pub fn classify(err: &(dyn Error + 'static)) -> Self {
+        if err.is::<CliError>() || err.is::<PostError>() {
+            Self::Usage
+        } else if err.is::<InitializeError>() {
+            Self::NoAdapter
+        } else if let Some(err) = err.downcast_ref::<PlanError>() {
+            match err {
+                PlanError::UnsizedBinding { .. }
+                | PlanError::UnknownEntryPoint { .. }
+                | PlanError::AmbiguousEntryPoint { .. }
+                | PlanError::UnknownConstant { .. }
+                | PlanError::PushConstantSize { .. }
+                | PlanError::NoSliceOffset => Self::Usage,
+                // Sizes come from the command line, while roles are only wrong from a bug.
+                PlanError::Buffer(BufferSpecError::Unaligned { .. }) => Self::Usage,
+                PlanError::Buffer(
+                    BufferSpecError::NoRoles { .. } | BufferSpecError::MappedWith { .. },
+                ) => Self::Failure,
+                PlanError::UnsupportedBinding { .. } => Self::ShaderError,
+                PlanError::ExceedsLimit { .. }
+                | PlanError::EntryPointExceedsLimit { .. }
+                | PlanError::ChunkedOutputExceedsLimit { .. }
+                | PlanError::OverBudget { .. } => Self::OverBudget,
+            }
+        } else if let Some(JournalError::NotFound(_)) = err.downcast_ref() {
+            Self::Usage
+        } else if err.is::<ReflectError>() {
+            Self::ShaderError
+        } else if let Some(err) = err.downcast_ref::<CheckError>() {
+            match err {
+                CheckError::HashMismatch { .. }
+                | CheckError::OutputsDiffer { .. }
+                | CheckError::Diverged { .. } => Self::Mismatch,
+                CheckError::ReadFile { .. }
+                | CheckError::WriteFile { .. }
+                | CheckError::Connect { .. }
+                | CheckError::NoPowerSensor
+                | CheckError::SelfTestFailed { .. } => Self::Failure,
+            }
+        } else if let Some(err) = err.downcast_ref::<RunError>() {
+            match err {
+                RunError::Compile(_) => Self::ShaderError,
+                RunError::Validation(_) | RunError::Internal(_) => Self::ValidationError,
+                RunError::OutOfMemory => Self::OutOfMemory,
+                RunError::Poll(wgpu::PollError::Timeout) => Self::Timeout,
+                RunError::InputCount { .. }
+                | RunError::InputSize { .. }
+                | RunError::PushConstantSize { .. } => Self::Usage,
+                RunError::Map(_)
+                | RunError::ReadBackSize { .. }
+                | RunError::NoPreviousStage
+                | RunError::Sink(_) => Self::Failure,
+            }
+        } else {
+            Self::Failure
+        }
+    }
+}
+
+impl From<ExitStatus> for ExitCode {
+    fn from(status: ExitStatus) -> Self {
+        Self::from(status as u8)
+    }
+}
@@ -0,0 +1,95 @@
+//! Hints appended to common errors, suggesting what to change to fix them.
+//!
+//! wgpu only reports errors as text, so most of these match on the messages of the errors a
+//! shader in this tool is likely to run into.
+
+use std::error::Error;
+
+use gpu_scratch::{InitializeError, RunError, plan::PlanError};
+
+/// Returns the part of `text` between `start` and the next `end`.
+fn between<'a>(text: &'a str, start: &str, end: &str) -> Option<&'a str> {
+    let (_, rest) = text.split_once(start)?;
+    rest.split_once(end).map(|(inner, _)| inner)
+}
+
+fn missing_usage(description: &str) -> Option<String> {
+    let label = between(description, "of Buffer with '", "' label do not contain")?;
+    let expected = between(description, "required usage flags BufferUsages(", ")")?;
+    Some(format!("add {expected} to the usages of buffer '{label}'"))
+}
+
+fn unbound_global(description: &str) -> Option<String> {
+    let binding = between(
+        description,
+        "Shader global ResourceBinding {",
+        "} is not available",
+    )?;
+    let group = between(binding, "group: ", ",")?;
+    let binding = binding.split_once("binding: ")?.1.trim();
+    Some(format!(
+        "the shader declares @group({group}) @binding({binding}), but nothing is bound there; \
+         pass a file for it with `--input`, as inputs are bound in order after the output, or \
+         values for a uniform buffer with `--param`"
+    ))
+}
+
+fn undersized_binding(description: &str) -> Option<String> {
+    let bound = between(description, "Buffer is bound with size ", " ")?;
+    let expected = between(description, "where the shader expects ", " ")?;
+    let index = description.split_once("compact index ")?.1.trim();
+    let index = index.split_whitespace().next()?;
+    let fix = if index == "0" {
+        "shrink the array the shader declares for its output"
+    } else {
+        "pass a larger `--input` file, or shrink the array the shader declares"
+    };
+
+    Some(format!(
+        "binding {index} is {bound} bytes but the shader needs at least {expected}; {fix}"
+    ))
+}
+
+fn access_mismatch(description: &str) -> Option<String> {
+    between(description, "Storage class ", "doesn't match the shader")?;
+    Some(String::from(
+        "the shader and the bind group layout disagree on how a storage buffer is accessed; \
+         declare inputs as `var<storage, read>` unless the shader writes to them",
+    ))
+}
+
+fn too_many_bindings(description: &str) -> Option<String> {
+    let kind = between(description, "Too many bindings of type ", " in")?;
+    let limit = between(description, "limit is ", ",")?;
+    Some(format!(
+        "this device allows at most {limit} bindings of type {kind} per shader stage; \
+         pass fewer `--input` files"
+    ))
+}
+
+/// Suggests a fix for `err`, if it is an error this recognises.
+pub fn for_error(err: &(dyn Error + 'static)) -> Option<String> {
+    if let Some(PlanError::AmbiguousEntryPoint { .. }) = err.downcast_ref() {
+        return Some(String::from("pass `--entry-point <name>` to pick one"));
+    }
+
+    if let Some(InitializeError::NoMatchingAdapter(..)) = err.downcast_ref() {
+        return Some(String::from(
+            "run `list-adapters` to see the adapters `--adapter` can pick from",
+        ));
+    }
+
+    let Some(RunError::Validation(description)) = err.downcast_ref() else {
+        return None;
+    };
+
+    let classifiers = [
+        missing_usage,
+        unbound_global,
+        undersized_binding,
+        access_mismatch,
+        too_many_bindings,
+    ];
+
+    (classifiers.iter()).find_map(|classify| classify(description))
+}
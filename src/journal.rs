@@ -0,0 +1,261 @@
+//! An append-only journal of every run, bench, compare, and sweep, so that past runs can be
+//! listed and replayed.
+//!
+//! Each line records one run as tab separated fields: the Unix timestamp, the SHA-256 of the
+//! shader source, the exit status name, the SHA-256 of the output (or `-`), then the command
+//! line arguments. A run's id is its line number, which never changes as lines are only added.
+
+use std::{
+    fs,
+    io::{self, Write as _},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum JournalError {
+    #[error("Unable to read the run journal: {0}")]
+    Io(#[from] io::Error),
+    #[error("No run with id {0} in the journal, see `gpu-scratch journal`")]
+    NotFound(usize),
+    #[error("Run {id} has a malformed journal entry")]
+    Malformed { id: usize },
+    #[error("Run {id} used a shader with sha256 {recorded}, but this build has {current}")]
+    ShaderChanged {
+        id: usize,
+        recorded: String,
+        current: String,
+    },
+}
+
+pub struct Entry {
+    pub id: usize,
+    pub timestamp: u64,
+    pub shader_hash: String,
+    pub status: String,
+    pub output_hash: Option<String>,
+    pub args: Vec<String>,
+}
+
+impl Entry {
+    fn parse(id: usize, line: &str) -> Result<Self, JournalError> {
+        let malformed = || JournalError::Malformed { id };
+
+        let mut fields = line.split('\t').map(unescape);
+        let mut next = || fields.next().ok_or_else(malformed);
+
+        let timestamp = next()?.parse().map_err(|_| malformed())?;
+        let shader_hash = next()?;
+        let status = next()?;
+        let output_hash = Some(next()?).filter(|hash| hash != "-");
+
+        Ok(Self {
+            id,
+            timestamp,
+            shader_hash,
+            status,
+            output_hash,
+            args: fields.collect(),
+        })
+    }
+
+    fn to_line(&self) -> String {
+        let fields = [
+            self.timestamp.to_string(),
+            self.shader_hash.clone(),
+            self.status.clone(),
+            self.output_hash
+                .clone()
+                .unwrap_or_else(|| String::from("-")),
+        ];
+
+        let fields: Vec<_> = fields.iter().chain(&self.args).map(|f| escape(f)).collect();
+        fields.join("\t")
+    }
+
+    /// Fails unless this run used the shader with SHA-256 `current`.
+    pub fn check_shader(&self, current: &str) -> Result<(), JournalError> {
+        if self.shader_hash != current {
+            return Err(JournalError::ShaderChanged {
+                id: self.id,
+                recorded: self.shader_hash.clone(),
+                current: current.to_owned(),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+fn escape(field: &str) -> String {
+    field
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+fn unescape(field: &str) -> String {
+    let mut out = String::with_capacity(field.len());
+    let mut chars = field.chars();
+    while let Some(char) = chars.next() {
+        match (char, chars.clone().next()) {
+            ('\\', Some(escaped @ ('\\' | 't' | 'n'))) => {
+                chars.next();
+                out.push(match escaped {
+                    't' => '\t',
+                    'n' => '\n',
+                    _ => '\\',
+                });
+            }
+            (char, _) => out.push(char),
+        }
+    }
+
+    out
+}
+
+pub struct Journal {
+    path: PathBuf,
+}
+
+impl Journal {
+    /// Opens the journal at `$GPU_SCRATCH_JOURNAL`, falling back to
+    /// `$XDG_STATE_HOME/gpu-scratch/journal` then `$HOME/.local/state/gpu-scratch/journal`.
+    pub fn open() -> io::Result<Self> {
+        let path = if let Some(path) = std::env::var_os("GPU_SCRATCH_JOURNAL") {
+            PathBuf::from(path)
+        } else if let Some(dir) = std::env::var_os("XDG_STATE_HOME") {
+            Path::new(&dir).join("gpu-scratch").join("journal")
+        } else if let Some(home) = std::env::var_os("HOME") {
+            Path::new(&home).join(".local/state/gpu-scratch/journal")
+        } else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "no journal location could be determined",
+            ));
+        };
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        Ok(Self { path })
+    }
+
+    /// Reads the journal, which is empty if no run has been recorded yet.
+    fn read(&self) -> io::Result<String> {
+        match fs::read_to_string(&self.path) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(String::new()),
+            result => result,
+        }
+    }
+
+    /// Reads every entry, oldest first, skipping any malformed lines with a warning.
+    pub fn entries(&self) -> Result<Vec<Entry>, JournalError> {
+        let contents = self.read()?;
+        let entries = (contents.lines().enumerate())
+            .filter_map(|(index, line)| {
+                Entry::parse(index + 1, line)
+                    .inspect_err(|err| log::warn!("Skipping journal line: {err}"))
+                    .ok()
+            })
+            .collect();
+
+        Ok(entries)
+    }
+
+    /// Reads the entry with `id`, only failing as malformed if that entry's line is.
+    pub fn get(&self, id: usize) -> Result<Entry, JournalError> {
+        let contents = self.read()?;
+        let line = (id.checked_sub(1))
+            .and_then(|index| contents.lines().nth(index))
+            .ok_or(JournalError::NotFound(id))?;
+
+        Entry::parse(id, line)
+    }
+
+    /// Appends a run to the journal.
+    pub fn record(
+        &self,
+        shader_hash: String,
+        status: &str,
+        output_hash: Option<String>,
+        args: Vec<String>,
+    ) -> io::Result<()> {
+        let timestamp = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_secs());
+
+        let entry = Entry {
+            id: 0,
+            timestamp,
+            shader_hash,
+            status: status.to_owned(),
+            output_hash,
+            args,
+        };
+
+        // A single `write` to a file opened for appending keeps concurrent runs from interleaving.
+        let mut file = fs::File::options()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        file.write_all(format!("{}\n", entry.to_line()).as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Entry, JournalError, escape, unescape};
+
+    #[test]
+    fn escape_round_trip() {
+        for field in [
+            "plain",
+            "tab\there",
+            "new\nline",
+            r"back\slash",
+            r"\t literal",
+            "\\\t\n",
+        ] {
+            let escaped = escape(field);
+            assert!(!escaped.contains(['\t', '\n']), "{escaped:?}");
+            assert_eq!(unescape(&escaped), field);
+        }
+    }
+
+    #[test]
+    fn unescape_leaves_unknown_escapes() {
+        assert_eq!(unescape(r"a\qb\"), r"a\qb\");
+    }
+
+    #[test]
+    fn entry_round_trip() {
+        let entry = Entry {
+            id: 0,
+            timestamp: 1_700_000_000,
+            shader_hash: String::from("abc"),
+            status: String::from("success"),
+            output_hash: None,
+            args: vec![String::from("run"), String::from("--param\tu32:1")],
+        };
+
+        let parsed = Entry::parse(7, &entry.to_line()).unwrap();
+        assert_eq!(parsed.id, 7);
+        assert_eq!(parsed.timestamp, entry.timestamp);
+        assert_eq!(parsed.shader_hash, entry.shader_hash);
+        assert_eq!(parsed.status, entry.status);
+        assert_eq!(parsed.output_hash, None);
+        assert_eq!(parsed.args, entry.args);
+    }
+
+    #[test]
+    fn malformed() {
+        for line in ["", "garbage", "1\tabc\tsuccess", "soon\tabc\tsuccess\t-"] {
+            assert!(matches!(
+                Entry::parse(3, line),
+                Err(JournalError::Malformed { id: 3 })
+            ));
+        }
+    }
+}
@@ -0,0 +1,1390 @@
+//! Runs compute shaders with wgpu, describing the work up front as a [`plan::Plan`].
+//!
+//! [`GpuContext`] owns the device, and runs shaders following a plan built from their
+//! [`reflect::Reflection`]. The `gpu-scratch` binary is a command line wrapper around it.
+//!
+//! For the common case of mapping one array to another, [`compute`] does all of this in one call.
+
+use std::{
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    task::{Context, Poll, Waker},
+    time::{Duration, Instant},
+};
+
+use wgpu::{ComputePassDescriptor, util::DeviceExt as _};
+
+use crate::{
+    buffer::{BufferRole, BufferSpec},
+    plan::{Budget, DispatchSize, Plan, PlanError},
+    poll::PollStrategy,
+    pool::BufferPool,
+    reflect::{ReflectError, Reflection},
+    shader::Shader,
+    sink::OutputSink,
+};
+
+pub mod buffer;
+pub mod cache;
+pub mod hash;
+pub mod pipeline;
+pub mod plan;
+pub mod poll;
+mod pool;
+pub mod prelude;
+pub mod reflect;
+pub mod resample;
+pub mod shader;
+pub mod sink;
+pub mod texture;
+
+#[derive(Debug, thiserror::Error)]
+pub enum InitializeError {
+    #[error("Unable to find GPU adapter!")]
+    NoAdapter,
+    #[error("Unable to find GPU device!")]
+    NoDevice,
+    #[error("No GPU adapter matches {0}, of the {1} available")]
+    NoMatchingAdapter(AdapterFilter, usize),
+}
+
+/// Picks an adapter by its index in [`adapters`], or by a case-insensitive substring of its name.
+#[derive(Clone, Debug)]
+pub enum AdapterFilter {
+    Index(usize),
+    Name(String),
+}
+
+impl AdapterFilter {
+    fn matches(&self, index: usize, info: &wgpu::AdapterInfo) -> bool {
+        match self {
+            Self::Index(wanted) => index == *wanted,
+            Self::Name(name) => info.name.to_lowercase().contains(&name.to_lowercase()),
+        }
+    }
+}
+
+impl std::str::FromStr for AdapterFilter {
+    type Err = std::convert::Infallible;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Ok(value
+            .parse()
+            .map_or_else(|_| Self::Name(value.to_owned()), Self::Index))
+    }
+}
+
+impl std::fmt::Display for AdapterFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Index(index) => write!(f, "index {index}"),
+            Self::Name(name) => write!(f, "name `{name}`"),
+        }
+    }
+}
+
+/// Which adapter [`GpuContext::with_selection`] requests its device from.
+#[derive(Clone, Debug, Default)]
+pub struct AdapterSelection {
+    /// The backends to consider, instead of those from `WGPU_BACKEND` or the platform defaults.
+    pub backends: Option<wgpu::Backends>,
+    /// The adapter to use, instead of the first high performance one.
+    pub adapter: Option<AdapterFilter>,
+}
+
+impl AdapterSelection {
+    fn instance(&self) -> wgpu::Instance {
+        let mut descriptor = wgpu::InstanceDescriptor::from_env_or_default();
+        if let Some(backends) = self.backends {
+            descriptor.backends = backends;
+        }
+
+        wgpu::Instance::new(&descriptor)
+    }
+}
+
+/// Lists the adapters on `backends`, or on the default backends if `None`, in the order
+/// [`AdapterFilter::Index`] refers to them.
+pub fn adapters(backends: Option<wgpu::Backends>) -> Vec<wgpu::AdapterInfo> {
+    let selection = AdapterSelection {
+        backends,
+        adapter: None,
+    };
+
+    let adapters = selection
+        .instance()
+        .enumerate_adapters(wgpu::Backends::all());
+    adapters.iter().map(wgpu::Adapter::get_info).collect()
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RunError {
+    #[error("Unable to compile the shader: {0}")]
+    Compile(String),
+    #[error("The plan reads the output of a previous pipeline stage, but there is none")]
+    NoPreviousStage,
+    #[error("wgpu validation error: {0}")]
+    Validation(String),
+    #[error("Internal wgpu error: {0}")]
+    Internal(String),
+    #[error("Out of GPU memory")]
+    OutOfMemory,
+    #[error("Unable to wait for the GPU: {0}")]
+    Poll(#[from] wgpu::PollError),
+    #[error("Unable to map the output buffer: {0}")]
+    Map(#[from] wgpu::BufferAsyncError),
+    #[error("The plan has {expected} inputs, but {actual} were given")]
+    InputCount { expected: usize, actual: usize },
+    #[error("Input {index} is {actual} bytes, but its buffer was prepared for {expected}")]
+    InputSize {
+        index: usize,
+        expected: usize,
+        actual: usize,
+    },
+    #[error("Unable to write the output to its sink: {0}")]
+    Sink(std::io::Error),
+    #[error("The push constants are {expected} bytes, but {actual} were given")]
+    PushConstantSize { expected: usize, actual: usize },
+    #[error("Unable to read back {size} bytes as `{element}`, which is {element_size} bytes")]
+    ReadBackSize {
+        size: usize,
+        element: &'static str,
+        element_size: usize,
+    },
+}
+
+impl From<wgpu::Error> for RunError {
+    fn from(err: wgpu::Error) -> Self {
+        match err {
+            wgpu::Error::OutOfMemory { .. } => Self::OutOfMemory,
+            wgpu::Error::Validation { description, .. } => Self::Validation(description),
+            wgpu::Error::Internal { description, .. } => Self::Internal(description),
+        }
+    }
+}
+
+/// Any of the errors [`compute`] can fail with.
+#[derive(Debug, thiserror::Error)]
+pub enum ComputeError {
+    #[error("Unable to read shader {}: {source}", path.display())]
+    ReadShader {
+        path: std::path::PathBuf,
+        source: std::io::Error,
+    },
+    #[error(
+        "Entry point `{entry_point}` has a workgroup size of {}x{}x{}, but `compute` only dispatches \
+         one dimensional workgroups",
+        workgroup_size[0],
+        workgroup_size[1],
+        workgroup_size[2]
+    )]
+    WorkgroupSize {
+        entry_point: String,
+        workgroup_size: [u32; 3],
+    },
+    #[error(transparent)]
+    Initialize(#[from] InitializeError),
+    #[error(transparent)]
+    Reflect(#[from] ReflectError),
+    #[error(transparent)]
+    Plan(#[from] PlanError),
+    #[error(transparent)]
+    Run(#[from] RunError),
+}
+
+/// Runs `f` inside an error scope for every filter, returning the first error it caused.
+///
+/// Errors caught by a scope never reach [`UncapturedErrors`], so they can be attributed to `f`.
+fn scoped<T>(device: &wgpu::Device, f: impl FnOnce() -> T) -> (T, Option<wgpu::Error>) {
+    const FILTERS: [wgpu::ErrorFilter; 3] = [
+        wgpu::ErrorFilter::OutOfMemory,
+        wgpu::ErrorFilter::Validation,
+        wgpu::ErrorFilter::Internal,
+    ];
+
+    for filter in FILTERS {
+        device.push_error_scope(filter);
+    }
+
+    let value = f();
+    let errors = FILTERS.map(|_| {
+        // Native backends resolve scopes as they are popped, so there is no need for a runtime.
+        let mut error = std::pin::pin!(device.pop_error_scope());
+        match error.as_mut().poll(&mut Context::from_waker(Waker::noop())) {
+            Poll::Ready(error) => error,
+            Poll::Pending => None,
+        }
+    });
+
+    (value, errors.into_iter().flatten().next())
+}
+
+/// Collects the errors wgpu would otherwise panic on, so they can be returned as a [`RunError`].
+#[derive(Clone, Default)]
+struct UncapturedErrors(Arc<Mutex<Option<wgpu::Error>>>);
+
+impl UncapturedErrors {
+    fn install(device: &wgpu::Device) -> Self {
+        let errors = Self::default();
+        device.on_uncaptured_error(Box::new({
+            let errors = errors.clone();
+            move |err| {
+                // Later errors are usually fallout from the first, so only keep that.
+                errors.0.lock().unwrap().get_or_insert(err);
+            }
+        }));
+
+        errors
+    }
+
+    /// Returns the first error that occurred since the last check, if any.
+    fn check(&self) -> Result<(), RunError> {
+        match self.0.lock().unwrap().take() {
+            Some(err) => Err(err.into()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Trades numerical reproducibility against speed when compiling the shader.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum MathProfile {
+    /// Bounds-checks memory accesses, forces loops to terminate, and zero-initializes workgroup
+    /// memory, so results never depend on out-of-bounds or uninitialized data.
+    Strict,
+    /// Skips runtime checks and workgroup memory initialization.
+    Fast,
+}
+
+impl MathProfile {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Strict => "strict",
+            Self::Fast => "fast",
+        }
+    }
+
+    fn create_shader_module(
+        self,
+        device: &wgpu::Device,
+        options: wgpu::ShaderModuleDescriptor,
+    ) -> wgpu::ShaderModule {
+        match self {
+            Self::Strict => device.create_shader_module(options),
+            // SAFETY: The fast profile is opt-in, and documented as trusting the shader to not
+            // access out of bounds or loop forever.
+            Self::Fast => unsafe {
+                device.create_shader_module_trusted(options, wgpu::ShaderRuntimeChecks::unchecked())
+            },
+        }
+    }
+
+    fn compilation_options<'a>(
+        self,
+        constants: &'a [(&'a str, f64)],
+    ) -> wgpu::PipelineCompilationOptions<'a> {
+        wgpu::PipelineCompilationOptions {
+            constants,
+            zero_initialize_workgroup_memory: self == Self::Strict,
+        }
+    }
+}
+
+static ENCODER_OPTIONS: wgpu::CommandEncoderDescriptor = wgpu::CommandEncoderDescriptor {
+    label: Some("encoder"),
+};
+
+/// The query set each slice's compute pass writes its timestamps to, and where they are resolved.
+struct Timestamps {
+    query_set: wgpu::QuerySet,
+    resolve: wgpu::Buffer,
+    readback: wgpu::Buffer,
+}
+
+impl Timestamps {
+    /// Creates the queries for `plan`, unless the device cannot write timestamps in passes, or
+    /// the plan has no slices or too many to fit in one query set.
+    fn new(device: &wgpu::Device, plan: &Plan) -> Option<Self> {
+        let count = plan.timestamp_count();
+        if count == 0 || !device.features().contains(wgpu::Features::TIMESTAMP_QUERY) {
+            return None;
+        }
+        if count > wgpu::QUERY_SET_MAX_QUERIES {
+            log::warn!("Not timing the passes, as {count} timestamps do not fit in a query set");
+            return None;
+        }
+
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some("timestamp-query-set"),
+            ty: wgpu::QueryType::Timestamp,
+            count,
+        });
+
+        Some(Self {
+            query_set,
+            resolve: device.create_buffer(&plan.timestamp_resolve_buffer().descriptor()),
+            readback: device.create_buffer(&plan.timestamp_readback_buffer().descriptor()),
+        })
+    }
+
+    fn pass_writes(&self, slice: usize) -> wgpu::ComputePassTimestampWrites<'_> {
+        let beginning = (slice * 2) as u32;
+        wgpu::ComputePassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: Some(beginning),
+            end_of_pass_write_index: Some(beginning + 1),
+        }
+    }
+}
+
+/// How a plan's push constants reach the shader.
+enum PushConstantData {
+    /// Set on every compute pass.
+    Pass(Vec<u8>),
+    /// Written into a uniform buffer that the shader's push constant was rebound to, on devices
+    /// without push constants.
+    Uniform(wgpu::Buffer),
+}
+
+/// A shader compiled and bound following a plan, from [`GpuContext::prepare`].
+///
+/// Running it again with [`GpuContext::run_prepared`] reuses the pipeline and buffers, only
+/// encoding and submitting the passes, so repeated runs skip compiling the shader. New inputs can
+/// be given with [`GpuContext::run_prepared_with`], which only uploads those that changed.
+pub struct PreparedShader<'a> {
+    plan: &'a Plan,
+    pipeline: wgpu::ComputePipeline,
+    /// The bindings of every slice, or `None` if the plan [`Plan::is_empty`], so has no slices.
+    bind_group: Option<wgpu::BindGroup>,
+    /// The buffer of each of the plan's inputs, in order.
+    inputs: Vec<wgpu::Buffer>,
+    /// The contents last uploaded to each input buffer, or `None` if still the plan's contents.
+    uploaded: Vec<Option<Vec<u8>>>,
+    /// The intermediate working buffer, copied into the output after the last slice.
+    buffer: wgpu::Buffer,
+    /// Where output buffers come from, and where the working buffer goes once dropped if
+    /// [`pools_working_buffer`] allows it.
+    pool: BufferPool,
+    staging: Option<wgpu::Buffer>,
+    push_constants: Option<PushConstantData>,
+    time_sliced: bool,
+    telemetry: Option<wgpu::Buffer>,
+    /// The buffer each slice copies telemetry to.
+    telemetry_readbacks: Vec<wgpu::Buffer>,
+    timestamps: Option<Timestamps>,
+}
+
+/// Whether the working buffer of `plan` can be taken from and returned to a [`BufferPool`].
+///
+/// A reused buffer holds whatever its last user left in it, so this needs the plan to initialize
+/// the working buffer on every run, and to copy it into a separate output rather than read it
+/// back directly.
+fn pools_working_buffer(plan: &Plan) -> bool {
+    plan.init.is_some() && plan.output_buffer().is_some()
+}
+
+/// Compiles the shader `source` and creates the buffers and bindings `plan` needs to run it.
+///
+/// The shader is compiled following `profile`, and if `pipeline_cache` is provided, the compute
+/// pipeline is compiled through it. A plan with a [`plan::StageInput`] has `stage_input`, the
+/// working buffer of the previous pipeline stage, bound there. The working buffer is taken from
+/// `pool` if the plan allows it, as are the output buffers of each run.
+///
+/// Errors creating the shader module are returned as [`RunError::Compile`], while any other
+/// error is left for the caller's error scope.
+///
+/// This function
+/// 1. Creates an intermediate working buffer, a staging buffer if the plan uploads its contents,
+///    a buffer holding the contents of each input, and uniform buffers holding any params and
+///    slice offsets.
+/// 2. Compiles the shader into a module.
+/// 3. Creates a BindGroupLayout describing the working buffer, inputs, params, and slice offsets.
+/// 4. Creates a ComputePipelineLayout containing the BindGroupLayout
+/// 5. Creates a ComputePipeline that contains the shader module following the ComputePipelineLayout.
+/// 6. Creates a BindGroup that following the BindGroupLayout.
+fn prepare_compute_shader<'a>(
+    device: &wgpu::Device,
+    source: &Shader,
+    plan: &'a Plan,
+    stage_input: Option<&wgpu::Buffer>,
+    profile: MathProfile,
+    pipeline_cache: Option<&wgpu::PipelineCache>,
+    pool: &BufferPool,
+) -> Result<PreparedShader<'a>, RunError> {
+    let stage_input = match (plan.stage_input, stage_input) {
+        (Some(planned), Some(buffer)) => Some((planned.binding.binding, buffer.clone())),
+        (Some(_), None) => return Err(RunError::NoPreviousStage),
+        (None, _) => None,
+    };
+
+    // Devices without push constants, or with too little room for them, get them through a
+    // uniform buffer instead, with the shader's `var<push_constant>` rebound to it.
+    let push_constant_fallback = (plan.push_constants.as_ref()).filter(|push_constants| {
+        !device.features().contains(wgpu::Features::PUSH_CONSTANTS)
+            || push_constants.contents.len() as u32 > device.limits().max_push_constant_size
+    });
+    let fallback_binding = plan.push_constant_fallback_binding();
+
+    let shader_options = wgpu::ShaderModuleDescriptor {
+        label: Some("shader-main"),
+        source: match push_constant_fallback {
+            Some(_) => source.module_source_with_uniform_push_constants(fallback_binding)?,
+            None => source.module_source()?,
+        },
+    };
+
+    let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+        binding,
+        count: None,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        },
+    };
+
+    let uniform_entry = |binding, has_dynamic_offset| wgpu::BindGroupLayoutEntry {
+        binding,
+        count: None,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset,
+            min_binding_size: None,
+        },
+    };
+
+    let layout_entries: Vec<_> = std::iter::once(storage_entry(
+        plan::WORKING_BINDING.binding,
+        plan.working_read_only,
+    ))
+    .chain((plan.stage_input).map(|input| storage_entry(input.binding.binding, input.read_only)))
+    .chain((plan.inputs.iter()).map(|input| storage_entry(input.binding.binding, input.read_only)))
+    .chain((plan.params.as_ref()).map(|params| uniform_entry(params.binding.binding, false)))
+    .chain((plan.slice_offset).map(|binding| uniform_entry(binding.binding, true)))
+    .chain((plan.telemetry.as_ref()).map(|t| storage_entry(t.binding.binding, false)))
+    .chain(push_constant_fallback.map(|_| uniform_entry(fallback_binding.binding, false)))
+    .collect();
+
+    let bind_group_layout_options = wgpu::BindGroupLayoutDescriptor {
+        label: Some("bind-group-layout"),
+        entries: &layout_entries,
+    };
+
+    let buffer = match pools_working_buffer(plan) {
+        true => pool.acquire(device, &plan.working_buffer()),
+        false => device.create_buffer(&plan.working_buffer().descriptor()),
+    };
+    let staging = plan.staging_buffer().map(|staging| {
+        let contents = plan.init.and_then(|init| init.contents(staging.size));
+        device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&staging.label),
+            contents: &contents.expect("staging buffers are only planned for uploads"),
+            usage: staging.usages(),
+        })
+    });
+
+    let inputs: Vec<_> = (plan.inputs.iter())
+        .map(|input| {
+            let spec = plan.input_buffer(input);
+            let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(&spec.label),
+                contents: &input.contents,
+                usage: spec.usages(),
+            });
+
+            (input.binding.binding, buffer)
+        })
+        .collect();
+
+    let params = plan
+        .params
+        .as_ref()
+        .zip(plan.params_buffer())
+        .map(|(params, spec)| {
+            let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(&spec.label),
+                contents: &params.contents,
+                usage: spec.usages(),
+            });
+
+            (params.binding.binding, buffer)
+        });
+
+    let slice_offsets = plan
+        .slice_offset
+        .zip(plan.slice_offset_buffer())
+        .map(|(binding, spec)| {
+            let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(&spec.label),
+                contents: &plan.slice_offset_contents(),
+                usage: spec.usages(),
+            });
+
+            (binding.binding, buffer)
+        });
+
+    let push_constant_buffer =
+        (push_constant_fallback.zip(plan.push_constant_buffer())).map(|(push_constants, spec)| {
+            let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(&spec.label),
+                contents: &push_constants.contents,
+                usage: spec.usages(),
+            });
+
+            (fallback_binding.binding, buffer)
+        });
+
+    // wgpu zeroes buffers on creation, so the counters start from zero without an upload.
+    let telemetry =
+        (plan.telemetry.as_ref().zip(plan.telemetry_buffer())).map(|(telemetry, spec)| {
+            (
+                telemetry.binding.binding,
+                device.create_buffer(&spec.descriptor()),
+            )
+        });
+    let telemetry_readbacks: Vec<_> = (plan.telemetry_readback_buffer().iter())
+        .flat_map(|spec| {
+            plan.slices()
+                .into_iter()
+                .map(|_| device.create_buffer(&spec.descriptor()))
+        })
+        .collect();
+
+    let timestamps = Timestamps::new(device, plan);
+
+    let (shader, error) = scoped(device, || {
+        profile.create_shader_module(device, shader_options)
+    });
+    match error {
+        Some(err @ wgpu::Error::OutOfMemory { .. }) => return Err(err.into()),
+        Some(wgpu::Error::Validation { description, .. })
+        | Some(wgpu::Error::Internal { description, .. }) => {
+            return Err(RunError::Compile(description));
+        }
+        None => {}
+    }
+
+    let push_constant_ranges: Vec<_> = (plan.push_constants.iter())
+        .filter(|_| push_constant_fallback.is_none())
+        .map(|push_constants| wgpu::PushConstantRange {
+            stages: wgpu::ShaderStages::COMPUTE,
+            range: 0..push_constants.contents.len() as u32,
+        })
+        .collect();
+
+    let bind_group_layout = device.create_bind_group_layout(&bind_group_layout_options);
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: Some("pipeline-layout-descriptor"),
+        bind_group_layouts: &[&bind_group_layout],
+        push_constant_ranges: &push_constant_ranges,
+    });
+
+    let constants: Vec<_> = (plan.constants.iter())
+        .map(|(key, value)| (key.as_str(), *value))
+        .collect();
+    let compute_pipeline_options = wgpu::ComputePipelineDescriptor {
+        label: Some("compile-pipeline"),
+        layout: Some(&pipeline_layout),
+        module: &shader,
+        entry_point: plan.entry_point.as_ref().map(|e| e.name.as_str()),
+        compilation_options: profile.compilation_options(&constants),
+        cache: pipeline_cache,
+    };
+
+    let bind_group_entries: Vec<_> = std::iter::once((plan::WORKING_BINDING.binding, &buffer))
+        .chain(
+            (stage_input.iter())
+                .chain(&inputs)
+                .chain(&params)
+                .chain(&telemetry)
+                .chain(&push_constant_buffer)
+                .map(|(binding, buffer)| (*binding, buffer)),
+        )
+        .map(|(binding, buffer)| wgpu::BindGroupEntry {
+            binding,
+            resource: buffer.as_entire_binding(),
+        })
+        .chain(
+            (slice_offsets.iter()).map(|(binding, buffer)| wgpu::BindGroupEntry {
+                binding: *binding,
+                // Each slice binds its own offset, selected with a dynamic offset.
+                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                    buffer,
+                    offset: 0,
+                    size: wgpu::BufferSize::new(size_of::<u32>() as u64),
+                }),
+            }),
+        )
+        .collect();
+
+    let bind_group_options = wgpu::BindGroupDescriptor {
+        label: Some("bind-group"),
+        layout: &bind_group_layout,
+        entries: &bind_group_entries,
+    };
+
+    let pipeline = device.create_compute_pipeline(&compute_pipeline_options);
+    let bind_group = match plan.is_empty() {
+        true => {
+            log::info!("Not binding or dispatching the shader, as the plan has no work to do");
+            None
+        }
+        false => Some(device.create_bind_group(&bind_group_options)),
+    };
+    Ok(PreparedShader {
+        plan,
+        pipeline,
+        bind_group,
+        uploaded: vec![None; inputs.len()],
+        inputs: inputs.into_iter().map(|(_, buffer)| buffer).collect(),
+        buffer,
+        pool: pool.clone(),
+        staging,
+        push_constants: match (&plan.push_constants, push_constant_buffer) {
+            (_, Some((_, buffer))) => Some(PushConstantData::Uniform(buffer)),
+            (Some(push_constants), None) => {
+                Some(PushConstantData::Pass(push_constants.contents.clone()))
+            }
+            (None, None) => None,
+        },
+        time_sliced: slice_offsets.is_some(),
+        telemetry: telemetry.map(|(_, buffer)| buffer),
+        telemetry_readbacks,
+        timestamps,
+    })
+}
+
+impl PreparedShader<'_> {
+    /// The contents input `index` was last uploaded with.
+    fn input_contents(&self, index: usize) -> &[u8] {
+        match &self.uploaded[index] {
+            Some(contents) => contents,
+            None => &self.plan.inputs[index].contents,
+        }
+    }
+
+    /// Encodes one run of the shader, copying the output to `output`, with one command buffer for
+    /// each of the plan's slices to be submitted in order.
+    ///
+    /// This
+    /// 1. Creates a CommandEncoder, encoding the plan's initialization of the working buffer, and
+    ///    clearing any telemetry left by a previous run.
+    /// 2. Creates a ComputePass with the ComputePipeline and BindGroup for each slice.
+    /// 3. Encodes each slice's dispatched ComputePass into its own CommandEncoder.
+    /// 4. Encodes a copy of any telemetry after each slice, and a copy from the intermediate
+    ///    buffer into `output` after the last slice.
+    /// 5. Finishes the encoders.
+    fn encode(&self, device: &wgpu::Device, output: &wgpu::Buffer) -> Vec<wgpu::CommandBuffer> {
+        let mut encoder = device.create_command_encoder(&ENCODER_OPTIONS);
+        self.encode_init(&mut encoder);
+
+        let slices = self.plan.slices();
+        let mut command_buffers = Vec::with_capacity(slices.len());
+        for (index, (_, workgroups)) in slices.into_iter().enumerate() {
+            if index > 0 {
+                command_buffers.push(encoder.finish());
+                encoder = device.create_command_encoder(&ENCODER_OPTIONS);
+            }
+
+            self.encode_slice(&mut encoder, index, workgroups);
+        }
+
+        self.encode_output(&mut encoder, output);
+        self.encode_timestamps(&mut encoder);
+        command_buffers.push(encoder.finish());
+        command_buffers
+    }
+
+    /// Encodes the plan's initialization of the working buffer, and clears any telemetry left by a
+    /// previous run.
+    fn encode_init(&self, encoder: &mut wgpu::CommandEncoder) {
+        match &self.staging {
+            Some(staging) => {
+                encoder.copy_buffer_to_buffer(staging, 0, &self.buffer, 0, self.plan.output_size);
+            }
+            None if self.plan.init.is_some() => encoder.clear_buffer(&self.buffer, 0, None),
+            None => {}
+        }
+
+        if let Some(telemetry) = &self.telemetry {
+            encoder.clear_buffer(telemetry, 0, None);
+        }
+    }
+
+    /// Encodes the compute pass of slice `index`, followed by a copy of any telemetry.
+    fn encode_slice(&self, encoder: &mut wgpu::CommandEncoder, index: usize, workgroups: u32) {
+        let dynamic_offsets = match self.time_sliced {
+            true => vec![(index as u64 * plan::SLICE_OFFSET_STRIDE) as u32],
+            false => Vec::new(),
+        };
+
+        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor {
+            label: None,
+            timestamp_writes: (self.timestamps.as_ref())
+                .map(|timestamps| timestamps.pass_writes(index)),
+        });
+        pass.set_pipeline(&self.pipeline);
+        pass.set_bind_group(0, self.bind_group.as_ref(), &dynamic_offsets);
+        if let Some(PushConstantData::Pass(contents)) = &self.push_constants {
+            pass.set_push_constants(0, contents);
+        }
+        pass.dispatch_workgroups(workgroups, self.plan.dispatch.y, self.plan.dispatch.z);
+        drop(pass);
+
+        if let (Some(telemetry), Some(readback)) =
+            (&self.telemetry, self.telemetry_readbacks.get(index))
+        {
+            encoder.copy_buffer_to_buffer(telemetry, 0, readback, 0, telemetry.size());
+        }
+    }
+
+    /// Encodes a copy from the intermediate working buffer into `output`, unless the plan reads
+    /// the working buffer back directly or in chunks.
+    fn encode_output(&self, encoder: &mut wgpu::CommandEncoder, output: &wgpu::Buffer) {
+        if self.plan.output_buffer().is_some() {
+            encoder.copy_buffer_to_buffer(&self.buffer, 0, output, 0, self.plan.output_size);
+        }
+    }
+
+    /// Creates the buffer a run's output is read back from, or reuses one from the pool, which is
+    /// the working buffer itself if the plan is zero-copy or read back in chunks.
+    fn create_output(&self, device: &wgpu::Device) -> wgpu::Buffer {
+        match self.plan.output_buffer() {
+            Some(spec) => self.pool.acquire(device, &spec),
+            None => self.buffer.clone(),
+        }
+    }
+
+    /// Encodes resolving the pass timestamps and copying them out, if the device writes them.
+    fn encode_timestamps(&self, encoder: &mut wgpu::CommandEncoder) {
+        if let Some(timestamps) = &self.timestamps {
+            let queries = 0..self.plan.timestamp_count();
+            encoder.resolve_query_set(&timestamps.query_set, queries, &timestamps.resolve, 0);
+            encoder.copy_buffer_to_buffer(
+                &timestamps.resolve,
+                0,
+                &timestamps.readback,
+                0,
+                timestamps.resolve.size(),
+            );
+        }
+    }
+}
+
+impl Drop for PreparedShader<'_> {
+    fn drop(&mut self) {
+        if pools_working_buffer(self.plan) {
+            self.pool
+                .release(&self.plan.working_buffer(), self.buffer.clone());
+        }
+    }
+}
+
+/// A step in running a shader, passed to the observer set with [`GpuContext::observe`].
+#[derive(Clone, Debug)]
+pub enum Event {
+    /// The shader started compiling.
+    CompileStarted,
+    /// The shader module and compute pipeline were created, taking `elapsed`.
+    CompileFinished { elapsed: Duration },
+    /// Submission `index` of `count` was submitted to the queue.
+    Submitted { index: usize, count: usize },
+    /// Submission `index` finished on the GPU, `elapsed` after it was submitted.
+    PassCompleted { index: usize, elapsed: Duration },
+    /// Every submission of a run finished, with the time the GPU spent in the compute passes if
+    /// the device supports timestamp queries.
+    RunCompleted {
+        elapsed: Duration,
+        gpu_elapsed: Option<Duration>,
+    },
+    /// `bytes` were read back from a buffer, `elapsed` after mapping it was requested.
+    ReadbackDone { bytes: usize, elapsed: Duration },
+}
+
+type Observer = Box<dyn Fn(&Event) + Send + Sync>;
+
+/// An adapter, device, and queue, with the device's uncaptured errors collected for reporting.
+pub struct GpuContext {
+    pub adapter: wgpu::Adapter,
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+    uncaptured_errors: UncapturedErrors,
+    observer: Option<Observer>,
+    poll_strategy: PollStrategy,
+    pool: BufferPool,
+}
+
+/// The result of [`GpuContext::run_shader`].
+pub struct ShaderRun {
+    /// The buffer the output was copied into, ready for [`GpuContext::read_buffer`].
+    ///
+    /// For a [`Plan::zero_copy`] plan this is the working buffer itself, so running the same
+    /// prepared shader again overwrites it. So is a [`Plan::readback_chunk`] plan's, which is
+    /// read with [`GpuContext::read_chunked`] instead.
+    pub output: wgpu::Buffer,
+    /// The wall-clock time from submission until the GPU finished, summed over submissions.
+    pub elapsed: Duration,
+    /// The time the GPU spent in the compute passes, summed over submissions, if the device
+    /// supports timestamp queries.
+    pub gpu_elapsed: Option<Duration>,
+    /// The shader's telemetry counters after each submission, if it declares
+    /// [`plan::TELEMETRY_NAME`].
+    pub telemetry: Vec<Vec<u32>>,
+    /// The spec `output` was taken from the buffer pool with, or `None` if it is the working
+    /// buffer.
+    pooled_output: Option<BufferSpec>,
+}
+
+impl GpuContext {
+    /// Requests a high performance adapter, and a device with the downlevel default limits.
+    pub async fn new() -> Result<Self, InitializeError> {
+        Self::with_selection(&AdapterSelection::default()).await
+    }
+
+    /// Like [`GpuContext::new`], but requests the device from the adapter `selection` picks.
+    pub async fn with_selection(selection: &AdapterSelection) -> Result<Self, InitializeError> {
+        static ADAPTER_OPTIONS: wgpu::RequestAdapterOptions = wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            force_fallback_adapter: false,
+            compatible_surface: None,
+        };
+
+        /// Features that are used when available, but are not required.
+        const OPTIONAL_FEATURES: wgpu::Features = wgpu::Features::PIPELINE_CACHE
+            .union(wgpu::Features::TIMESTAMP_QUERY)
+            .union(wgpu::Features::PUSH_CONSTANTS);
+
+        /// Features that are only used on adapters that share memory with the host, as they are
+        /// slow on discrete GPUs.
+        const UNIFIED_MEMORY_FEATURES: wgpu::Features = wgpu::Features::MAPPABLE_PRIMARY_BUFFERS;
+
+        let gpu = selection.instance();
+        let adapter = if let Some(filter) = &selection.adapter {
+            let adapters = gpu.enumerate_adapters(wgpu::Backends::all());
+            let count = adapters.len();
+            let mut matching = (adapters.into_iter().enumerate())
+                .filter(|(index, adapter)| filter.matches(*index, &adapter.get_info()));
+
+            let Some((_, adapter)) = matching.next() else {
+                return Err(InitializeError::NoMatchingAdapter(filter.clone(), count));
+            };
+
+            adapter
+        } else {
+            let Ok(adapter) = gpu.request_adapter(&ADAPTER_OPTIONS).await else {
+                return Err(InitializeError::NoAdapter);
+            };
+
+            adapter
+        };
+
+        let info = adapter.get_info();
+        log::info!("Using adapter {info:?}");
+
+        let mut wanted_features = OPTIONAL_FEATURES;
+        if matches!(
+            info.device_type,
+            wgpu::DeviceType::IntegratedGpu | wgpu::DeviceType::Cpu
+        ) {
+            wanted_features |= UNIFIED_MEMORY_FEATURES;
+        }
+
+        let device_options = wgpu::DeviceDescriptor {
+            label: Some("device"),
+            required_features: adapter.features() & wanted_features,
+            required_limits: wgpu::Limits {
+                // There is no portable limit, so take all the room the adapter has, which is
+                // none without push constants.
+                max_push_constant_size: adapter.limits().max_push_constant_size,
+                ..wgpu::Limits::downlevel_defaults()
+            },
+            memory_hints: wgpu::MemoryHints::Performance,
+            trace: wgpu::Trace::Off,
+        };
+
+        let Ok((device, queue)) = adapter.request_device(&device_options).await else {
+            return Err(InitializeError::NoDevice);
+        };
+
+        let uncaptured_errors = UncapturedErrors::install(&device);
+        Ok(Self {
+            adapter,
+            device,
+            queue,
+            uncaptured_errors,
+            observer: None,
+            poll_strategy: PollStrategy::default(),
+            pool: BufferPool::default(),
+        })
+    }
+
+    /// Whether plans can set [`Plan::zero_copy`] to read their output back without a copy, which
+    /// is only enabled on integrated GPUs and other adapters that share memory with the host.
+    pub fn supports_zero_copy(&self) -> bool {
+        (self.device.features()).contains(wgpu::Features::MAPPABLE_PRIMARY_BUFFERS)
+    }
+
+    /// Submits the shader `source` following `plan`, and waits for the GPU to finish.
+    ///
+    /// The shader is compiled following `profile`, and if `pipeline_cache` is provided, the
+    /// compute pipeline is compiled through it. To run the same shader many times, prepare it
+    /// once with [`Self::prepare`] instead.
+    pub fn run_shader<'s>(
+        &self,
+        source: impl Into<Shader<'s>>,
+        plan: &Plan,
+        profile: MathProfile,
+        pipeline_cache: Option<&wgpu::PipelineCache>,
+    ) -> Result<ShaderRun, RunError> {
+        let prepared = self.prepare(source, plan, profile, pipeline_cache)?;
+        self.run_prepared(&prepared)
+    }
+
+    /// Compiles the shader `source` and creates the buffers `plan` needs, without running it.
+    ///
+    /// The shader is compiled following `profile`, and if `pipeline_cache` is provided, the
+    /// compute pipeline is compiled through it.
+    pub fn prepare<'a, 's>(
+        &self,
+        source: impl Into<Shader<'s>>,
+        plan: &'a Plan,
+        profile: MathProfile,
+        pipeline_cache: Option<&wgpu::PipelineCache>,
+    ) -> Result<PreparedShader<'a>, RunError> {
+        let source = source.into();
+        self.emit(Event::CompileStarted);
+        let start = Instant::now();
+        let (prepared, error) = scoped(&self.device, || {
+            prepare_compute_shader(
+                &self.device,
+                &source,
+                plan,
+                None,
+                profile,
+                pipeline_cache,
+                &self.pool,
+            )
+        });
+
+        if let Some(err) = error {
+            return Err(err.into());
+        }
+
+        let prepared = prepared?;
+        self.emit(Event::CompileFinished {
+            elapsed: start.elapsed(),
+        });
+        Ok(prepared)
+    }
+
+    /// Calls `observer` with each [`Event`] as shaders are compiled, run, and read back.
+    pub fn observe(&mut self, observer: impl Fn(&Event) + Send + Sync + 'static) {
+        self.observer = Some(Box::new(observer));
+    }
+
+    /// Sets how to wait for the GPU to finish runs and readbacks, which is
+    /// [`PollStrategy::LOW_LATENCY`] by default.
+    pub fn set_poll_strategy(&mut self, strategy: PollStrategy) {
+        self.poll_strategy = strategy;
+    }
+
+    /// Waits for every submission so far, up to and including `submission`, to finish.
+    fn wait_for(&self, submission: wgpu::SubmissionIndex) -> Result<(), wgpu::PollError> {
+        let done = Arc::new(AtomicBool::new(false));
+        self.queue.on_submitted_work_done({
+            let done = done.clone();
+            move || done.store(true, Ordering::Release)
+        });
+
+        self.poll_strategy.wait(
+            &self.device,
+            wgpu::PollType::WaitForSubmissionIndex(submission),
+            || done.load(Ordering::Acquire),
+        )
+    }
+
+    fn emit(&self, event: Event) {
+        if let Some(observer) = &self.observer {
+            observer(&event);
+        }
+    }
+
+    /// Runs a shader from [`Self::prepare`] once, and waits for the GPU to finish.
+    pub fn run_prepared(&self, prepared: &PreparedShader) -> Result<ShaderRun, RunError> {
+        let ((output, command_buffers), error) = scoped(&self.device, || {
+            let output = prepared.create_output(&self.device);
+            let command_buffers = prepared.encode(&self.device, &output);
+
+            (output, command_buffers)
+        });
+
+        if let Some(err) = error {
+            return Err(err.into());
+        }
+
+        let mut elapsed = Duration::ZERO;
+        let mut telemetry = Vec::new();
+        let count = command_buffers.len();
+        for (index, command_buffer) in command_buffers.into_iter().enumerate() {
+            let start = Instant::now();
+            let submission = self.queue.submit(std::iter::once(command_buffer));
+            self.emit(Event::Submitted { index, count });
+
+            // wgpu treats polling for a submission that failed validation as fatal, so check first.
+            self.uncaptured_errors.check()?;
+            self.wait_for(submission)?;
+            let pass_elapsed = start.elapsed();
+            self.emit(Event::PassCompleted {
+                index,
+                elapsed: pass_elapsed,
+            });
+            elapsed += pass_elapsed;
+
+            if let Some(readback) = prepared.telemetry_readbacks.get(index) {
+                let counters: Vec<u32> = self.read_back(readback)?;
+                log::info!("Submission {index} telemetry: {counters:?}");
+                telemetry.push(counters);
+            }
+        }
+        self.uncaptured_errors.check()?;
+        log::info!("GPU Completed");
+
+        let gpu_elapsed = match &prepared.timestamps {
+            Some(timestamps) => Some(self.pass_time(&timestamps.readback)?),
+            None => None,
+        };
+        self.emit(Event::RunCompleted {
+            elapsed,
+            gpu_elapsed,
+        });
+
+        Ok(ShaderRun {
+            output,
+            elapsed,
+            gpu_elapsed,
+            telemetry,
+            pooled_output: prepared.plan.output_buffer(),
+        })
+    }
+
+    /// Runs a shader from [`Self::prepare`] once like [`Self::run_prepared`], but with `inputs`
+    /// in place of the plan's inputs.
+    ///
+    /// Each input must be the same size as the plan's, and read-only inputs are only written to
+    /// the GPU if they differ from their last upload, so those that rarely change cost nothing to
+    /// pass again.
+    pub fn run_prepared_with(
+        &self,
+        prepared: &mut PreparedShader,
+        inputs: &[&[u8]],
+    ) -> Result<ShaderRun, RunError> {
+        if inputs.len() != prepared.inputs.len() {
+            return Err(RunError::InputCount {
+                expected: prepared.inputs.len(),
+                actual: inputs.len(),
+            });
+        }
+
+        for (index, &contents) in inputs.iter().enumerate() {
+            let input = &prepared.plan.inputs[index];
+            if contents.len() != input.contents.len() {
+                return Err(RunError::InputSize {
+                    index,
+                    expected: input.contents.len(),
+                    actual: contents.len(),
+                });
+            }
+
+            // The shader may have written to inputs that are not read-only, so always upload those.
+            if !input.read_only || prepared.input_contents(index) != contents {
+                log::debug!("Uploading {} bytes to input {index}", contents.len());
+                self.queue
+                    .write_buffer(&prepared.inputs[index], 0, contents);
+                prepared.uploaded[index] = Some(contents.to_vec());
+            }
+        }
+
+        self.run_prepared(prepared)
+    }
+
+    /// Replaces the push constants of a shader from [`Self::prepare`] for its next runs.
+    ///
+    /// On devices with push constants this uploads nothing, as they are set on each compute pass,
+    /// so suits values that change every run, like an iteration index. Elsewhere they are
+    /// written to the uniform buffer standing in for them. `contents` must be the size of the
+    /// shader's `var<push_constant>`.
+    pub fn set_push_constants(
+        &self,
+        prepared: &mut PreparedShader,
+        contents: &[u8],
+    ) -> Result<(), RunError> {
+        let expected = (prepared.plan.push_constants.as_ref())
+            .map_or(0, |push_constants| push_constants.contents.len());
+        if contents.len() != expected {
+            return Err(RunError::PushConstantSize {
+                expected,
+                actual: contents.len(),
+            });
+        }
+
+        match &mut prepared.push_constants {
+            Some(PushConstantData::Pass(data)) => data.copy_from_slice(contents),
+            Some(PushConstantData::Uniform(buffer)) => self.queue.write_buffer(buffer, 0, contents),
+            None => {}
+        }
+
+        Ok(())
+    }
+
+    /// Sums the time between each pair of pass timestamps in `readback`.
+    fn pass_time(&self, readback: &wgpu::Buffer) -> Result<Duration, RunError> {
+        let timestamps: Vec<u64> = self.read_back(readback)?;
+        let ticks: u64 = (timestamps.chunks_exact(2))
+            .map(|pass| pass[1].saturating_sub(pass[0]))
+            .sum();
+
+        let nanos = ticks as f64 * f64::from(self.queue.get_timestamp_period());
+        Ok(Duration::from_nanos(nanos as u64))
+    }
+
+    /// Maps `buffer`, which must have been created with `MAP_READ`, and copies out its contents.
+    ///
+    /// An empty buffer cannot be mapped, so it reads back as empty without waiting for the GPU.
+    pub fn read_buffer(&self, buffer: &wgpu::Buffer) -> Result<Vec<u8>, RunError> {
+        if buffer.size() == 0 {
+            return Ok(Vec::new());
+        }
+
+        let start = Instant::now();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        buffer.map_async(wgpu::MapMode::Read, .., move |result| {
+            // The receiver outlives the poll below, so this cannot fail.
+            let _ = sender.send(result);
+        });
+
+        let mut result = None;
+        self.poll_strategy
+            .wait(&self.device, wgpu::PollType::Wait, || {
+                result = receiver.try_recv().ok();
+                result.is_some()
+            })?;
+        let result = result.or_else(|| receiver.recv().ok());
+        result.unwrap_or(Err(wgpu::BufferAsyncError))?;
+
+        let data = buffer.get_mapped_range(..).to_vec();
+        buffer.unmap();
+        self.emit(Event::ReadbackDone {
+            bytes: data.len(),
+            elapsed: start.elapsed(),
+        });
+        Ok(data)
+    }
+
+    /// Like [`Self::read_buffer`], but sleeps between polls on the runtime and blocks on a
+    /// blocking thread, so the runtime can make progress on other tasks in the meantime.
+    pub async fn read_buffer_async(&self, buffer: &wgpu::Buffer) -> Result<Vec<u8>, RunError> {
+        self.read_buffer_range_async(buffer, buffer.size()).await
+    }
+
+    /// Like [`Self::read_buffer_async`], but only reads the first `size` bytes of `buffer`.
+    async fn read_buffer_range_async(
+        &self,
+        buffer: &wgpu::Buffer,
+        size: u64,
+    ) -> Result<Vec<u8>, RunError> {
+        if size == 0 {
+            return Ok(Vec::new());
+        }
+
+        let start = Instant::now();
+        let (sender, mut receiver) = tokio::sync::oneshot::channel();
+        buffer.map_async(wgpu::MapMode::Read, ..size, move |result| {
+            // The receiver is awaited below, so this cannot fail.
+            let _ = sender.send(result);
+        });
+
+        let mut result = None;
+        self.poll_strategy
+            .wait_async(&self.device, wgpu::PollType::Wait, || {
+                result = receiver.try_recv().ok();
+                result.is_some()
+            })
+            .await?;
+        let result = match result {
+            Some(result) => result,
+            None => receiver.await.unwrap_or(Err(wgpu::BufferAsyncError)),
+        };
+        result?;
+
+        let data = buffer.get_mapped_range(..size).to_vec();
+        buffer.unmap();
+        self.emit(Event::ReadbackDone {
+            bytes: data.len(),
+            elapsed: start.elapsed(),
+        });
+        Ok(data)
+    }
+
+    /// Reads `buffer` like [`Self::read_buffer_async`], and sends its contents to `sink`.
+    pub async fn read_into(
+        &self,
+        buffer: &wgpu::Buffer,
+        sink: &mut (impl OutputSink + ?Sized),
+    ) -> Result<(), RunError> {
+        let data = self.read_buffer_async(buffer).await?;
+        sink.write_output(&data).map_err(RunError::Sink)
+    }
+
+    /// Reads `buffer`, which must have been created with `COPY_SRC`, into `sink` in chunks of at
+    /// most `chunk_size` bytes, so its contents never need to fit in memory at once.
+    ///
+    /// Each chunk is copied into one staging buffer, reused for every chunk, and sent to `sink`
+    /// as soon as it is mapped, so only the staging buffer is limited to `chunk_size`, while
+    /// `buffer` itself is as large as it was created. The chunk size is rounded down to a
+    /// multiple of [`wgpu::COPY_BUFFER_ALIGNMENT`].
+    pub async fn read_chunked(
+        &self,
+        buffer: &wgpu::Buffer,
+        chunk_size: u64,
+        sink: &mut (impl OutputSink + ?Sized),
+    ) -> Result<(), RunError> {
+        if buffer.size() == 0 {
+            return Ok(());
+        }
+
+        let align = wgpu::COPY_BUFFER_ALIGNMENT;
+        let chunk_size = (chunk_size.min(self.device.limits().max_buffer_size) / align * align)
+            .clamp(align, buffer.size().next_multiple_of(align));
+        let spec =
+            BufferSpec::new(plan::READBACK_CHUNK_LABEL, chunk_size).role(BufferRole::Readback);
+        let staging = self.pool.acquire(&self.device, &spec);
+
+        // The staging buffer goes back to the pool even if a chunk fails.
+        let result = self.read_chunks(buffer, &staging, sink).await;
+        self.pool.release(&spec, staging);
+        result
+    }
+
+    /// Copies `buffer` through `staging` one chunk at a time, as large as `staging`.
+    async fn read_chunks(
+        &self,
+        buffer: &wgpu::Buffer,
+        staging: &wgpu::Buffer,
+        sink: &mut (impl OutputSink + ?Sized),
+    ) -> Result<(), RunError> {
+        let chunk_size = staging.size();
+        for offset in (0..buffer.size()).step_by(chunk_size as usize) {
+            let size = chunk_size.min(buffer.size() - offset);
+            let mut encoder = self.device.create_command_encoder(&Default::default());
+            encoder.copy_buffer_to_buffer(buffer, offset, staging, 0, size);
+            self.queue.submit([encoder.finish()]);
+
+            let chunk = self.read_buffer_range_async(staging, size).await?;
+            sink.write_output(&chunk).map_err(RunError::Sink)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the output buffer of `run` to be reused by later runs of the same output size,
+    /// once it has been read back.
+    ///
+    /// Outputs that are a working buffer, from [`Plan::zero_copy`] or [`Plan::readback_chunk`]
+    /// plans, still belong to their shader, so are dropped instead.
+    pub fn recycle(&self, run: ShaderRun) {
+        if let Some(spec) = &run.pooled_output {
+            self.pool.release(spec, run.output);
+        }
+    }
+
+    /// Reads `buffer` like [`Self::read_buffer`], reinterpreting its contents as `T`s.
+    ///
+    /// The contents are copied, so `T` may need a stricter alignment than the mapping has, but
+    /// the buffer must hold a whole number of `T`s.
+    pub fn read_back<T: bytemuck::Pod>(&self, buffer: &wgpu::Buffer) -> Result<Vec<T>, RunError> {
+        cast_read_back(&self.read_buffer(buffer)?)
+    }
+
+    /// Like [`Self::read_back`], but reads the buffer with [`Self::read_buffer_async`].
+    pub async fn read_back_async<T: bytemuck::Pod>(
+        &self,
+        buffer: &wgpu::Buffer,
+    ) -> Result<Vec<T>, RunError> {
+        cast_read_back(&self.read_buffer_async(buffer).await?)
+    }
+}
+
+/// Copies `data` into `T`s, failing unless it holds a whole number of them.
+fn cast_read_back<T: bytemuck::Pod>(data: &[u8]) -> Result<Vec<T>, RunError> {
+    if !data.len().is_multiple_of(size_of::<T>()) {
+        return Err(RunError::ReadBackSize {
+            size: data.len(),
+            element: std::any::type_name::<T>(),
+            element_size: size_of::<T>(),
+        });
+    }
+
+    Ok(bytemuck::pod_collect_to_vec(data))
+}
+
+/// Runs the shader `source` once for every element of `input`, returning its output.
+///
+/// The shader reads `input` from `@group(0) @binding(1)` and writes one `Out` per element to
+/// `@group(0) @binding(0)`, indexing both by `global_invocation_id.x`. Its workgroup size must
+/// be one dimensional, like `@workgroup_size(64)`, and enough workgroups are dispatched in x to
+/// cover `input`, so the shader should skip invocations past `arrayLength` of its input.
+///
+/// This creates a new [`GpuContext`] for every call, so use one directly to run many shaders.
+pub async fn compute<'s, In: bytemuck::Pod, Out: bytemuck::Pod>(
+    source: impl Into<Shader<'s>>,
+    input: &[In],
+) -> Result<Vec<Out>, ComputeError> {
+    let source = source.into();
+    let gpu = GpuContext::new().await?;
+    let reflection = Reflection::new(&source)?;
+
+    let mut plan = Plan::new(&reflection, (input.len() * size_of::<Out>()) as u64);
+    plan.set_entry_point(&reflection, None)?;
+    plan.add_input(&reflection, bytemuck::cast_slice(input).to_vec());
+    plan.bind_remaining(&reflection)?;
+    plan.zero_copy = gpu.supports_zero_copy();
+
+    if let Some(entry_point) = &plan.entry_point {
+        let [x, y, z] = entry_point.workgroup_size;
+        if y != 1 || z != 1 {
+            return Err(ComputeError::WorkgroupSize {
+                entry_point: entry_point.name.clone(),
+                workgroup_size: entry_point.workgroup_size,
+            });
+        }
+
+        let workgroups = input.len().div_ceil(x as usize);
+        plan.dispatch = DispatchSize {
+            x: u32::try_from(workgroups).unwrap_or(u32::MAX),
+            ..DispatchSize::ONE
+        };
+    }
+
+    plan.check(&gpu.device.limits(), &Budget::default())?;
+    let shader_run = gpu.run_shader(&source, &plan, MathProfile::Strict, None)?;
+    Ok(gpu.read_back_async(&shader_run.output).await?)
+}
+
+/// Like [`compute`], but reads the shader from the file at `path` when called, as SPIR-V if its
+/// extension is `.spv` and as WGSL otherwise.
+pub async fn compute_file<In: bytemuck::Pod, Out: bytemuck::Pod>(
+    path: impl AsRef<std::path::Path>,
+    input: &[In],
+) -> Result<Vec<Out>, ComputeError> {
+    let path = path.as_ref();
+    let shader = Shader::read(path, None).map_err(|source| ComputeError::ReadShader {
+        path: path.to_owned(),
+        source,
+    })?;
+
+    compute(shader, input).await
+}
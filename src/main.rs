@@ -1,166 +1,956 @@
-use std::{borrow::Cow, error::Error};
+use std::{
+    error::Error, num::NonZeroU32, path::Path, process::ExitCode, str::FromStr, time::Duration,
+};
 
-use wgpu::{BufferDescriptor, ComputePassDescriptor};
+use gpu_scratch::{
+    AdapterSelection, GpuContext, MathProfile, RunError, ShaderRun,
+    cache::ArtifactCache,
+    hash::{Sha256, to_hex},
+    plan::{Budget, DispatchSize, Plan},
+    poll::PollStrategy,
+    reflect::{ReflectError, Reflection},
+    shader::{Language, Shader},
+    sink::OutputSink,
+};
+
+use crate::{
+    exit::ExitStatus, journal::Journal, json::Json, mesh::Mesh, output::OutputFormat,
+    post::PostExpression,
+};
+
+mod bench;
+mod cli;
+mod events;
+mod exit;
+mod hint;
+mod journal;
+mod json;
+mod mesh;
+mod npy;
+mod output;
+mod post;
+mod power;
+mod selftest;
+mod summary;
+mod sweep;
+mod watch;
+
+const SHADER_SOURCE: &str = include_str!("main.wgsl");
+
+/// The size of the shader's output, in bytes, unless given `--output-size`.
+const OUTPUT_SIZE: u64 = (12 * size_of::<u32>()) as u64;
+
+/// The artifact cache kind used for serialized `wgpu::PipelineCache` data.
+const PIPELINE_CACHE_KIND: &str = "pipeline";
 
 #[tokio::main(flavor = "current_thread")]
-async fn main() -> Result<(), Box<dyn Error>> {
-    real_main().await
+async fn main() -> ExitCode {
+    match real_main().await {
+        Ok(()) => ExitStatus::Success.into(),
+        Err(err) => {
+            eprintln!("Error: {err}");
+            if let Some(hint) = hint::for_error(&*err) {
+                eprintln!("Hint: {hint}");
+            }
+
+            ExitStatus::classify(&*err).into()
+        }
+    }
 }
 
-#[derive(Debug, thiserror::Error)]
-pub enum InitializeError {
-    #[error("Unable to find GPU adapter!")]
-    NoAdapter,
-    #[error("Unable to find GPU device!")]
-    NoDevice,
-}
-
-async fn initialize_gpu() -> Result<(wgpu::Device, wgpu::Queue), InitializeError> {
-    static ADAPTER_OPTIONS: wgpu::RequestAdapterOptions = wgpu::RequestAdapterOptions {
-        power_preference: wgpu::PowerPreference::HighPerformance,
-        force_fallback_adapter: false,
-        compatible_surface: None,
-    };
+fn report_entry_points(
+    reflection: &Reflection,
+    adapter: &wgpu::Adapter,
+    limits: &wgpu::Limits,
+    matches: &cli::Matches,
+) {
+    for usage in reflection.memory_usage() {
+        if matches.is_present("memory-report") {
+            let [x, y, z] = usage.workgroup_size;
+            eprintln!(
+                "{} ({x}x{y}x{z}): {} of {} bytes of workgroup memory, {} bytes of private memory per invocation",
+                usage.name,
+                usage.workgroup_bytes,
+                limits.max_compute_workgroup_storage_size,
+                usage.private_bytes,
+            );
+        }
 
-    static DEVICE_OPTIONS: wgpu::DeviceDescriptor = wgpu::DeviceDescriptor {
-        label: Some("device"),
-        required_features: wgpu::Features::empty(),
-        required_limits: wgpu::Limits::downlevel_defaults(),
-        memory_hints: wgpu::MemoryHints::Performance,
-        trace: wgpu::Trace::Off,
-    };
+        if matches.is_present("occupancy-report") {
+            let invocations: u32 = usage.workgroup_size.iter().product();
+            match usage.subgroup_occupancy(&adapter.limits()) {
+                Some(occupancy) => eprintln!(
+                    "{}: {invocations} invocations fill {} subgroups of {} at {:.0}% lane utilization, \
+                     prefer workgroup sizes that are a multiple of {}",
+                    usage.name,
+                    occupancy.subgroups_per_workgroup,
+                    occupancy.subgroup_size,
+                    occupancy.lane_utilization * 100.0,
+                    occupancy.subgroup_size,
+                ),
+                None => eprintln!(
+                    "{}: the {} backend does not report subgroup sizes",
+                    usage.name,
+                    adapter.get_info().backend,
+                ),
+            }
+        }
+
+        usage.warn_on_low_occupancy(limits);
+    }
+}
+
+async fn real_main() -> Result<(), Box<dyn Error>> {
+    env_logger::init();
+
+    let matches = cli::parse(std::env::args().skip(1))?;
+    if matches.is_present("help") {
+        print!("{}", cli::help(matches.command));
+        return Ok(());
+    }
+
+    if matches.is_present("cli-schema") {
+        println!("{}", cli::schema());
+        return Ok(());
+    }
+
+    match matches.command.name {
+        "completions" => {
+            let shell = matches.positional(0).expect("shell is required");
+            print!("{}", cli::completions(shell));
+            Ok(())
+        }
+        "journal" => {
+            for entry in Journal::open()?.entries()? {
+                let output_hash = entry.output_hash.as_deref().unwrap_or("-");
+                println!(
+                    "{:>4}  {}  {:<16}  {output_hash:<64}  {}",
+                    entry.id,
+                    entry.timestamp,
+                    entry.status,
+                    entry.args.join(" ")
+                );
+            }
+
+            Ok(())
+        }
+        "list-adapters" => {
+            list_adapters(&matches);
+            Ok(())
+        }
+        "selftest" => selftest::selftest(&matches).await,
+        "replay" => {
+            let id = matches.parse_positional(0)?.expect("id is required");
+            let entry = Journal::open()?.get(id)?;
+
+            let mut args = entry.args.clone();
+            if let Some(output_hash) = &entry.output_hash {
+                args.extend([String::from("--expect-hash"), output_hash.clone()]);
+            }
+
+            let matches = cli::parse(args.clone())?;
+            entry.check_shader(&journal_shader_hash(&matches)?)?;
+
+            eprintln!("Replaying run {id}: {}", args.join(" "));
+            run_journaled(&matches, args).await
+        }
+        _ if matches.is_present("watch") => watch::watch(&matches).await,
+        _ => run_journaled(&matches, std::env::args().skip(1).collect()).await,
+    }
+}
+
+/// Reads the shader at `path`, in the `--lang` language or else the one its extension implies.
+fn read_shader(matches: &cli::Matches, path: &str) -> Result<Shader<'static>, CheckError> {
+    let language = matches.value("lang").and_then(Language::from_name);
+    Shader::read(Path::new(path), language).map_err(|source| CheckError::ReadFile {
+        path: path.to_owned(),
+        source,
+    })
+}
+
+/// The shader to run, read from `--shader` or the built-in one if not given.
+fn shader_source(matches: &cli::Matches) -> Result<Shader<'static>, CheckError> {
+    match matches.value("shader") {
+        Some(path) => read_shader(matches, path),
+        None => Ok(Shader::from(SHADER_SOURCE)),
+    }
+}
+
+/// Reflects `source`, skipping naga's validation checks if it passed them before according to
+/// the artifact cache in `--cache-dir`.
+fn reflect(matches: &cli::Matches, source: &Shader) -> Result<Reflection, ReflectError> {
+    match ArtifactCache::open(matches.value("cache-dir").map(Path::new)) {
+        Ok(cache) => Reflection::with_cache(source, &cache),
+        Err(err) => {
+            log::warn!("Artifact cache disabled: {err}");
+            Reflection::new(source)
+        }
+    }
+}
+
+fn shader_hash(source: &Shader) -> String {
+    let mut hasher = Sha256::default();
+    hasher.update(source.as_bytes());
+    to_hex(&hasher.finish())
+}
+
+/// The SHA-256 a run of `matches` is journaled under: of the shader `shader_source` reads, or
+/// for `compare`, of the hashes of both of its shaders.
+fn journal_shader_hash(matches: &cli::Matches) -> Result<String, CheckError> {
+    if matches.command.name != "compare" {
+        return Ok(shader_hash(&shader_source(matches)?));
+    }
+
+    let mut hasher = Sha256::default();
+    for index in 0..2 {
+        let path = matches.positional(index).expect("paths are required");
+        hasher.update(shader_hash(&read_shader(matches, path)?).as_bytes());
+    }
+
+    Ok(to_hex(&hasher.finish()))
+}
 
-    let gpu = wgpu::Instance::new(&wgpu::InstanceDescriptor::from_env_or_default());
-    let Ok(adapter) = gpu.request_adapter(&ADAPTER_OPTIONS).await else {
-        return Err(InitializeError::NoAdapter);
+/// Runs `matches`, then records the run and its outcome in the journal under `args`.
+///
+/// Only `run` has a single output to record the hash of, so `bench`, `compare`, and `sweep` are
+/// recorded with their status alone.
+async fn run_journaled(matches: &cli::Matches, args: Vec<String>) -> Result<(), Box<dyn Error>> {
+    let shader_hash = journal_shader_hash(matches)?;
+    let result = match matches.command.name {
+        "bench" => bench::bench(matches).await.map(|()| None),
+        "compare" => compare(matches).await.map(|()| None),
+        "sweep" => sweep::sweep(matches).await.map(|()| None),
+        _ => match run_context(matches).await {
+            Ok(gpu) => run(matches, &gpu, &shader_source(matches)?).await,
+            Err(err) => Err(err),
+        },
     };
+    let (status, output_hash) = match &result {
+        Ok(output_hash) => (ExitStatus::Success, output_hash.clone()),
+        Err(err) => {
+            let output_hash = match err.downcast_ref() {
+                Some(CheckError::HashMismatch { actual, .. }) => Some(actual.clone()),
+                _ => None,
+            };
 
-    let Ok((device, queue)) = adapter.request_device(&DEVICE_OPTIONS).await else {
-        return Err(InitializeError::NoDevice);
+            (ExitStatus::classify(&**err), output_hash)
+        }
     };
 
-    Ok((device, queue))
+    if let Err(err) = Journal::open()
+        .and_then(|journal| journal.record(shader_hash, status.name(), output_hash, args))
+    {
+        log::warn!("Unable to record the run in the journal: {err}");
+    }
+
+    result.map(drop)
+}
+
+fn math_profile(matches: &cli::Matches) -> Result<MathProfile, cli::CliError> {
+    matches.check_conflict("strict-math", "fast-math")?;
+    Ok(if matches.is_present("fast-math") {
+        MathProfile::Fast
+    } else {
+        MathProfile::Strict
+    })
+}
+
+/// The size of the working buffer read back as output, from `--output-size`.
+fn output_size(matches: &cli::Matches) -> Result<u64, cli::CliError> {
+    Ok(matches.parse_value("output-size")?.unwrap_or(OUTPUT_SIZE))
+}
+
+/// The adapter to run on, from `--backend` and `--adapter`.
+fn adapter_selection(matches: &cli::Matches) -> Result<AdapterSelection, cli::CliError> {
+    Ok(AdapterSelection {
+        backends: matches
+            .value("backend")
+            .map(wgpu::Backends::from_comma_list),
+        adapter: matches.parse_value("adapter")?,
+    })
+}
+
+/// Prints every adapter on the `--backend` backends, numbered for `--adapter`.
+fn list_adapters(matches: &cli::Matches) {
+    let backends = matches
+        .value("backend")
+        .map(wgpu::Backends::from_comma_list);
+    let adapters = gpu_scratch::adapters(backends);
+    if adapters.is_empty() {
+        eprintln!("No adapters found");
+    }
+
+    for (index, info) in adapters.iter().enumerate() {
+        println!(
+            "{index:>3}  {:<8} {:<14} {}",
+            format!("{:?}", info.backend),
+            format!("{:?}", info.device_type),
+            info.name
+        );
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
-pub enum RunError {}
+pub enum CheckError {
+    #[error("Output hash {actual} does not match the expected {expected}")]
+    HashMismatch { expected: String, actual: String },
+    #[error("Unable to read {path}: {source}")]
+    ReadFile {
+        path: String,
+        source: std::io::Error,
+    },
+    #[error("Unable to write {path}: {source}")]
+    WriteFile {
+        path: String,
+        source: std::io::Error,
+    },
+    #[error("Unable to connect to {address}: {source}")]
+    Connect {
+        address: String,
+        source: std::io::Error,
+    },
+    #[error("{differing} of {total} output words differ beyond the tolerance")]
+    OutputsDiffer { differing: usize, total: usize },
+    #[error("{diverged} of {iterations} iterations produced a different output to the warm-up run")]
+    Diverged { diverged: usize, iterations: usize },
+    #[error("No GPU hwmon sensor reports energy or power, so `--energy` cannot measure it")]
+    NoPowerSensor,
+    #[error("{failed} of {total} self-test checks failed")]
+    SelfTestFailed { failed: usize, total: usize },
+}
 
-/// Runs the `src/main.wgsl` shader on the GPU, copying the output to `output`.
+/// Reads the contents of every `--input` file, in order.
 ///
-/// This function
-/// 1. Creates an intermediate working buffer.
-/// 2. Compiles the shader into a module.
-/// 3. Creates a BindGroupLayout describing the working buffer.
-/// 4. Creates a ComputePipelineLayout containing the BindGroupLayout
-/// 5. Creates a CommandEncoder.
-/// 6. Creates a ComputePipeline that contains the shader module following the ComputePipelineLayout.
-/// 7. Creates a BindGroup that following the BindGroupLayout.
-/// 8. Creates a ComputePass with the ComputePipeline and BindGroup.
-/// 9. Encodes a dispatched ComputePass into the CommandEncoder.
-/// 10. Encodes a copy from the intermediate buffer into `output`
-/// 11. Finishes the encode.
-fn construct_compute_shader(device: &wgpu::Device, output: &wgpu::Buffer) -> wgpu::CommandBuffer {
-    const SHADER_OPTIONS: wgpu::ShaderModuleDescriptor = wgpu::ShaderModuleDescriptor {
-        label: Some("shader-main"),
-        source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("main.wgsl"))),
+/// Files ending in `.npy` are read as NumPy arrays, uploading only their data, unless given as
+/// `raw:<path>` to upload the whole file.
+fn read_inputs(matches: &cli::Matches) -> Result<Vec<Vec<u8>>, Box<dyn Error>> {
+    let read = |input: &str| -> Result<Vec<u8>, Box<dyn Error>> {
+        if let Some(path) = input.strip_prefix("raw:") {
+            return read_raw(path);
+        }
+
+        if Path::new(input)
+            .extension()
+            .is_some_and(|extension| extension == "npy")
+        {
+            return Ok(npy::read(input)?);
+        }
+
+        read_raw(input)
     };
 
-    static ENCODER_OPTIONS: wgpu::CommandEncoderDescriptor = wgpu::CommandEncoderDescriptor {
-        label: Some("encoder"),
+    matches.values("input").map(read).collect()
+}
+
+fn read_raw(path: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let contents = std::fs::read(path).map_err(|source| CheckError::ReadFile {
+        path: path.to_owned(),
+        source,
+    })?;
+
+    Ok(contents)
+}
+
+/// A `--param` value, like `u32:64` or `f32:0.5`, as the word uploaded for it.
+struct Param(u32);
+
+impl FromStr for Param {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (name, value) = value
+            .split_once(':')
+            .ok_or("expected `<type>:<value>`, like `u32:64`")?;
+        if !ScalarType::NAMES.contains(&name) {
+            return Err(format!("unknown type `{name}`, expected u32, i32, or f32"));
+        }
+
+        ScalarType::from_name(name).encode(value).map(Self)
+    }
+}
+
+/// Reads every `--param` into the layout of a uniform struct with one field per param.
+fn read_params(matches: &cli::Matches) -> Result<Option<Vec<u8>>, cli::CliError> {
+    read_words(matches, "param")
+}
+
+/// Reads every `--push` into the layout of a push constant struct with one field per value.
+fn read_push_constants(matches: &cli::Matches) -> Result<Option<Vec<u8>>, cli::CliError> {
+    read_words(matches, "push")
+}
+
+/// Reads every value of the flag `name`, which are written like `--param` values, as
+/// consecutive words.
+fn read_words(matches: &cli::Matches, name: &str) -> Result<Option<Vec<u8>>, cli::CliError> {
+    let params: Vec<Param> = matches.parse_values(name)?;
+    let words: Vec<u32> = params.into_iter().map(|Param(word)| word).collect();
+    Ok((!words.is_empty()).then(|| bytemuck::cast_slice(&words).to_vec()))
+}
+
+/// A `--const` value, like `WORKGROUP_SIZE=256`.
+struct Constant {
+    name: String,
+    value: f64,
+}
+
+impl FromStr for Constant {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (name, value) = value
+            .split_once('=')
+            .ok_or("expected `<name>=<value>`, like `WORKGROUP_SIZE=256`")?;
+
+        Ok(Self {
+            name: name.to_owned(),
+            value: value.trim().parse().map_err(|err| format!("{err}"))?,
+        })
+    }
+}
+
+/// Sets every `--const` on `plan`, which should have its entry point picked first so that its
+/// workgroup size follows the constants.
+fn set_constants(
+    matches: &cli::Matches,
+    reflection: &Reflection,
+    plan: &mut Plan,
+) -> Result<(), Box<dyn Error>> {
+    for Constant { name, value } in matches.parse_values("const")? {
+        plan.set_constant(reflection, &name, value)?;
+    }
+
+    Ok(())
+}
+
+/// The type output words are decoded as, for `--scalar`, `--element-type`, and `--post`.
+#[derive(Clone, Copy)]
+enum ScalarType {
+    U32,
+    I32,
+    F32,
+}
+
+impl ScalarType {
+    const NAMES: &[&str] = &["u32", "i32", "f32"];
+
+    fn from_name(name: &str) -> Self {
+        match name {
+            "u32" => Self::U32,
+            "i32" => Self::I32,
+            "f32" => Self::F32,
+            _ => unreachable!("scalar type should have been validated by the parser"),
+        }
+    }
+
+    fn decode(self, word: u32) -> String {
+        match self {
+            Self::U32 => word.to_string(),
+            Self::I32 => word.cast_signed().to_string(),
+            Self::F32 => f32::from_bits(word).to_string(),
+        }
+    }
+
+    fn to_json(self, word: u32) -> Json {
+        match self {
+            Self::U32 => Json::UInt(u64::from(word)),
+            Self::I32 => Json::Int(i64::from(word.cast_signed())),
+            Self::F32 => Json::Float(f32::from_bits(word)),
+        }
+    }
+
+    fn encode(self, value: &str) -> Result<u32, String> {
+        match self {
+            Self::U32 => value.parse::<u32>().map_err(|err| err.to_string()),
+            Self::I32 => {
+                (value.parse::<i32>().map(i32::cast_unsigned)).map_err(|err| err.to_string())
+            }
+            Self::F32 => (value.parse::<f32>().map(f32::to_bits)).map_err(|err| err.to_string()),
+        }
+    }
+
+    fn to_f64(self, word: u32) -> f64 {
+        match self {
+            Self::U32 => f64::from(word),
+            Self::I32 => f64::from(word.cast_signed()),
+            Self::F32 => f64::from(f32::from_bits(word)),
+        }
+    }
+}
+
+/// Opens `--output-to`, if it was passed, as a TCP connection for `tcp:<host>:<port>` or else a
+/// file.
+fn output_sink(matches: &cli::Matches) -> Result<Option<Box<dyn OutputSink>>, CheckError> {
+    let Some(target) = matches.value("output-to") else {
+        return Ok(None);
     };
 
-    static BIND_GROUP_LAYOUT_OPTIONS: wgpu::BindGroupLayoutDescriptor =
-        wgpu::BindGroupLayoutDescriptor {
-            label: Some("bind-group-layout"),
-            entries: &[wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                count: None,
-                visibility: wgpu::ShaderStages::COMPUTE,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Storage { read_only: false },
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
-                },
-            }],
-        };
+    let sink: Box<dyn OutputSink> = match target.strip_prefix("tcp:") {
+        Some(address) => Box::new(std::net::TcpStream::connect(address).map_err(|source| {
+            CheckError::Connect {
+                address: address.to_owned(),
+                source,
+            }
+        })?),
+        None => {
+            Box::new(
+                std::fs::File::create(target).map_err(|source| CheckError::WriteFile {
+                    path: target.to_owned(),
+                    source,
+                })?,
+            )
+        }
+    };
+
+    Ok(Some(sink))
+}
 
-    let buffer = device.create_buffer(&BufferDescriptor {
-        label: Some("buffer-intermediate"),
-        size: output.size(),
-        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
-        mapped_at_creation: false,
-    });
-
-    let shader = device.create_shader_module(SHADER_OPTIONS);
-    let bind_group_layout = device.create_bind_group_layout(&BIND_GROUP_LAYOUT_OPTIONS);
-    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-        label: Some("pipeline-layout-descriptor"),
-        bind_group_layouts: &[&bind_group_layout],
-        push_constant_ranges: &[],
-    });
-
-    let compute_pipeline_options = wgpu::ComputePipelineDescriptor {
-        label: Some("compile-pipeline"),
-        layout: Some(&pipeline_layout),
-        module: &shader,
-        entry_point: None,
-        compilation_options: wgpu::PipelineCompilationOptions::default(),
-        cache: None,
+/// Writes the plan to `--emit-graph`, if it was passed.
+fn emit_graph(
+    matches: &cli::Matches,
+    plan: &Plan,
+    elapsed: Option<Duration>,
+) -> Result<(), CheckError> {
+    let Some(path) = matches.value("emit-graph") else {
+        return Ok(());
     };
 
-    let bind_group_options = wgpu::BindGroupDescriptor {
-        label: Some("bind-group"),
-        layout: &bind_group_layout,
-        entries: &[wgpu::BindGroupEntry {
-            binding: 0,
-            resource: buffer.as_entire_binding(),
-        }],
+    let graph = plan.graph(elapsed).to_string();
+    std::fs::write(path, graph).map_err(|source| CheckError::WriteFile {
+        path: path.to_owned(),
+        source,
+    })
+}
+
+/// A `wgpu::PipelineCache` loaded from the artifact cache, so repeat runs of the same shader on
+/// the same adapter skip recompiling it.
+struct PersistedPipelineCache {
+    artifact_cache: Option<ArtifactCache>,
+    key: String,
+    cache: Option<wgpu::PipelineCache>,
+}
+
+impl PersistedPipelineCache {
+    /// Loads the pipeline cache for running `source` following `plan`, from `--cache-dir` or the
+    /// default artifact cache, if the device supports pipeline caches.
+    fn open(
+        matches: &cli::Matches,
+        gpu: &GpuContext,
+        source: &Shader,
+        plan: &Plan,
+        profile: MathProfile,
+    ) -> Self {
+        let artifact_cache = ArtifactCache::open(matches.value("cache-dir").map(Path::new))
+            .inspect_err(|err| log::warn!("Artifact cache disabled: {err}"))
+            .ok();
+
+        let entry_point = plan
+            .entry_point
+            .as_ref()
+            .map_or("default", |e| e.name.as_str());
+        let constants: Vec<_> = (plan.constants.iter())
+            .map(|(key, value)| format!("{key}={value}"))
+            .collect();
+        let options = format!(
+            "entry={entry_point};math={};constants={}",
+            profile.name(),
+            constants.join(",")
+        );
+        let key = ArtifactCache::key(source.as_bytes(), &gpu.adapter.get_info(), &options);
+        let cache = gpu
+            .device
+            .features()
+            .contains(wgpu::Features::PIPELINE_CACHE)
+            .then(|| {
+                let data = artifact_cache
+                    .as_ref()
+                    .and_then(|cache| cache.load(PIPELINE_CACHE_KIND, &key));
+
+                // SAFETY: The data was produced by `PipelineCache::get_data` for this adapter,
+                // and `fallback` makes wgpu discard it if it turns out to be invalid.
+                unsafe {
+                    gpu.device
+                        .create_pipeline_cache(&wgpu::PipelineCacheDescriptor {
+                            label: Some("pipeline-cache"),
+                            data: data.as_deref(),
+                            fallback: true,
+                        })
+                }
+            });
+
+        Self {
+            artifact_cache,
+            key,
+            cache,
+        }
+    }
+
+    fn get(&self) -> Option<&wgpu::PipelineCache> {
+        self.cache.as_ref()
+    }
+
+    /// Stores the pipeline cache, now including any pipelines compiled with it.
+    fn save(self) {
+        if let (Some(artifact_cache), Some(data)) = (
+            &self.artifact_cache,
+            self.cache.and_then(|cache| cache.get_data()),
+        ) && let Err(err) = artifact_cache.store(PIPELINE_CACHE_KIND, &self.key, &data)
+        {
+            log::warn!("Unable to store pipeline cache: {err}");
+        }
+    }
+}
+
+/// Creates the context to run on, waiting for the GPU following `--poll` and streaming its
+/// `--events` if asked to.
+async fn run_context(matches: &cli::Matches) -> Result<GpuContext, Box<dyn Error>> {
+    let mut gpu = GpuContext::with_selection(&adapter_selection(matches)?).await?;
+    if let Some(strategy) = matches.value("poll").and_then(PollStrategy::from_name) {
+        gpu.set_poll_strategy(strategy);
+    }
+
+    events::stream(matches, &mut gpu)?;
+    Ok(gpu)
+}
+
+/// Runs the shader `source` on `gpu`, returning the SHA-256 of its output unless it was a dry
+/// run.
+async fn run(
+    matches: &cli::Matches,
+    gpu: &GpuContext,
+    source: &Shader<'_>,
+) -> Result<Option<String>, Box<dyn Error>> {
+    let profile = math_profile(matches)?;
+    let post_expressions = matches
+        .values("post")
+        .map(PostExpression::parse)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let reflection = reflect(matches, source)?;
+    reflection.warn_on_unwritten_storage();
+    report_entry_points(&reflection, &gpu.adapter, &gpu.device.limits(), matches);
+
+    matches.check_conflict("scalar", "write-mesh")?;
+    matches.check_conflict("scalar", "output-size")?;
+    matches.check_conflict("write-mesh", "output-size")?;
+    matches.check_requires("write-mesh", "mesh")?;
+    let mesh = matches.value("mesh").map(Mesh::read).transpose()?;
+
+    matches.check_conflict("scalar", "element-type")?;
+    let scalar = matches.value("scalar").map(ScalarType::from_name);
+    let element_type = matches
+        .value("element-type")
+        .map_or(ScalarType::U32, ScalarType::from_name);
+    let output_format = match (matches.value("output-format"), matches.value("output-file")) {
+        (Some(name), _) => OutputFormat::from_name(name),
+        (None, Some(path)) => OutputFormat::from_path(Path::new(path)),
+        (None, None) => OutputFormat::Debug,
+    };
+    let output_size = match (scalar, &mesh) {
+        (Some(_), _) => size_of::<u32>() as u64,
+        (None, Some(mesh)) if matches.is_present("write-mesh") => {
+            mesh.position_bytes().len() as u64
+        }
+        (None, _) => output_size(matches)?,
     };
 
-    let mut encoder = device.create_command_encoder(&ENCODER_OPTIONS);
-    {
-        let compute_pipeline = device.create_compute_pipeline(&compute_pipeline_options);
-        let bind_group = device.create_bind_group(&bind_group_options);
+    let mut plan = Plan::new(&reflection, output_size);
+    plan.set_entry_point(&reflection, matches.value("entry-point"))?;
+    set_constants(matches, &reflection, &mut plan)?;
+    plan.init = matches.parse_value("init")?;
+    if let Some(mesh) = &mesh {
+        plan.add_input(&reflection, mesh.position_bytes());
+        plan.add_input(&reflection, mesh.index_bytes());
+    }
+    for contents in read_inputs(matches)? {
+        plan.add_input(&reflection, contents);
+    }
+    if let Some(params) = read_params(matches)? {
+        plan.set_params_bytes(&reflection, params);
+    }
+    if let Some(push_constants) = read_push_constants(matches)? {
+        plan.set_push_constants(&reflection, push_constants)?;
+    }
+    plan.bind_remaining(&reflection)?;
+    plan.dispatch = matches
+        .parse_value("dispatch")?
+        .unwrap_or(DispatchSize::ONE);
+    plan.slice_workgroups = matches
+        .parse_value::<NonZeroU32>("time-slice")?
+        .map(NonZeroU32::get);
+    plan.readback_chunk = matches.parse_value("readback-chunk")?;
+    plan.zero_copy = gpu.supports_zero_copy() && plan.readback_chunk.is_none();
+    if matches.is_present("explain") || matches.is_present("dry-run") {
+        eprint!("{plan}");
+    }
+
+    let budget = Budget {
+        max_invocations: matches.parse_value("max-invocations")?,
+        max_vram_bytes: matches.parse_value("max-vram")?,
+    };
 
-        let mut pass = encoder.begin_compute_pass(&ComputePassDescriptor::default());
-        pass.set_pipeline(&compute_pipeline);
-        pass.set_bind_group(0, &bind_group, &[]);
-        pass.dispatch_workgroups(1, 1, 1);
+    plan.check(&gpu.device.limits(), &budget)?;
+    if matches.is_present("dry-run") {
+        emit_graph(matches, &plan, None)?;
+        return Ok(None);
+    }
+
+    let mut sink = output_sink(matches)?;
+
+    let pipeline_cache = PersistedPipelineCache::open(matches, gpu, source, &plan, profile);
+    let shader_run = gpu.run_shader(source, &plan, profile, pipeline_cache.get())?;
+    emit_graph(matches, &plan, Some(shader_run.elapsed))?;
+    if let Some(gpu_elapsed) = shader_run.gpu_elapsed {
+        eprintln!(
+            "GPU time: {:.3} ms in compute passes, {:.3} ms from submission",
+            gpu_elapsed.as_secs_f64() * 1000.0,
+            shader_run.elapsed.as_secs_f64() * 1000.0
+        );
+    }
+    if let (Some(telemetry), Some(counters)) = (&plan.telemetry, shader_run.telemetry.last()) {
+        let counters: Vec<_> = (telemetry.counters.iter().zip(counters))
+            .map(|(name, value)| format!("{name} = {value}"))
+            .collect();
+        eprintln!("Telemetry: {}", counters.join(", "));
     }
 
-    encoder.copy_buffer_to_buffer(&buffer, 0, output, 0, output.size());
-    encoder.finish()
+    pipeline_cache.save();
+
+    // Chunked output only needs keeping whole if something decodes it.
+    let decoded = scalar.is_some()
+        || [
+            "output-format",
+            "output-file",
+            "post",
+            "summarize",
+            "write-mesh",
+        ]
+        .iter()
+        .any(|long| matches.is_present(long));
+    let mut hasher = Sha256::default();
+    let mut size = 0;
+    let words: Option<Vec<u32>> = match plan.readback_chunk {
+        // Stream each chunk to `--output-to` as it arrives, rather than once all are read.
+        Some(chunk_size) => {
+            let mut words = Vec::new();
+            let mut sink = sink.take();
+            let mut collect = |chunk: &[u8]| {
+                hasher.update(chunk);
+                size += chunk.len();
+                if decoded {
+                    words.extend(bytemuck::pod_collect_to_vec::<u8, u32>(chunk));
+                }
+
+                sink.as_mut()
+                    .map_or(Ok(()), |sink| sink.write_output(chunk))
+            };
+
+            gpu.read_chunked(&shader_run.output, chunk_size, &mut collect)
+                .await?;
+            decoded.then_some(words)
+        }
+        None => {
+            let words = gpu.read_back_async(&shader_run.output).await?;
+            hasher.update(bytemuck::cast_slice(&words));
+            size = words.len() * size_of::<u32>();
+            Some(words)
+        }
+    };
+    gpu.recycle(shader_run);
+
+    if let Some(words) = &words {
+        let data: &[u8] = bytemuck::cast_slice(words);
+        let element = scalar.unwrap_or(element_type);
+        output::print(
+            matches.value("output-file"),
+            output_format,
+            words,
+            scalar,
+            element,
+        )?;
+
+        if let Some(sink) = &mut sink {
+            sink.write_output(data).map_err(RunError::Sink)?;
+        }
+
+        if let (Some(mesh), Some(path)) = (&mesh, matches.value("write-mesh")) {
+            mesh.with_positions(path, data)?.write(path)?;
+            eprintln!("Wrote {} vertices to {path}", mesh.positions.len());
+        }
+
+        let values: Vec<f64> = words.iter().map(|&word| element.to_f64(word)).collect();
+        if matches.is_present("summarize") {
+            eprint!("{}", summary::summarize(&values));
+        }
+        for expression in &post_expressions {
+            println!("{expression} = {}", expression.evaluate(&values));
+        }
+    }
+
+    let hash = to_hex(&hasher.finish());
+    eprintln!("Output: {size} bytes, sha256 {hash}");
+
+    if let Some(expected) = matches.value("expect-hash")
+        && !expected.eq_ignore_ascii_case(&hash)
+    {
+        return Err(CheckError::HashMismatch {
+            expected: expected.to_owned(),
+            actual: hash,
+        }
+        .into());
+    }
+
+    Ok(Some(hash))
 }
 
-async fn real_main() -> Result<(), Box<dyn Error>> {
-    env_logger::init();
+/// The number of times `compare` dispatches each shader by default.
+const DEFAULT_COMPARE_REPEAT: u32 = 5;
 
-    let (device, queue) = initialize_gpu().await?;
+/// The number of output words listed when two shaders' outputs differ.
+const MAX_LISTED_DIFFERENCES: usize = 8;
 
-    let output = device.create_buffer(&BufferDescriptor {
-        label: Some("output-buffer"),
-        size: (12 * size_of::<u32>()) as u64,
-        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
-        mapped_at_creation: false,
-    });
+/// Parses the value of `--long` as a tolerance, which must not be negative, or 0 if not given.
+fn parse_tolerance(matches: &cli::Matches, long: &str) -> Result<f64, cli::CliError> {
+    let tolerance: f64 = matches.parse_value(long)?.unwrap_or(0.0);
+    if tolerance.is_nan() || tolerance < 0.0 {
+        return Err(cli::CliError::Unparsable {
+            name: format!("`--{long}`"),
+            value: tolerance.to_string(),
+            reason: String::from("must be a number that is not negative"),
+        });
+    }
 
-    let index = queue.submit(std::iter::once(construct_compute_shader(&device, &output)));
+    Ok(tolerance)
+}
 
-    device.poll(wgpu::PollType::WaitForSubmissionIndex(index))?;
-    log::info!("GPU Completed");
+/// Runs the shaders at the two positional paths on the same input, failing if their outputs
+/// differ by more than `--tolerance`, and reports how their GPU times compare.
+///
+/// Output words are decoded as `--element-type` before comparing them, and also match if they
+/// differ by no more than `--relative-tolerance` times the larger of their magnitudes.
+///
+/// On devices without timestamp queries, the wall-clock times from submission are compared
+/// instead.
+async fn compare(matches: &cli::Matches) -> Result<(), Box<dyn Error>> {
+    let profile = math_profile(matches)?;
+    let tolerance = parse_tolerance(matches, "tolerance")?;
+    let relative_tolerance = parse_tolerance(matches, "relative-tolerance")?;
+    let element = matches
+        .value("element-type")
+        .map_or(ScalarType::U32, ScalarType::from_name);
+    let output_size = output_size(matches)?;
+    let init = matches.parse_value("init")?;
+    let dispatch = matches
+        .parse_value("dispatch")?
+        .unwrap_or(DispatchSize::ONE);
+    let repeat: u32 = matches
+        .parse_value("repeat")?
+        .unwrap_or(DEFAULT_COMPARE_REPEAT);
+    if repeat == 0 {
+        return Err(cli::CliError::Unparsable {
+            name: String::from("`--repeat`"),
+            value: repeat.to_string(),
+            reason: String::from("must be at least 1"),
+        }
+        .into());
+    }
+
+    let inputs = read_inputs(matches)?;
+    let params = read_params(matches)?;
+    let gpu = GpuContext::with_selection(&adapter_selection(matches)?).await?;
+    if !(gpu.device.features()).contains(wgpu::Features::TIMESTAMP_QUERY) {
+        eprintln!("The device has no timestamp queries, so comparing wall-clock times instead");
+    }
+    // Every run on the device has the same kind of timing, so they can be compared.
+    let time = |shader_run: &ShaderRun| shader_run.gpu_elapsed.unwrap_or(shader_run.elapsed);
+
+    let run_file = |path: &str| -> Result<(Vec<u32>, Duration), Box<dyn Error>> {
+        let source = read_shader(matches, path)?;
+        let reflection = Reflection::new(&source)?;
+        let mut plan = Plan::new(&reflection, output_size);
+        plan.set_entry_point(&reflection, matches.value("entry-point"))?;
+        set_constants(matches, &reflection, &mut plan)?;
+        plan.init = init;
+        for contents in &inputs {
+            plan.add_input(&reflection, contents.clone());
+        }
+        if let Some(params) = &params {
+            plan.set_params_bytes(&reflection, params.clone());
+        }
+        plan.bind_remaining(&reflection)?;
+        plan.dispatch = dispatch;
+        plan.check(&gpu.device.limits(), &Budget::default())?;
 
-    output.map_async(wgpu::MapMode::Read, .., {
-        let output = output.clone();
-        move |result| {
-            if let Err(err) = result {
-                log::error!("{err}");
-                return;
+        // Compile once, so only the dispatches are timed, then keep the fastest of them.
+        let prepared = gpu.prepare(&source, &plan, profile, None)?;
+        let mut fastest = None;
+        for _ in 0..repeat {
+            let shader_run = gpu.run_prepared(&prepared)?;
+            if fastest
+                .as_ref()
+                .is_none_or(|fastest| time(&shader_run) < time(fastest))
+            {
+                if let Some(slower) = fastest.replace(shader_run) {
+                    gpu.recycle(slower);
+                }
+            } else {
+                gpu.recycle(shader_run);
             }
+        }
 
-            println!("{:?}", &output.get_mapped_range(..)[..]);
+        let fastest = fastest.expect("--repeat is at least 1");
+        let elapsed = time(&fastest);
+        let kind = if fastest.gpu_elapsed.is_some() {
+            "GPU time"
+        } else {
+            "wall-clock time"
+        };
+        let words = gpu.read_back(&fastest.output)?;
+        gpu.recycle(fastest);
+        eprintln!(
+            "{path}: {:.3} ms {kind}, fastest of {repeat}",
+            elapsed.as_secs_f64() * 1000.0
+        );
+        Ok((words, elapsed))
+    };
+
+    let [a_path, b_path] =
+        [0, 1].map(|index| matches.positional(index).expect("paths are required"));
+    let (a, a_elapsed) = run_file(a_path)?;
+    let (b, b_elapsed) = run_file(b_path)?;
+
+    if a_elapsed.is_zero() {
+        eprintln!("{a_path} took no measurable time, so the times cannot be compared");
+    } else {
+        let delta = (b_elapsed.as_secs_f64() / a_elapsed.as_secs_f64() - 1.0) * 100.0;
+        let direction = if delta < 0.0 { "faster" } else { "slower" };
+        eprintln!("{b_path} is {:.1}% {direction} than {a_path}", delta.abs());
+    }
+
+    let matching = |a: u32, b: u32| {
+        let (a, b) = (element.to_f64(a), element.to_f64(b));
+        let difference = (a - b).abs();
+        difference <= tolerance || difference <= relative_tolerance * a.abs().max(b.abs())
+    };
+    let differences: Vec<_> = (a.iter().zip(&b).enumerate())
+        .filter(|&(_, (&a, &b))| a != b && !matching(a, b))
+        .collect();
+
+    for &(index, (&a, &b)) in differences.iter().take(MAX_LISTED_DIFFERENCES) {
+        println!(
+            "word {index}: {} != {}",
+            element.decode(a),
+            element.decode(b)
+        );
+    }
+
+    if !differences.is_empty() {
+        return Err(CheckError::OutputsDiffer {
+            differing: differences.len(),
+            total: a.len(),
         }
-    });
+        .into());
+    }
 
-    device.poll(wgpu::PollType::Wait)?;
+    println!("Outputs match on all {} words", a.len());
     Ok(())
 }
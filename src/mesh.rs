@@ -0,0 +1,531 @@
+//! `--mesh` and `--write-mesh`, which load triangle meshes into storage buffers and write the
+//! shader's output back out as a mesh.
+//!
+//! Meshes are read from Wavefront OBJ or PLY files, picked by extension, keeping only vertex
+//! positions and faces, which are triangulated as fans. They are uploaded as two inputs: the
+//! positions as tightly packed `f32` triples, then the faces as `u32` vertex indices, three per
+//! triangle, so a shader declares both as flat arrays.
+
+use std::{fmt::Write as _, path::Path};
+
+#[derive(Debug, thiserror::Error)]
+pub enum MeshError {
+    #[error("Unable to access mesh {path}: {source}")]
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+    #[error("Unable to parse mesh {path}: {reason}")]
+    Parse { path: String, reason: String },
+    #[error("Unknown mesh format for {0}, expected a .obj or .ply file")]
+    UnknownFormat(String),
+    #[error(
+        "The output is {size} bytes, which is not a whole number of vertex positions to write to {path}"
+    )]
+    OutputSize { path: String, size: usize },
+}
+
+enum Format {
+    Obj,
+    Ply,
+}
+
+impl Format {
+    fn of(path: &str) -> Result<Self, MeshError> {
+        let extension = Path::new(path).extension().and_then(|e| e.to_str());
+        match extension.map(str::to_ascii_lowercase).as_deref() {
+            Some("obj") => Ok(Self::Obj),
+            Some("ply") => Ok(Self::Ply),
+            _ => Err(MeshError::UnknownFormat(path.to_owned())),
+        }
+    }
+}
+
+pub struct Mesh {
+    pub positions: Vec<[f32; 3]>,
+    /// Triangles, as indices into `positions`.
+    pub faces: Vec<[u32; 3]>,
+}
+
+/// Splits `polygon` into a fan of triangles around its first vertex.
+fn triangulate(polygon: &[u32], faces: &mut Vec<[u32; 3]>) {
+    if let Some((&first, rest)) = polygon.split_first() {
+        faces.extend(rest.windows(2).map(|edge| [first, edge[0], edge[1]]));
+    }
+}
+
+impl Mesh {
+    pub fn read(path: &str) -> Result<Self, MeshError> {
+        let format = Format::of(path)?;
+        let data = std::fs::read(path).map_err(|source| MeshError::Io {
+            path: path.to_owned(),
+            source,
+        })?;
+
+        let mesh = match format {
+            Format::Obj => Self::parse_obj(&String::from_utf8_lossy(&data)),
+            Format::Ply => Self::parse_ply(&data),
+        };
+
+        let mesh = mesh.map_err(|reason| MeshError::Parse {
+            path: path.to_owned(),
+            reason,
+        })?;
+        mesh.check_indices().map_err(|reason| MeshError::Parse {
+            path: path.to_owned(),
+            reason,
+        })?;
+
+        log::info!(
+            "Loaded {} vertices and {} triangles from {path}",
+            mesh.positions.len(),
+            mesh.faces.len()
+        );
+        Ok(mesh)
+    }
+
+    fn check_indices(&self) -> Result<(), String> {
+        let count = self.positions.len();
+        match self.faces.iter().flatten().find(|&&i| i as usize >= count) {
+            Some(index) => Err(format!(
+                "a face refers to vertex {index}, but there are only {count}"
+            )),
+            None => Ok(()),
+        }
+    }
+
+    fn parse_obj(text: &str) -> Result<Self, String> {
+        let mut positions = Vec::new();
+        let mut faces = Vec::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let error = |reason: &str| format!("line {}: {reason}", number + 1);
+            let mut fields = line.split_whitespace();
+            match fields.next() {
+                Some("v") => {
+                    let mut position = [0.0; 3];
+                    for coordinate in &mut position {
+                        let field = fields.next().ok_or_else(|| error("expected x, y, and z"))?;
+                        *coordinate = field.parse().map_err(|_| error("expected a number"))?;
+                    }
+                    positions.push(position);
+                }
+                Some("f") => {
+                    let polygon = fields
+                        .map(|vertex| {
+                            // Only the position index of `v/vt/vn` is used.
+                            let index = vertex.split('/').next().unwrap_or_default();
+                            let index: i64 = index
+                                .parse()
+                                .map_err(|_| error("expected a vertex index"))?;
+
+                            // Negative indices count back from the latest vertex.
+                            let index = match index {
+                                1.. => index - 1,
+                                ..0 => positions.len() as i64 + index,
+                                0 => return Err(error("vertex indices start at 1")),
+                            };
+                            u32::try_from(index).map_err(|_| error("vertex index out of range"))
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+
+                    triangulate(&polygon, &mut faces);
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Self { positions, faces })
+    }
+
+    fn parse_ply(data: &[u8]) -> Result<Self, String> {
+        const END_HEADER: &[u8] = b"end_header\n";
+        let header_end = (data.windows(END_HEADER.len()))
+            .position(|window| window == END_HEADER)
+            .ok_or("missing `end_header`")?;
+        let header = String::from_utf8_lossy(&data[..header_end]);
+        let body = &data[header_end + END_HEADER.len()..];
+
+        let mut lines = header.lines();
+        if lines.next() != Some("ply") {
+            return Err(String::from("missing the `ply` magic number"));
+        }
+
+        let mut encoding = None;
+        let mut elements: Vec<PlyElement> = Vec::new();
+        for line in lines {
+            let fields: Vec<_> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                ["format", format, _] => {
+                    encoding = Some(match *format {
+                        "ascii" => PlyEncoding::Ascii,
+                        "binary_little_endian" => PlyEncoding::LittleEndian,
+                        "binary_big_endian" => PlyEncoding::BigEndian,
+                        _ => return Err(format!("unknown format `{format}`")),
+                    });
+                }
+                ["element", name, count] => elements.push(PlyElement {
+                    name: String::from(*name),
+                    count: count
+                        .parse()
+                        .map_err(|_| format!("invalid count for `{name}`"))?,
+                    properties: Vec::new(),
+                }),
+                ["property", "list", count, item, name] => {
+                    let element = elements.last_mut().ok_or("property before any element")?;
+                    element.properties.push(PlyProperty {
+                        name: String::from(*name),
+                        kind: PlyKind::List(PlyScalar::parse(count)?, PlyScalar::parse(item)?),
+                    });
+                }
+                ["property", scalar, name] => {
+                    let element = elements.last_mut().ok_or("property before any element")?;
+                    element.properties.push(PlyProperty {
+                        name: String::from(*name),
+                        kind: PlyKind::Scalar(PlyScalar::parse(scalar)?),
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        let mut reader = PlyReader {
+            encoding: encoding.ok_or("missing `format`")?,
+            data: body,
+        };
+
+        let mut positions = Vec::new();
+        let mut faces = Vec::new();
+        for element in &elements {
+            let position_of = |axis| element.properties.iter().position(|p| p.name == axis);
+            let axes = [position_of("x"), position_of("y"), position_of("z")];
+            let indices = (element.properties.iter())
+                .position(|p| p.name == "vertex_indices" || p.name == "vertex_index");
+
+            for _ in 0..element.count {
+                let values = (element.properties.iter())
+                    .map(|property| reader.read(&property.kind))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                if element.name == "vertex" {
+                    let mut position = [0.0; 3];
+                    for (coordinate, axis) in position.iter_mut().zip(axes) {
+                        let axis = axis.ok_or("vertices need x, y, and z properties")?;
+                        *coordinate = values[axis][0] as f32;
+                    }
+                    positions.push(position);
+                } else if element.name == "face"
+                    && let Some(indices) = indices
+                {
+                    let polygon: Vec<_> = values[indices].iter().map(|&i| i as u32).collect();
+                    triangulate(&polygon, &mut faces);
+                }
+            }
+        }
+
+        Ok(Self { positions, faces })
+    }
+
+    /// The positions as tightly packed `f32` triples.
+    pub fn position_bytes(&self) -> Vec<u8> {
+        bytemuck::cast_slice(&self.positions).to_vec()
+    }
+
+    /// The faces as `u32` vertex indices, three per triangle.
+    pub fn index_bytes(&self) -> Vec<u8> {
+        bytemuck::cast_slice(&self.faces).to_vec()
+    }
+
+    /// This mesh's faces, with `output` as the positions of its vertices.
+    pub fn with_positions(&self, path: &str, output: &[u8]) -> Result<Self, MeshError> {
+        let size = size_of::<[f32; 3]>();
+        if !output.len().is_multiple_of(size) {
+            return Err(MeshError::OutputSize {
+                path: path.to_owned(),
+                size: output.len(),
+            });
+        }
+
+        Ok(Self {
+            positions: bytemuck::pod_collect_to_vec(output),
+            faces: self.faces.clone(),
+        })
+    }
+
+    pub fn write(&self, path: &str) -> Result<(), MeshError> {
+        let mut text = String::new();
+        match Format::of(path)? {
+            Format::Obj => {
+                for [x, y, z] in &self.positions {
+                    let _ = writeln!(text, "v {x} {y} {z}");
+                }
+                for [a, b, c] in &self.faces {
+                    let _ = writeln!(text, "f {} {} {}", a + 1, b + 1, c + 1);
+                }
+            }
+            Format::Ply => {
+                let _ = write!(
+                    text,
+                    "ply\nformat ascii 1.0\nelement vertex {}\nproperty float x\nproperty float y\n\
+                     property float z\nelement face {}\nproperty list uchar uint vertex_indices\n\
+                     end_header\n",
+                    self.positions.len(),
+                    self.faces.len()
+                );
+                for [x, y, z] in &self.positions {
+                    let _ = writeln!(text, "{x} {y} {z}");
+                }
+                for [a, b, c] in &self.faces {
+                    let _ = writeln!(text, "3 {a} {b} {c}");
+                }
+            }
+        }
+
+        std::fs::write(path, text).map_err(|source| MeshError::Io {
+            path: path.to_owned(),
+            source,
+        })
+    }
+}
+
+struct PlyElement {
+    name: String,
+    count: usize,
+    properties: Vec<PlyProperty>,
+}
+
+struct PlyProperty {
+    name: String,
+    kind: PlyKind,
+}
+
+enum PlyKind {
+    Scalar(PlyScalar),
+    /// A list of items, prefixed by its length.
+    List(PlyScalar, PlyScalar),
+}
+
+#[derive(Clone, Copy)]
+enum PlyScalar {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl PlyScalar {
+    fn parse(name: &str) -> Result<Self, String> {
+        Ok(match name {
+            "char" | "int8" => Self::I8,
+            "uchar" | "uint8" => Self::U8,
+            "short" | "int16" => Self::I16,
+            "ushort" | "uint16" => Self::U16,
+            "int" | "int32" => Self::I32,
+            "uint" | "uint32" => Self::U32,
+            "float" | "float32" => Self::F32,
+            "double" | "float64" => Self::F64,
+            _ => return Err(format!("unknown property type `{name}`")),
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Self::I8 | Self::U8 => 1,
+            Self::I16 | Self::U16 => 2,
+            Self::I32 | Self::U32 | Self::F32 => 4,
+            Self::F64 => 8,
+        }
+    }
+}
+
+enum PlyEncoding {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+/// Reads property values from the body of a PLY file, after the header.
+struct PlyReader<'a> {
+    encoding: PlyEncoding,
+    data: &'a [u8],
+}
+
+impl PlyReader<'_> {
+    fn read(&mut self, kind: &PlyKind) -> Result<Vec<f64>, String> {
+        match *kind {
+            PlyKind::Scalar(scalar) => Ok(vec![self.scalar(scalar)?]),
+            PlyKind::List(count, item) => {
+                let count = self.scalar(count)? as usize;
+                (0..count).map(|_| self.scalar(item)).collect()
+            }
+        }
+    }
+
+    fn scalar(&mut self, scalar: PlyScalar) -> Result<f64, String> {
+        if let PlyEncoding::Ascii = self.encoding {
+            let start = (self.data.iter())
+                .position(|byte| !byte.is_ascii_whitespace())
+                .ok_or("unexpected end of data")?;
+            let rest = &self.data[start..];
+            let end = (rest.iter())
+                .position(u8::is_ascii_whitespace)
+                .unwrap_or(rest.len());
+
+            let token = String::from_utf8_lossy(&rest[..end]);
+            self.data = &rest[end..];
+            return token
+                .parse()
+                .map_err(|_| format!("invalid value `{token}`"));
+        }
+
+        let size = scalar.size();
+        if self.data.len() < size {
+            return Err(String::from("unexpected end of data"));
+        }
+
+        let (bytes, rest) = self.data.split_at(size);
+        self.data = rest;
+
+        let mut buffer = [0; 8];
+        buffer[..size].copy_from_slice(bytes);
+        if let PlyEncoding::BigEndian = self.encoding {
+            buffer[..size].reverse();
+        }
+
+        let [b0, b1, b2, b3, ..] = buffer;
+        Ok(match scalar {
+            PlyScalar::I8 => f64::from(b0.cast_signed()),
+            PlyScalar::U8 => f64::from(b0),
+            PlyScalar::I16 => f64::from(i16::from_le_bytes([b0, b1])),
+            PlyScalar::U16 => f64::from(u16::from_le_bytes([b0, b1])),
+            PlyScalar::I32 => f64::from(i32::from_le_bytes([b0, b1, b2, b3])),
+            PlyScalar::U32 => f64::from(u32::from_le_bytes([b0, b1, b2, b3])),
+            PlyScalar::F32 => f64::from(f32::from_le_bytes([b0, b1, b2, b3])),
+            PlyScalar::F64 => f64::from_le_bytes(buffer),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Mesh, MeshError};
+
+    #[test]
+    fn obj() {
+        let text = "# A unit square\nv 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0.5\nvn 0 0 1\n\
+                    f 1/1/1 2/2/1 3/3/1 -1//1\n";
+        let mesh = Mesh::parse_obj(text).unwrap();
+        assert_eq!(
+            mesh.positions,
+            [
+                [0.0, 0.0, 0.0],
+                [1.0, 0.0, 0.0],
+                [1.0, 1.0, 0.0],
+                [0.0, 1.0, 0.5]
+            ]
+        );
+        assert_eq!(mesh.faces, [[0, 1, 2], [0, 2, 3]]);
+
+        assert!(Mesh::parse_obj("v 0 0\n").is_err());
+        assert!(Mesh::parse_obj("v 0 0 0\nf 0 1 1\n").is_err());
+        assert!(Mesh::parse_obj("v 0 0 0\nf -2 1 1\n").is_err());
+    }
+
+    #[test]
+    fn ply_ascii() {
+        let data = b"ply\nformat ascii 1.0\ncomment A triangle\nelement vertex 3\n\
+                     property float x\nproperty float y\nproperty float z\nproperty uchar red\n\
+                     element face 1\nproperty list uchar int vertex_indices\nend_header\n\
+                     0 0 0 255\n1 0 0 255\n0 1 0.5 255\n3 0 1 2\n";
+        let mesh = Mesh::parse_ply(data).unwrap();
+        assert_eq!(
+            mesh.positions,
+            [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.5]]
+        );
+        assert_eq!(mesh.faces, [[0, 1, 2]]);
+    }
+
+    #[test]
+    fn ply_binary() {
+        let header = "ply\nformat binary_big_endian 1.0\nelement vertex 2\nproperty double x\n\
+                      property float y\nproperty short z\nelement face 1\n\
+                      property list uchar uint vertex_indices\nend_header\n";
+        let mut data = header.as_bytes().to_vec();
+        for (x, y, z) in [(1.5f64, -2.0f32, 3i16), (0.0, 0.25, -4)] {
+            data.extend(x.to_be_bytes());
+            data.extend(y.to_be_bytes());
+            data.extend(z.to_be_bytes());
+        }
+        data.push(4);
+        for index in [0u32, 1, 0, 1] {
+            data.extend(index.to_be_bytes());
+        }
+
+        let mesh = Mesh::parse_ply(&data).unwrap();
+        assert_eq!(mesh.positions, [[1.5, -2.0, 3.0], [0.0, 0.25, -4.0]]);
+        assert_eq!(mesh.faces, [[0, 1, 0], [0, 0, 1]]);
+
+        data.truncate(data.len() - 1);
+        assert!(Mesh::parse_ply(&data).is_err());
+    }
+
+    #[test]
+    fn check_indices() {
+        let mesh = Mesh {
+            positions: vec![[0.0; 3]; 2],
+            faces: vec![[0, 1, 2]],
+        };
+        assert!(mesh.check_indices().is_err());
+    }
+
+    #[test]
+    fn write_round_trip() {
+        let mesh = Mesh {
+            positions: vec![[0.0, 0.0, 0.0], [1.5, 0.0, -1.0], [0.0, 2.0, 0.25]],
+            faces: vec![[0, 1, 2], [2, 1, 0]],
+        };
+
+        let directory = std::env::temp_dir();
+        for extension in ["obj", "ply"] {
+            let path = directory.join(format!(
+                "gpu-scratch-mesh-{}.{extension}",
+                std::process::id()
+            ));
+            let path = path.to_str().unwrap();
+
+            mesh.write(path).unwrap();
+            let read = Mesh::read(path);
+            let _ = std::fs::remove_file(path);
+
+            let read = read.unwrap();
+            assert_eq!(read.positions, mesh.positions, "{extension}");
+            assert_eq!(read.faces, mesh.faces, "{extension}");
+        }
+
+        assert!(matches!(
+            mesh.write("mesh.stl"),
+            Err(MeshError::UnknownFormat(_))
+        ));
+    }
+
+    #[test]
+    fn with_positions() {
+        let mesh = Mesh {
+            positions: vec![[0.0; 3]],
+            faces: Vec::new(),
+        };
+        let output: Vec<u8> = bytemuck::cast_slice(&[1.0f32, 2.0, 3.0]).to_vec();
+        assert_eq!(
+            mesh.with_positions("a.obj", &output).unwrap().positions,
+            [[1.0, 2.0, 3.0]]
+        );
+        assert!(matches!(
+            mesh.with_positions("a.obj", &output[..8]),
+            Err(MeshError::OutputSize { size: 8, .. })
+        ));
+    }
+}
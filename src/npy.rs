@@ -0,0 +1,330 @@
+//! `--input data.npy`, which uploads the contents of NumPy arrays after checking their header,
+//! and `--output-format npy`, which writes the output as one.
+//!
+//! Only little-endian, C-order arrays of 4 byte elements are read, since those are the only ones
+//! a shader can declare as a storage array: `f32`, `i32`, and `u32`.
+
+use std::{
+    fmt::{self, Display},
+    io::{self, Write},
+};
+
+use crate::ScalarType;
+
+/// The bytes every `.npy` file starts with.
+const MAGIC: &[u8] = b"\x93NUMPY";
+
+/// The alignment NumPy pads the magic, version, header length and header to, so the data that
+/// follows is aligned.
+const HEADER_ALIGN: usize = 64;
+
+/// The NumPy dtypes that can be uploaded, and the WGSL types they match.
+const DTYPES: &[(&str, &str)] = &[("<f4", "f32"), ("<i4", "i32"), ("<u4", "u32")];
+
+#[derive(Debug, thiserror::Error)]
+pub enum NpyError {
+    #[error("Unable to read {path}: {source}")]
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+    #[error("Unable to parse NumPy array {path}: {reason}")]
+    Parse { path: String, reason: String },
+    #[error("{path} holds `{dtype}` elements, but only `<f4`, `<i4`, and `<u4` can be uploaded")]
+    UnsupportedDtype { path: String, dtype: String },
+    #[error("{path} is in Fortran order, but only C order arrays can be uploaded")]
+    FortranOrder { path: String },
+    #[error(
+        "{path} has shape {shape}, so should hold {expected} bytes of data, but holds {actual}"
+    )]
+    DataSize {
+        path: String,
+        shape: Shape,
+        expected: u64,
+        actual: usize,
+    },
+}
+
+/// The shape of an array, displayed like NumPy does.
+#[derive(Debug)]
+pub struct Shape(pub Vec<u64>);
+
+impl Shape {
+    /// The number of elements, or `None` if it overflows.
+    fn elements(&self) -> Option<u64> {
+        (self.0.iter()).try_fold(1u64, |elements, &dimension| elements.checked_mul(dimension))
+    }
+}
+
+impl Display for Shape {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.as_slice() {
+            [single] => write!(f, "({single},)"),
+            dimensions => {
+                let dimensions: Vec<_> = dimensions.iter().map(u64::to_string).collect();
+                write!(f, "({})", dimensions.join(", "))
+            }
+        }
+    }
+}
+
+/// The fields of a `.npy` header, which is a Python dict literal like
+/// `{'descr': '<f4', 'fortran_order': False, 'shape': (3, 4), }`.
+struct Header {
+    descr: String,
+    fortran_order: bool,
+    shape: Shape,
+}
+
+/// The text following `'key':` in `header`.
+fn field<'a>(header: &'a str, key: &str) -> Result<&'a str, String> {
+    let (_, rest) = (header.split_once(&format!("'{key}':")))
+        .ok_or_else(|| format!("the header has no `{key}`"))?;
+
+    Ok(rest.trim_start())
+}
+
+impl Header {
+    fn parse(header: &str) -> Result<Self, String> {
+        let descr = field(header, "descr")?
+            .strip_prefix('\'')
+            .and_then(|descr| descr.split_once('\''))
+            .map(|(descr, _)| descr.to_owned())
+            .ok_or("`descr` is not a string")?;
+
+        let fortran_order = field(header, "fortran_order")?;
+        let fortran_order = if fortran_order.starts_with("True") {
+            true
+        } else if fortran_order.starts_with("False") {
+            false
+        } else {
+            return Err(String::from("`fortran_order` is not a bool"));
+        };
+
+        let shape = field(header, "shape")?
+            .strip_prefix('(')
+            .and_then(|shape| shape.split_once(')'))
+            .map(|(shape, _)| shape)
+            .ok_or("`shape` is not a tuple")?;
+        let shape = (shape.split(','))
+            .map(str::trim)
+            .filter(|dimension| !dimension.is_empty())
+            .map(|dimension| dimension.parse::<u64>().map_err(|err| err.to_string()))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            descr,
+            fortran_order,
+            shape: Shape(shape),
+        })
+    }
+}
+
+/// Splits a `.npy` file into its header text and the array data following it.
+fn split(data: &[u8]) -> Result<(&str, &[u8]), String> {
+    let rest = data
+        .strip_prefix(MAGIC)
+        .ok_or("the file is not a NumPy array")?;
+    let [major, _minor, rest @ ..] = rest else {
+        return Err(String::from("the header is truncated"));
+    };
+
+    // Version 1 has a 2 byte header length, and later versions a 4 byte one.
+    let (header_len, rest) = match (major, rest) {
+        (1, [a, b, rest @ ..]) => (u16::from_le_bytes([*a, *b]) as usize, rest),
+        (2 | 3, [a, b, c, d, rest @ ..]) => (u32::from_le_bytes([*a, *b, *c, *d]) as usize, rest),
+        (1..=3, _) => return Err(String::from("the header is truncated")),
+        (major, _) => return Err(format!("version {major} is not supported")),
+    };
+
+    if rest.len() < header_len {
+        return Err(String::from("the header is truncated"));
+    }
+
+    let (header, data) = rest.split_at(header_len);
+    let header = std::str::from_utf8(header).map_err(|err| err.to_string())?;
+    Ok((header, data))
+}
+
+/// Reads the `.npy` file at `path`, returning its array data once its header has been checked.
+pub fn read(path: &str) -> Result<Vec<u8>, NpyError> {
+    let contents = std::fs::read(path).map_err(|source| NpyError::Io {
+        path: path.to_owned(),
+        source,
+    })?;
+
+    parse(path, &contents)
+}
+
+/// Checks the header of `contents`, read from `path`, and returns the array data following it.
+fn parse(path: &str, contents: &[u8]) -> Result<Vec<u8>, NpyError> {
+    let parse_error = |reason| NpyError::Parse {
+        path: path.to_owned(),
+        reason,
+    };
+
+    let (header, data) = split(contents).map_err(parse_error)?;
+    let header = Header::parse(header).map_err(parse_error)?;
+
+    let Some((_, wgsl_type)) = DTYPES.iter().find(|(dtype, _)| *dtype == header.descr) else {
+        return Err(NpyError::UnsupportedDtype {
+            path: path.to_owned(),
+            dtype: header.descr,
+        });
+    };
+
+    // A one dimensional array is laid out the same in either order.
+    if header.fortran_order && header.shape.0.len() > 1 {
+        return Err(NpyError::FortranOrder {
+            path: path.to_owned(),
+        });
+    }
+
+    let expected = (header.shape.elements())
+        .and_then(|elements| elements.checked_mul(size_of::<u32>() as u64))
+        .ok_or_else(|| parse_error(format!("shape {} is too large", header.shape)))?;
+    if data.len() as u64 != expected {
+        return Err(NpyError::DataSize {
+            path: path.to_owned(),
+            shape: header.shape,
+            expected,
+            actual: data.len(),
+        });
+    }
+
+    log::info!("Read {path} as a {} array of {wgsl_type}", header.shape);
+    Ok(data.to_vec())
+}
+
+/// Writes `words` as a one dimensional `.npy` array of `element`s.
+pub fn write(writer: &mut dyn Write, words: &[u32], element: ScalarType) -> io::Result<()> {
+    let descr = match element {
+        ScalarType::U32 => "<u4",
+        ScalarType::I32 => "<i4",
+        ScalarType::F32 => "<f4",
+    };
+
+    let mut header = format!(
+        "{{'descr': '{descr}', 'fortran_order': False, 'shape': {}, }}",
+        Shape(vec![words.len() as u64])
+    );
+
+    // The magic, the 2 byte version, and the 2 byte header length come first, and the header
+    // ends with a newline.
+    let prefix = MAGIC.len() + 4;
+    let padding = (prefix + header.len() + 1).next_multiple_of(HEADER_ALIGN);
+    header.extend(std::iter::repeat_n(
+        ' ',
+        padding - prefix - header.len() - 1,
+    ));
+    header.push('\n');
+
+    let header_len = u16::try_from(header.len()).expect("the header is under 100 bytes");
+    writer.write_all(MAGIC)?;
+    writer.write_all(&[1, 0])?;
+    writer.write_all(&header_len.to_le_bytes())?;
+    writer.write_all(header.as_bytes())?;
+    writer.write_all(bytemuck::cast_slice(words))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NpyError, parse, write};
+    use crate::ScalarType;
+
+    /// Builds a `.npy` file of format `version` with the header `header` followed by `data`.
+    fn npy(version: u8, header: &str, data: &[u8]) -> Vec<u8> {
+        let mut contents = b"\x93NUMPY".to_vec();
+        contents.extend([version, 0]);
+        match version {
+            1 => contents.extend((header.len() as u16).to_le_bytes()),
+            _ => contents.extend((header.len() as u32).to_le_bytes()),
+        }
+        contents.extend(header.as_bytes());
+        contents.extend(data);
+        contents
+    }
+
+    fn header(descr: &str, fortran_order: bool, shape: &str) -> String {
+        let fortran_order = if fortran_order { "True" } else { "False" };
+        format!("{{'descr': '{descr}', 'fortran_order': {fortran_order}, 'shape': {shape}, }}\n")
+    }
+
+    #[test]
+    fn header_versions() {
+        let data = [1u8, 0, 0, 0, 2, 0, 0, 0];
+        for version in 1..=3 {
+            let contents = npy(version, &header("<u4", false, "(2,)"), &data);
+            assert_eq!(
+                parse("a.npy", &contents).unwrap(),
+                data,
+                "version {version}"
+            );
+        }
+
+        let contents = npy(4, &header("<u4", false, "(2,)"), &data);
+        assert!(matches!(
+            parse("a.npy", &contents),
+            Err(NpyError::Parse { .. })
+        ));
+    }
+
+    #[test]
+    fn fortran_order() {
+        let data = [0; 16];
+        let contents = npy(1, &header("<f4", true, "(2, 2)"), &data);
+        assert!(matches!(
+            parse("a.npy", &contents),
+            Err(NpyError::FortranOrder { .. })
+        ));
+
+        // One dimensional arrays are the same in either order.
+        let contents = npy(1, &header("<f4", true, "(4,)"), &data);
+        assert_eq!(parse("a.npy", &contents).unwrap(), data);
+    }
+
+    #[test]
+    fn size_mismatch() {
+        let contents = npy(1, &header("<i4", false, "(3,)"), &[0; 8]);
+        assert!(matches!(
+            parse("a.npy", &contents),
+            Err(NpyError::DataSize {
+                expected: 12,
+                actual: 8,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn size_overflow() {
+        let shape = format!("({}, 2)", u64::MAX);
+        let contents = npy(1, &header("<u4", false, &shape), &[]);
+        assert!(matches!(
+            parse("a.npy", &contents),
+            Err(NpyError::Parse { .. })
+        ));
+    }
+
+    #[test]
+    fn unsupported_dtype() {
+        let contents = npy(1, &header("<f8", false, "(1,)"), &[0; 8]);
+        assert!(matches!(
+            parse("a.npy", &contents),
+            Err(NpyError::UnsupportedDtype { .. })
+        ));
+    }
+
+    #[test]
+    fn write_round_trip() {
+        let words = [1, 2, 3];
+        let mut contents = Vec::new();
+        write(&mut contents, &words, ScalarType::U32).unwrap();
+
+        assert_eq!(contents.len() % 64, 12);
+        assert_eq!(
+            parse("a.npy", &contents).unwrap(),
+            bytemuck::cast_slice::<u32, u8>(&words)
+        );
+    }
+}
@@ -0,0 +1,123 @@
+//! `--output-format`, which serializes the output words of a run so they can be piped into
+//! other tools.
+
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::Path,
+};
+
+use crate::{CheckError, ScalarType, json::Json, npy};
+
+/// The number of bytes on each line of a hex dump.
+const HEX_LINE_BYTES: usize = 16;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// The output bytes as a Rust debug list, or the `--scalar` value.
+    Debug,
+    /// A JSON array of the output words.
+    Json,
+    /// A CSV table of the index and value of each output word.
+    Csv,
+    /// The raw output bytes.
+    Bin,
+    /// A hex dump of the output bytes, with the offset of each line.
+    Hex,
+    /// A one dimensional NumPy array of the output words.
+    Npy,
+}
+
+impl OutputFormat {
+    pub const NAMES: &[&str] = &["debug", "json", "csv", "bin", "hex", "npy"];
+
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "debug" => Self::Debug,
+            "json" => Self::Json,
+            "csv" => Self::Csv,
+            "bin" => Self::Bin,
+            "hex" => Self::Hex,
+            "npy" => Self::Npy,
+            _ => unreachable!("output format should have been validated by the parser"),
+        }
+    }
+
+    /// The format implied by the extension of `path`, which is [`Self::Debug`] unless it is
+    /// `.json`, `.csv`, `.bin`, or `.npy`.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => Self::Json,
+            Some("csv") => Self::Csv,
+            Some("bin") => Self::Bin,
+            Some("npy") => Self::Npy,
+            _ => Self::Debug,
+        }
+    }
+
+    /// Writes `words` to `writer`, decoding each as `element` for the formats that have types.
+    pub fn write(
+        self,
+        writer: &mut dyn Write,
+        words: &[u32],
+        element: ScalarType,
+    ) -> io::Result<()> {
+        let data: &[u8] = bytemuck::cast_slice(words);
+        match self {
+            Self::Debug => writeln!(writer, "{data:?}"),
+            Self::Json => {
+                let values = words.iter().map(|&word| element.to_json(word)).collect();
+                writeln!(writer, "{}", Json::Array(values))
+            }
+            Self::Csv => {
+                writeln!(writer, "index,value")?;
+                for (index, &word) in words.iter().enumerate() {
+                    writeln!(writer, "{index},{}", element.decode(word))?;
+                }
+
+                Ok(())
+            }
+            Self::Bin => writer.write_all(data),
+            Self::Hex => {
+                for (line, chunk) in data.chunks(HEX_LINE_BYTES).enumerate() {
+                    let bytes: Vec<_> = chunk.iter().map(|byte| format!("{byte:02x}")).collect();
+                    writeln!(writer, "{:08x}  {}", line * HEX_LINE_BYTES, bytes.join(" "))?;
+                }
+
+                Ok(())
+            }
+            Self::Npy => npy::write(writer, words, element),
+        }
+    }
+}
+
+/// Writes `words` as `format` to the file at `path`, or to stdout if `None`, decoding them as
+/// `element`.
+///
+/// In the debug format, a `--scalar` run prints its one word decoded rather than its bytes.
+pub fn print(
+    path: Option<&str>,
+    format: OutputFormat,
+    words: &[u32],
+    scalar: Option<ScalarType>,
+    element: ScalarType,
+) -> Result<(), CheckError> {
+    let write = || {
+        let mut writer: Box<dyn Write> = match path {
+            Some(path) => Box::new(File::create(path)?),
+            None => Box::new(io::stdout().lock()),
+        };
+
+        match (format, scalar) {
+            (OutputFormat::Debug, Some(scalar)) => writeln!(writer, "{}", scalar.decode(words[0]))?,
+            _ => format.write(&mut writer, words, element)?,
+        }
+
+        writer.flush()
+    };
+
+    write().map_err(|source| CheckError::WriteFile {
+        path: path.unwrap_or("stdout").to_owned(),
+        source,
+    })
+}
@@ -0,0 +1,146 @@
+//! Chains several shaders into one submission, with each stage reading what the one before wrote.
+//!
+//! A stage after the first binds the previous stage's working buffer with
+//! [`Plan::add_stage_input`], so intermediate results stay on the GPU without a copy, and only the
+//! last stage's output is read back.
+
+use std::time::{Duration, Instant};
+
+use crate::{
+    ENCODER_OPTIONS, Event, GpuContext, MathProfile, PreparedShader, RunError, ShaderRun,
+    plan::Plan, prepare_compute_shader, scoped, shader::Shader,
+};
+
+struct Stage<'a> {
+    source: Shader<'a>,
+    plan: &'a Plan,
+}
+
+/// An ordered list of shader stages, run in order in one command buffer by
+/// [`GpuContext::run_pipeline`].
+pub struct Pipeline<'a> {
+    stages: Vec<Stage<'a>>,
+}
+
+impl<'a> Pipeline<'a> {
+    /// Starts a pipeline with the shader `source` following `plan` as its first stage.
+    pub fn new(source: impl Into<Shader<'a>>, plan: &'a Plan) -> Self {
+        Self {
+            stages: vec![Stage {
+                source: source.into(),
+                plan,
+            }],
+        }
+    }
+
+    /// Adds the shader `source` following `plan` as the next stage.
+    pub fn then(mut self, source: impl Into<Shader<'a>>, plan: &'a Plan) -> Self {
+        self.stages.push(Stage {
+            source: source.into(),
+            plan,
+        });
+        self
+    }
+}
+
+impl GpuContext {
+    /// Runs every stage of `pipeline` in one submission, and waits for the GPU to finish.
+    ///
+    /// Each shader is compiled following `profile`. The returned output is the last stage's, and
+    /// its timings and telemetry cover every stage.
+    pub fn run_pipeline(
+        &self,
+        pipeline: &Pipeline,
+        profile: MathProfile,
+    ) -> Result<ShaderRun, RunError> {
+        self.emit(Event::CompileStarted);
+        let start = Instant::now();
+        let (encoded, error) = scoped(&self.device, || self.encode_pipeline(pipeline, profile));
+        if let Some(err) = error {
+            return Err(err.into());
+        }
+        let (output, command_buffer, stages) = encoded?;
+        self.emit(Event::CompileFinished {
+            elapsed: start.elapsed(),
+        });
+
+        let start = Instant::now();
+        let submission = self.queue.submit(std::iter::once(command_buffer));
+        self.emit(Event::Submitted { index: 0, count: 1 });
+
+        // wgpu treats polling for a submission that failed validation as fatal, so check first.
+        self.uncaptured_errors.check()?;
+        self.wait_for(submission)?;
+        let elapsed = start.elapsed();
+        self.emit(Event::PassCompleted { index: 0, elapsed });
+        log::info!("GPU Completed");
+
+        let mut telemetry = Vec::new();
+        let mut gpu_elapsed = Some(Duration::ZERO);
+        for (index, prepared) in stages.iter().enumerate() {
+            for readback in &prepared.telemetry_readbacks {
+                let counters: Vec<u32> = self.read_back(readback)?;
+                log::info!("Stage {index} telemetry: {counters:?}");
+                telemetry.push(counters);
+            }
+
+            gpu_elapsed = match (&prepared.timestamps, gpu_elapsed) {
+                (Some(timestamps), Some(total)) => {
+                    Some(total + self.pass_time(&timestamps.readback)?)
+                }
+                _ => None,
+            };
+        }
+
+        self.emit(Event::RunCompleted {
+            elapsed,
+            gpu_elapsed,
+        });
+
+        let last = stages.last().expect("pipelines have at least one stage");
+        Ok(ShaderRun {
+            output,
+            elapsed,
+            gpu_elapsed,
+            telemetry,
+            pooled_output: last.plan.output_buffer(),
+        })
+    }
+
+    /// Prepares every stage of `pipeline`, and encodes them into one command buffer that copies
+    /// the last stage's output into the returned buffer.
+    fn encode_pipeline<'a>(
+        &self,
+        pipeline: &Pipeline<'a>,
+        profile: MathProfile,
+    ) -> Result<(wgpu::Buffer, wgpu::CommandBuffer, Vec<PreparedShader<'a>>), RunError> {
+        let mut stages: Vec<PreparedShader> = Vec::with_capacity(pipeline.stages.len());
+        for stage in &pipeline.stages {
+            let previous = stages.last().map(|prepared| &prepared.buffer);
+            stages.push(prepare_compute_shader(
+                &self.device,
+                &stage.source,
+                stage.plan,
+                previous,
+                profile,
+                None,
+                &self.pool,
+            )?);
+        }
+
+        let last = stages.last().expect("pipelines have at least one stage");
+        let output = last.create_output(&self.device);
+
+        let mut encoder = self.device.create_command_encoder(&ENCODER_OPTIONS);
+        for prepared in &stages {
+            prepared.encode_init(&mut encoder);
+            for (index, (_, workgroups)) in prepared.plan.slices().into_iter().enumerate() {
+                prepared.encode_slice(&mut encoder, index, workgroups);
+            }
+            prepared.encode_timestamps(&mut encoder);
+        }
+
+        last.encode_output(&mut encoder, &output);
+        Ok((output, encoder.finish(), stages))
+    }
+}
@@ -0,0 +1,1376 @@
+//! A description of the GPU work a run performs, resolved before anything is created.
+//!
+//! [`GpuContext::run_shader`](crate::GpuContext::run_shader) builds its resources from the
+//! plan, so what `--explain` prints is exactly what gets submitted.
+
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+    time::Duration,
+};
+
+use crate::{
+    buffer::{BufferRole, BufferSpec, BufferSpecError},
+    reflect::{BindingKind, EntryPointMemory, Reflection},
+};
+
+pub const WORKING_BUFFER_LABEL: &str = "buffer-intermediate";
+pub const OUTPUT_BUFFER_LABEL: &str = "output-buffer";
+pub const STAGING_BUFFER_LABEL: &str = "staging-buffer";
+pub const READBACK_CHUNK_LABEL: &str = "readback-chunk";
+pub const PARAMS_BUFFER_LABEL: &str = "params-buffer";
+pub const SLICE_OFFSET_BUFFER_LABEL: &str = "slice-offset-buffer";
+pub const PUSH_CONSTANT_BUFFER_LABEL: &str = "push-constant-buffer";
+
+pub const TELEMETRY_BUFFER_LABEL: &str = "telemetry-buffer";
+pub const TELEMETRY_READBACK_LABEL: &str = "telemetry-readback";
+pub const TIMESTAMP_RESOLVE_LABEL: &str = "timestamp-resolve-buffer";
+pub const TIMESTAMP_READBACK_LABEL: &str = "timestamp-readback";
+
+/// The name of the `var<storage, read_write>` a shader can write `u32` counters into, which are
+/// read back after every submission.
+pub const TELEMETRY_NAME: &str = "telemetry";
+
+/// The name of the `var<uniform>` a time-sliced shader reads its workgroup offset in x from.
+pub const SLICE_OFFSET_NAME: &str = "slice_offset";
+
+/// The stride between the offsets of successive slices in the slice offset buffer.
+///
+/// This is the largest `min_uniform_buffer_offset_alignment` WebGPU allows, so it is valid on
+/// every device.
+pub const SLICE_OFFSET_STRIDE: u64 = 256;
+
+pub const WORKING_BINDING: naga::ResourceBinding = naga::ResourceBinding {
+    group: 0,
+    binding: 0,
+};
+
+/// Invocation count above which a plan is likely to run for a very long time.
+const INVOCATION_WARN_THRESHOLD: u64 = 1 << 36;
+
+#[derive(Debug, thiserror::Error)]
+pub enum PlanError {
+    #[error(transparent)]
+    Buffer(#[from] BufferSpecError),
+    #[error("The plan needs {required} for {what}, but the device limit is {limit}")]
+    ExceedsLimit {
+        what: &'static str,
+        required: u64,
+        limit: u64,
+    },
+    #[error(
+        "Entry point `{entry_point}` needs {required} for {what}, but the device limit is {limit}"
+    )]
+    EntryPointExceedsLimit {
+        entry_point: String,
+        what: &'static str,
+        required: u64,
+        limit: u64,
+    },
+    #[error(
+        "The output is {size} bytes, over the device's buffer size limit of {limit}: chunked \
+         readback only bounds the staging buffer, so the working buffer must still fit"
+    )]
+    ChunkedOutputExceedsLimit { size: u64, limit: u64 },
+    #[error("The plan needs {required} {what}, over the budget of {budget}")]
+    OverBudget {
+        what: &'static str,
+        required: u64,
+        budget: u64,
+    },
+    #[error("The shader has no compute entry point `{name}`, expected one of: {available}")]
+    UnknownEntryPoint { name: String, available: String },
+    #[error("The shader has several compute entry points, so one must be picked: {available}")]
+    AmbiguousEntryPoint { available: String },
+    #[error("Time slicing needs the shader to declare `var<uniform> {SLICE_OFFSET_NAME}: u32`")]
+    NoSliceOffset,
+    #[error("The shader has no `override` constant `{name}`, expected one of: {available}")]
+    UnknownConstant { name: String, available: String },
+    #[error("{size} bytes of push constants were given, but `{name}` is {declared} bytes")]
+    PushConstantSize {
+        name: String,
+        size: usize,
+        declared: u32,
+    },
+    #[error(
+        "`{name}` at @binding({binding}) is runtime-sized, so it must be bound to an input with data"
+    )]
+    UnsizedBinding { name: String, binding: u32 },
+    #[error("`{name}` at @group({group}) @binding({binding}) cannot be bound: {reason}")]
+    UnsupportedBinding {
+        name: String,
+        group: u32,
+        binding: u32,
+        reason: String,
+    },
+}
+
+/// How the working buffer is initialized before the compute pass.
+#[derive(Clone, Copy)]
+pub enum BufferInit {
+    /// Cleared with `CommandEncoder::clear_buffer`.
+    Zero,
+    /// Each `u32` holds its own index.
+    Iota,
+    /// Each `u32` holds the same value.
+    Fill(u32),
+}
+
+impl BufferInit {
+    /// Returns the initial contents of a `size` byte buffer, or `None` if it is cleared on the GPU.
+    pub fn contents(self, size: u64) -> Option<Vec<u8>> {
+        let words = size / size_of::<u32>() as u64;
+        let contents: Vec<u32> = match self {
+            Self::Zero => return None,
+            Self::Iota => (0..words).map(|index| index as u32).collect(),
+            Self::Fill(value) => (0..words).map(|_| value).collect(),
+        };
+
+        Some(contents.into_iter().flat_map(u32::to_ne_bytes).collect())
+    }
+}
+
+impl FromStr for BufferInit {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.split_once(':') {
+            None if value == "zero" => Ok(Self::Zero),
+            None if value == "iota" => Ok(Self::Iota),
+            Some(("fill", constant)) => constant
+                .parse()
+                .map(Self::Fill)
+                .map_err(|err| format!("invalid fill value: {err}")),
+            _ => Err(String::from("expected `zero`, `iota`, or `fill:<u32>`")),
+        }
+    }
+}
+
+impl Display for BufferInit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Zero => f.write_str("zero"),
+            Self::Iota => f.write_str("iota"),
+            Self::Fill(value) => write!(f, "fill:{value}"),
+        }
+    }
+}
+
+/// The number of workgroups a compute pass dispatches in each dimension.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DispatchSize {
+    pub x: u32,
+    pub y: u32,
+    pub z: u32,
+}
+
+impl DispatchSize {
+    pub const ONE: Self = Self { x: 1, y: 1, z: 1 };
+
+    pub fn to_array(self) -> [u32; 3] {
+        [self.x, self.y, self.z]
+    }
+}
+
+impl FromStr for DispatchSize {
+    type Err = String;
+
+    /// Parses `XxYxZ`, such as `64x1x1`, where omitted trailing dimensions default to 1.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut counts = [1; 3];
+        let mut parts = value.split('x');
+        for (count, part) in counts.iter_mut().zip(parts.by_ref()) {
+            *count = match part.parse() {
+                Ok(0) => return Err(String::from("workgroup counts must be at least 1")),
+                Ok(parsed) => parsed,
+                Err(err) => return Err(format!("invalid workgroup count `{part}`: {err}")),
+            };
+        }
+
+        if parts.next().is_some() {
+            return Err(String::from("expected at most 3 dimensions, like `64x1x1`"));
+        }
+
+        let [x, y, z] = counts;
+        Ok(Self { x, y, z })
+    }
+}
+
+impl Display for DispatchSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}x{}", self.x, self.y, self.z)
+    }
+}
+
+/// Host data uploaded into a storage buffer before the compute pass.
+pub struct Input {
+    pub binding: naga::ResourceBinding,
+    /// Whether the buffer is bound as `var<storage, read>`.
+    pub read_only: bool,
+    pub contents: Vec<u8>,
+}
+
+impl Input {
+    pub fn label(&self) -> String {
+        format!("input-buffer-{}", self.binding.binding)
+    }
+
+    fn access(&self) -> &'static str {
+        if self.read_only { "read" } else { "read_write" }
+    }
+}
+
+/// The working buffer of the previous stage of a [`crate::pipeline::Pipeline`], bound in place of
+/// an input so the shader reads what that stage wrote without a copy.
+#[derive(Clone, Copy)]
+pub struct StageInput {
+    pub binding: naga::ResourceBinding,
+    /// Whether the buffer is bound as `var<storage, read>`.
+    pub read_only: bool,
+    /// The previous stage's output size, in bytes.
+    pub size: u64,
+}
+
+/// Host data uploaded into the uniform buffer the shader declares, for passing parameters.
+pub struct Params {
+    pub binding: naga::ResourceBinding,
+    pub contents: Vec<u8>,
+}
+
+/// Host data for the shader's `var<push_constant>`, set on every compute pass.
+///
+/// Devices without push constants get them through a uniform buffer instead, with the shader's
+/// push constant rebound to [`Plan::push_constant_fallback_binding`].
+pub struct PushConstants {
+    /// The contents, zero-padded to the size the shader declares.
+    pub contents: Vec<u8>,
+}
+
+/// Counters the shader declares as [`TELEMETRY_NAME`], bound zeroed and read back after every
+/// submission.
+pub struct Telemetry {
+    pub binding: naga::ResourceBinding,
+    pub size: u64,
+    /// The name of each `u32` counter, from the struct's members or the array's indices.
+    pub counters: Vec<String>,
+}
+
+impl Telemetry {
+    fn new(reflection: &Reflection) -> Option<Self> {
+        let shader_binding = (reflection.bindings().into_iter()).find(|b| {
+            b.name == TELEMETRY_NAME && b.kind == BindingKind::Storage { read_only: false }
+        })?;
+
+        let Some(size) = shader_binding.size else {
+            log::warn!("`{TELEMETRY_NAME}` is runtime-sized, so it is not read back as telemetry");
+            return None;
+        };
+
+        let words = (size / size_of::<u32>() as u64) as usize;
+        let counters = reflection
+            .member_names(&shader_binding.binding)
+            .filter(|names| names.len() == words)
+            .unwrap_or_else(|| {
+                (0..words)
+                    .map(|index| format!("{TELEMETRY_NAME}[{index}]"))
+                    .collect()
+            });
+
+        Some(Self {
+            binding: shader_binding.binding,
+            size,
+            counters,
+        })
+    }
+}
+
+/// Limits on the resources a plan may use, on top of the device limits.
+#[derive(Default)]
+pub struct Budget {
+    pub max_invocations: Option<u64>,
+    pub max_vram_bytes: Option<u64>,
+}
+
+/// The estimated resources a plan will use.
+pub struct Cost {
+    /// Bytes of GPU memory allocated for buffers.
+    pub vram_bytes: u64,
+    /// Bytes copied between the host and the GPU.
+    pub transfer_bytes: u64,
+    pub invocations: Option<u64>,
+}
+
+pub struct Plan {
+    /// The size of the working and output buffers, in bytes.
+    pub output_size: u64,
+    /// Whether the working buffer is bound as `var<storage, read>`.
+    pub working_read_only: bool,
+    /// The compute entry point wgpu will pick, if the shader has exactly one.
+    pub entry_point: Option<EntryPointMemory>,
+    pub dispatch: DispatchSize,
+    /// How the working buffer is initialized before the compute pass, if at all.
+    pub init: Option<BufferInit>,
+    /// The previous pipeline stage's working buffer, bound before any inputs.
+    pub stage_input: Option<StageInput>,
+    /// Host data bound after the working buffer, in binding order.
+    pub inputs: Vec<Input>,
+    pub params: Option<Params>,
+    /// Zeroed until set with [`Plan::set_push_constants`], if the shader declares any.
+    pub push_constants: Option<PushConstants>,
+    /// Where the shader reads the workgroup offset of each slice from, if it declares
+    /// [`SLICE_OFFSET_NAME`].
+    pub slice_offset: Option<naga::ResourceBinding>,
+    /// The most workgroups in x to dispatch per submission, or `None` to dispatch them all at
+    /// once.
+    ///
+    /// Each submission is waited on before the next, so that no single one runs long enough to
+    /// trip the OS GPU watchdog. The working buffer carries state between slices.
+    pub slice_workgroups: Option<u32>,
+    pub telemetry: Option<Telemetry>,
+    /// The values of the shader's `override` constants, by the key wgpu looks them up with.
+    pub constants: Vec<(String, f64)>,
+    /// Whether the working buffer is mapped and read back directly, rather than copied into a
+    /// separate output buffer first.
+    ///
+    /// This needs a device with `MAPPABLE_PRIMARY_BUFFERS`, see
+    /// [`crate::GpuContext::supports_zero_copy`].
+    pub zero_copy: bool,
+    /// Read the working buffer back in chunks of at most this many bytes, rather than copying
+    /// it into an output buffer as large as itself.
+    ///
+    /// This only bounds the staging memory: [`Plan::check`] still rejects working buffers over
+    /// the device's `max_buffer_size`, with [`PlanError::ChunkedOutputExceedsLimit`].
+    ///
+    /// The run's output is then the working buffer, read with
+    /// [`crate::GpuContext::read_chunked`].
+    pub readback_chunk: Option<u64>,
+}
+
+impl Plan {
+    pub fn new(reflection: &Reflection, output_size: u64) -> Self {
+        let mut entry_points = reflection.memory_usage();
+        let entry_point = (entry_points.len() == 1).then(|| entry_points.remove(0));
+
+        Self {
+            output_size,
+            working_read_only: reflection
+                .storage_read_only(&WORKING_BINDING)
+                .unwrap_or(false),
+            entry_point,
+            dispatch: DispatchSize::ONE,
+            init: None,
+            stage_input: None,
+            inputs: Vec::new(),
+            params: None,
+            push_constants: (reflection.push_constant()).map(|push_constant| PushConstants {
+                contents: vec![0; push_constant.size as usize],
+            }),
+            slice_offset: (reflection.bindings().into_iter())
+                .find(|b| b.name == SLICE_OFFSET_NAME && b.kind == BindingKind::Uniform)
+                .map(|b| b.binding),
+            slice_workgroups: None,
+            telemetry: Telemetry::new(reflection),
+            constants: Vec::new(),
+            zero_copy: false,
+            readback_chunk: None,
+        }
+    }
+
+    /// The number of shader invocations the dispatch will run, if the entry point is known.
+    pub fn invocations(&self) -> Option<u64> {
+        let entry_point = self.entry_point.as_ref()?;
+        let per_workgroup = entry_point
+            .workgroup_size
+            .iter()
+            .map(|&size| u64::from(size));
+        let workgroups = self.dispatch.to_array().map(u64::from);
+
+        Some(per_workgroup.chain(workgroups).product())
+    }
+
+    /// Whether the plan has no work to do, as its output, an input, or the previous stage's
+    /// output is empty, or it dispatches no workgroups.
+    ///
+    /// wgpu cannot bind empty buffers, so the shader of an empty plan is compiled but never bound
+    /// or dispatched. Its output is the initialized working buffer, which is empty if the output is.
+    pub fn is_empty(&self) -> bool {
+        self.output_size == 0
+            || self.inputs.iter().any(|input| input.contents.is_empty())
+            || (self.stage_input).is_some_and(|stage_input| stage_input.size == 0)
+            || self.dispatch.to_array().contains(&0)
+    }
+
+    /// Whether [`Plan::init`] uploads the initial contents through a staging buffer.
+    pub fn uploads_init(&self) -> bool {
+        matches!(self.init, Some(BufferInit::Iota | BufferInit::Fill(_)))
+    }
+
+    /// The buffer the shader binds, which is also the source of the readback copy.
+    pub fn working_buffer(&self) -> BufferSpec {
+        let mut spec =
+            BufferSpec::new(WORKING_BUFFER_LABEL, self.output_size).role(BufferRole::Output);
+        if self.init.is_some() {
+            spec = spec.role(BufferRole::Input);
+        }
+        if self.zero_copy {
+            spec = spec.role(BufferRole::Mapped);
+        }
+
+        spec
+    }
+
+    /// The buffer the working buffer is copied into to be read back, unless it is read back
+    /// directly with [`Plan::zero_copy`] or in chunks with [`Plan::readback_chunk`].
+    pub fn output_buffer(&self) -> Option<BufferSpec> {
+        (!self.zero_copy && self.readback_chunk.is_none()).then(|| {
+            BufferSpec::new(OUTPUT_BUFFER_LABEL, self.output_size).role(BufferRole::Readback)
+        })
+    }
+
+    /// The label of the buffer the output is read back from.
+    fn readback_label(&self) -> &'static str {
+        match self.output_buffer() {
+            Some(_) => OUTPUT_BUFFER_LABEL,
+            None => WORKING_BUFFER_LABEL,
+        }
+    }
+
+    /// The buffer holding the initial contents of the working buffer, if they are uploaded.
+    pub fn staging_buffer(&self) -> Option<BufferSpec> {
+        self.uploads_init().then(|| {
+            BufferSpec::new(STAGING_BUFFER_LABEL, self.output_size).role(BufferRole::Staging)
+        })
+    }
+
+    /// The binding after the working buffer and any earlier inputs, skipping the bindings of the
+    /// shader's uniform buffers and its telemetry buffer.
+    fn next_input_binding(&self, reflection: &Reflection) -> naga::ResourceBinding {
+        let previous = (self.inputs.last().map(|input| input.binding))
+            .or(self.stage_input.map(|stage_input| stage_input.binding))
+            .unwrap_or(WORKING_BINDING);
+        let mut binding = naga::ResourceBinding {
+            group: WORKING_BINDING.group,
+            binding: previous.binding + 1,
+        };
+        let reserved: Vec<_> = (reflection.bindings().into_iter())
+            .filter(|b| b.kind == BindingKind::Uniform)
+            .map(|b| b.binding)
+            .chain(self.telemetry.as_ref().map(|telemetry| telemetry.binding))
+            .collect();
+        while reserved.contains(&binding) {
+            binding.binding += 1;
+        }
+
+        binding
+    }
+
+    /// The access the shader declares for the input at `binding`, warning if it declares none.
+    fn input_read_only(&self, reflection: &Reflection, binding: naga::ResourceBinding) -> bool {
+        reflection.storage_read_only(&binding).unwrap_or_else(|| {
+            log::warn!(
+                "Input {} is bound to @group({}) @binding({}), which the shader does not declare",
+                self.inputs.len(),
+                binding.group,
+                binding.binding
+            );
+            true
+        })
+    }
+
+    /// Binds `contents` after the working buffer and any earlier inputs, with the access the
+    /// shader declares for that binding.
+    ///
+    /// The bindings of the shader's uniform buffers are skipped, so inputs and params can be
+    /// added in any order.
+    pub fn add_input(&mut self, reflection: &Reflection, contents: Vec<u8>) {
+        let binding = self.next_input_binding(reflection);
+        self.inputs.push(Input {
+            binding,
+            read_only: self.input_read_only(reflection, binding),
+            contents,
+        });
+    }
+
+    /// Binds the working buffer of `previous`, the stage before this one in a
+    /// [`crate::pipeline::Pipeline`], after the working buffer, like an input.
+    ///
+    /// This must be called before [`Plan::add_input`], so that it is bound first.
+    pub fn add_stage_input(&mut self, reflection: &Reflection, previous: &Plan) {
+        let binding = self.next_input_binding(reflection);
+        self.stage_input = Some(StageInput {
+            binding,
+            read_only: self.input_read_only(reflection, binding),
+            size: previous.output_size,
+        });
+    }
+
+    /// Binds `params` to the first uniform buffer the shader declares, other than
+    /// [`SLICE_OFFSET_NAME`].
+    pub fn set_params(&mut self, reflection: &Reflection, params: &impl bytemuck::Pod) {
+        self.set_params_bytes(reflection, bytemuck::bytes_of(params).to_vec());
+    }
+
+    /// Like [`Plan::set_params`], but for parameters that are already bytes.
+    pub fn set_params_bytes(&mut self, reflection: &Reflection, contents: Vec<u8>) {
+        let binding = (reflection.bindings().into_iter())
+            .find(|b| b.kind == BindingKind::Uniform && b.name != SLICE_OFFSET_NAME)
+            .map(|b| b.binding);
+        let Some(binding) = binding else {
+            log::warn!("The shader declares no uniform buffer, so the params will not be bound");
+            return;
+        };
+
+        self.params = Some(Params { binding, contents });
+    }
+
+    /// Sets the shader's `var<push_constant>` to `contents`, zero-padded to its declared size.
+    pub fn set_push_constants(
+        &mut self,
+        reflection: &Reflection,
+        mut contents: Vec<u8>,
+    ) -> Result<(), PlanError> {
+        let Some(push_constant) = reflection.push_constant() else {
+            log::warn!(
+                "The shader declares no push constant, so the push constants will not be set"
+            );
+            return Ok(());
+        };
+
+        if contents.len() > push_constant.size as usize {
+            return Err(PlanError::PushConstantSize {
+                name: push_constant.name,
+                size: contents.len(),
+                declared: push_constant.size,
+            });
+        }
+
+        contents.resize(push_constant.size as usize, 0);
+        self.push_constants = Some(PushConstants { contents });
+        Ok(())
+    }
+
+    /// Where the push constants are bound as a `var<uniform>` on devices without push
+    /// constants, which is the binding after every other one the plan binds.
+    pub fn push_constant_fallback_binding(&self) -> naga::ResourceBinding {
+        let last = std::iter::once(WORKING_BINDING)
+            .chain(self.stage_input.map(|stage_input| stage_input.binding))
+            .chain(self.inputs.iter().map(|input| input.binding))
+            .chain(self.params.as_ref().map(|params| params.binding))
+            .chain(self.slice_offset)
+            .chain(self.telemetry.as_ref().map(|telemetry| telemetry.binding))
+            .map(|binding| binding.binding)
+            .max()
+            .unwrap_or(WORKING_BINDING.binding);
+
+        naga::ResourceBinding {
+            group: WORKING_BINDING.group,
+            binding: last + 1,
+        }
+    }
+
+    /// The uniform buffer push constants are uploaded to on devices without push constants.
+    pub fn push_constant_buffer(&self) -> Option<BufferSpec> {
+        let push_constants = self.push_constants.as_ref()?;
+        let spec = BufferSpec::new(
+            PUSH_CONSTANT_BUFFER_LABEL,
+            push_constants.contents.len() as u64,
+        );
+        Some(spec.role(BufferRole::Uniform))
+    }
+
+    /// Dispatches the compute entry point called `name`, or fails if `name` is `None` and the
+    /// shader has more than one to pick from.
+    pub fn set_entry_point(
+        &mut self,
+        reflection: &Reflection,
+        name: Option<&str>,
+    ) -> Result<(), PlanError> {
+        let mut entry_points = reflection.memory_usage();
+        let available = || {
+            let names: Vec<_> = entry_points.iter().map(|e| e.name.as_str()).collect();
+            names.join(", ")
+        };
+
+        match name {
+            Some(name) => match entry_points.iter().position(|e| e.name == name) {
+                Some(index) => self.entry_point = Some(entry_points.swap_remove(index)),
+                None => {
+                    return Err(PlanError::UnknownEntryPoint {
+                        name: name.to_owned(),
+                        available: available(),
+                    });
+                }
+            },
+            None if entry_points.len() > 1 => {
+                return Err(PlanError::AmbiguousEntryPoint {
+                    available: available(),
+                });
+            }
+            None => {}
+        }
+
+        self.resolve_workgroup_size(reflection);
+        Ok(())
+    }
+
+    /// Updates the entry point's workgroup size for the constants set so far, as dimensions
+    /// given by `override` constants depend on them.
+    fn resolve_workgroup_size(&mut self, reflection: &Reflection) {
+        if let Some(entry_point) = &mut self.entry_point
+            && let Some(size) = reflection.workgroup_size(&entry_point.name, &self.constants)
+        {
+            entry_point.workgroup_size = size;
+        }
+    }
+
+    /// Compiles the pipeline with the shader's `override` constant `name` set to `value`,
+    /// replacing any value set before.
+    ///
+    /// The entry point's workgroup size is updated if the constant sizes it, so invocation
+    /// counts and limit checks see the size the pipeline will be compiled with.
+    pub fn set_constant(
+        &mut self,
+        reflection: &Reflection,
+        name: &str,
+        value: f64,
+    ) -> Result<(), PlanError> {
+        let overrides = reflection.overrides();
+        let Some(constant) = overrides.iter().find(|o| o.name == name) else {
+            let names: Vec<_> = overrides.iter().map(|o| o.name.as_str()).collect();
+            return Err(PlanError::UnknownConstant {
+                name: name.to_owned(),
+                available: names.join(", "),
+            });
+        };
+
+        self.constants.retain(|(key, _)| *key != constant.key);
+        self.constants.push((constant.key.clone(), value));
+        self.resolve_workgroup_size(reflection);
+        Ok(())
+    }
+
+    /// Whether the plan binds a buffer at `binding`.
+    pub fn binds(&self, binding: &naga::ResourceBinding) -> bool {
+        *binding == WORKING_BINDING
+            || (self.stage_input).is_some_and(|stage_input| stage_input.binding == *binding)
+            || self.inputs.iter().any(|input| input.binding == *binding)
+            || self
+                .params
+                .as_ref()
+                .is_some_and(|params| params.binding == *binding)
+            || self.slice_offset == Some(*binding)
+            || (self.telemetry.as_ref()).is_some_and(|telemetry| telemetry.binding == *binding)
+    }
+
+    /// Binds a zeroed buffer to every storage or uniform buffer the shader declares that the plan
+    /// does not bind yet, so shaders with scratch buffers run without any inputs.
+    ///
+    /// Fails on runtime-sized buffers, as there is no size to zero, and on any resource other
+    /// than a buffer in bind group 0.
+    pub fn bind_remaining(&mut self, reflection: &Reflection) -> Result<(), PlanError> {
+        for shader_binding in reflection.bindings() {
+            let binding = shader_binding.binding;
+            if self.binds(&binding) {
+                continue;
+            }
+
+            let name = shader_binding.name;
+            let unsupported = |reason: String| PlanError::UnsupportedBinding {
+                name: name.clone(),
+                group: binding.group,
+                binding: binding.binding,
+                reason,
+            };
+
+            if binding.group != WORKING_BINDING.group {
+                return Err(unsupported(format!(
+                    "only bind group {} is bound",
+                    WORKING_BINDING.group
+                )));
+            }
+
+            let contents = match (shader_binding.kind, shader_binding.size) {
+                (BindingKind::Other(resource), _) => {
+                    return Err(unsupported(format!("{resource}s cannot be bound yet")));
+                }
+                (BindingKind::Uniform, _) if self.params.is_some() => {
+                    return Err(unsupported(String::from(
+                        "only one uniform buffer can be bound",
+                    )));
+                }
+                (_, None) => {
+                    return Err(PlanError::UnsizedBinding {
+                        name,
+                        binding: binding.binding,
+                    });
+                }
+                (_, Some(size)) => vec![0; size as usize],
+            };
+
+            log::info!("Binding {} zeroed bytes to `{name}`", contents.len());
+            match shader_binding.kind {
+                BindingKind::Storage { read_only } => self.inputs.push(Input {
+                    binding,
+                    read_only,
+                    contents,
+                }),
+                _ => self.params = Some(Params { binding, contents }),
+            }
+        }
+
+        self.inputs.sort_by_key(|input| input.binding.binding);
+        Ok(())
+    }
+
+    /// The workgroup offset in x and workgroup count of each submission, in order, which is none
+    /// for an empty plan.
+    pub fn slices(&self) -> Vec<(u32, u32)> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+
+        let total = self.dispatch.x;
+        let per_slice = self
+            .slice_workgroups
+            .unwrap_or(total)
+            .clamp(1, total.max(1));
+        (0..total)
+            .step_by(per_slice as usize)
+            .map(|offset| (offset, per_slice.min(total - offset)))
+            .collect()
+    }
+
+    /// The offset of each of [`Plan::slices`], spaced [`SLICE_OFFSET_STRIDE`] bytes apart.
+    pub fn slice_offset_contents(&self) -> Vec<u8> {
+        let slices = self.slices();
+        let mut contents = vec![0; slices.len() * SLICE_OFFSET_STRIDE as usize];
+        for (index, (offset, _)) in slices.into_iter().enumerate() {
+            let start = index * SLICE_OFFSET_STRIDE as usize;
+            contents[start..start + size_of::<u32>()].copy_from_slice(&offset.to_ne_bytes());
+        }
+
+        contents
+    }
+
+    pub fn slice_offset_buffer(&self) -> Option<BufferSpec> {
+        self.slice_offset?;
+        let size = self.slices().len() as u64 * SLICE_OFFSET_STRIDE;
+        let spec = BufferSpec::new(SLICE_OFFSET_BUFFER_LABEL, size);
+        Some(spec.role(BufferRole::Uniform))
+    }
+
+    /// The buffer the shader writes telemetry to, which is cleared before each run and copied out
+    /// after every submission.
+    pub fn telemetry_buffer(&self) -> Option<BufferSpec> {
+        let telemetry = self.telemetry.as_ref()?;
+        let spec = BufferSpec::new(TELEMETRY_BUFFER_LABEL, telemetry.size);
+        Some(spec.role(BufferRole::Output).role(BufferRole::Input))
+    }
+
+    /// The buffer each submission's telemetry is copied into, one per slice.
+    pub fn telemetry_readback_buffer(&self) -> Option<BufferSpec> {
+        let telemetry = self.telemetry.as_ref()?;
+        let spec = BufferSpec::new(TELEMETRY_READBACK_LABEL, telemetry.size);
+        Some(spec.role(BufferRole::Readback))
+    }
+
+    /// The number of timestamps written when the device supports timestamp queries, one at
+    /// either end of each slice's compute pass.
+    pub fn timestamp_count(&self) -> u32 {
+        (self.slices().len() * 2).try_into().unwrap_or(u32::MAX)
+    }
+
+    /// The buffer the pass timestamps are resolved into.
+    ///
+    /// This is not part of [`Self::buffers`], as it is only created if the device supports
+    /// timestamp queries.
+    pub fn timestamp_resolve_buffer(&self) -> BufferSpec {
+        let size = u64::from(self.timestamp_count()) * u64::from(wgpu::QUERY_SIZE);
+        BufferSpec::new(TIMESTAMP_RESOLVE_LABEL, size).role(BufferRole::QueryResolve)
+    }
+
+    /// The buffer the resolved timestamps are copied into, to be read back after the last slice.
+    pub fn timestamp_readback_buffer(&self) -> BufferSpec {
+        let size = self.timestamp_resolve_buffer().size;
+        BufferSpec::new(TIMESTAMP_READBACK_LABEL, size).role(BufferRole::Readback)
+    }
+
+    pub fn params_buffer(&self) -> Option<BufferSpec> {
+        let params = self.params.as_ref()?;
+        let spec = BufferSpec::new(PARAMS_BUFFER_LABEL, params.contents.len() as u64);
+        Some(spec.role(BufferRole::Uniform))
+    }
+
+    pub fn input_buffer(&self, input: &Input) -> BufferSpec {
+        BufferSpec::new(input.label(), input.contents.len() as u64).role(BufferRole::Input)
+    }
+
+    pub fn buffers(&self) -> Vec<BufferSpec> {
+        let inputs = self.inputs.iter().map(|input| self.input_buffer(input));
+        std::iter::once(self.working_buffer())
+            .chain(self.output_buffer())
+            .chain(self.staging_buffer())
+            .chain(inputs)
+            .chain(self.params_buffer())
+            // Counted even on devices with push constants, which leave it unused.
+            .chain(self.push_constant_buffer())
+            .chain(self.slice_offset_buffer())
+            .chain(self.telemetry_buffer())
+            .chain(self.telemetry_readback_buffer())
+            .collect()
+    }
+
+    pub fn cost(&self) -> Cost {
+        let staging_bytes = self.staging_buffer().map_or(0, |staging| staging.size);
+        let input_bytes: u64 = self.inputs.iter().map(|i| i.contents.len() as u64).sum();
+        let params_bytes = (self.params_buffer().into_iter())
+            .chain(self.push_constant_buffer())
+            .chain(self.slice_offset_buffer())
+            .map(|buffer| buffer.size)
+            .sum::<u64>();
+
+        // Every slice copies its telemetry into a readback buffer of its own.
+        let readback_bytes = self.telemetry_readback_buffer().map_or(0, |r| r.size);
+        let telemetry_bytes = readback_bytes * self.slices().len() as u64;
+
+        let buffer_bytes: u64 = self.buffers().iter().map(|buffer| buffer.size).sum();
+        Cost {
+            vram_bytes: buffer_bytes - readback_bytes + telemetry_bytes,
+            transfer_bytes: self.output_size
+                + staging_bytes
+                + input_bytes
+                + params_bytes
+                + telemetry_bytes,
+            invocations: self.invocations(),
+        }
+    }
+
+    /// Checks the workgroup size and memory of `entry_point` against the device `limits`, which
+    /// wgpu would otherwise only report as a generic pipeline creation error.
+    fn check_entry_point(
+        entry_point: &EntryPointMemory,
+        limits: &wgpu::Limits,
+    ) -> Result<(), PlanError> {
+        let [x, y, z] = entry_point.workgroup_size.map(u64::from);
+        let checks = [
+            (
+                "bytes of workgroup memory",
+                entry_point.workgroup_bytes.into(),
+                limits.max_compute_workgroup_storage_size.into(),
+            ),
+            (
+                "invocations per workgroup",
+                x * y * z,
+                limits.max_compute_invocations_per_workgroup.into(),
+            ),
+            (
+                "workgroup size x",
+                x,
+                limits.max_compute_workgroup_size_x.into(),
+            ),
+            (
+                "workgroup size y",
+                y,
+                limits.max_compute_workgroup_size_y.into(),
+            ),
+            (
+                "workgroup size z",
+                z,
+                limits.max_compute_workgroup_size_z.into(),
+            ),
+        ];
+
+        match checks
+            .into_iter()
+            .find(|(_, required, limit)| required > limit)
+        {
+            Some((what, required, limit)) => Err(PlanError::EntryPointExceedsLimit {
+                entry_point: entry_point.name.clone(),
+                what,
+                required,
+                limit,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// The number of storage buffers the plan binds: the working buffer, the stage input, every
+    /// input including zeroed scratch buffers, and the telemetry buffer.
+    fn storage_bindings(&self) -> u64 {
+        1 + u64::from(self.stage_input.is_some())
+            + self.inputs.len() as u64
+            + u64::from(self.telemetry.is_some())
+    }
+
+    /// Checks the plan's cost against the device `limits` and `budget`, warning about plans
+    /// that are likely to run for a very long time.
+    pub fn check(&self, limits: &wgpu::Limits, budget: &Budget) -> Result<Cost, PlanError> {
+        if self.slice_workgroups.is_some() && self.slice_offset.is_none() {
+            return Err(PlanError::NoSliceOffset);
+        }
+
+        for buffer in self.buffers() {
+            buffer.validate()?;
+        }
+
+        let cost = self.cost();
+
+        let exceeds_limit = |what, required, limit| {
+            (required > limit).then_some(PlanError::ExceedsLimit {
+                what,
+                required,
+                limit,
+            })
+        };
+
+        if self.readback_chunk.is_some() && self.output_size > limits.max_buffer_size {
+            return Err(PlanError::ChunkedOutputExceedsLimit {
+                size: self.output_size,
+                limit: limits.max_buffer_size,
+            });
+        }
+
+        let largest_binding = (self.inputs.iter())
+            .map(|input| input.contents.len() as u64)
+            .chain(self.stage_input.map(|stage_input| stage_input.size))
+            .chain(self.telemetry.as_ref().map(|telemetry| telemetry.size))
+            .fold(self.output_size, u64::max);
+
+        let max_workgroups = limits.max_compute_workgroups_per_dimension;
+        let limit_errors = [
+            exceeds_limit("a buffer", largest_binding, limits.max_buffer_size),
+            exceeds_limit(
+                "a storage binding",
+                largest_binding,
+                limits.max_storage_buffer_binding_size.into(),
+            ),
+            exceeds_limit(
+                "a uniform binding",
+                self.params_buffer().map_or(0, |params| params.size),
+                limits.max_uniform_buffer_binding_size.into(),
+            ),
+            exceeds_limit(
+                "storage buffers",
+                self.storage_bindings(),
+                limits.max_storage_buffers_per_shader_stage.into(),
+            ),
+            exceeds_limit(
+                "workgroups in x",
+                self.dispatch.x.into(),
+                max_workgroups.into(),
+            ),
+            exceeds_limit(
+                "workgroups in y",
+                self.dispatch.y.into(),
+                max_workgroups.into(),
+            ),
+            exceeds_limit(
+                "workgroups in z",
+                self.dispatch.z.into(),
+                max_workgroups.into(),
+            ),
+        ];
+
+        if let Some(err) = limit_errors.into_iter().flatten().next() {
+            return Err(err);
+        }
+
+        if let Some(entry_point) = &self.entry_point {
+            Self::check_entry_point(entry_point, limits)?;
+        }
+
+        let over_budget = |what, required, budget: Option<u64>| {
+            let budget = budget?;
+            (required > budget).then_some(PlanError::OverBudget {
+                what,
+                required,
+                budget,
+            })
+        };
+
+        let budget_errors = [
+            cost.invocations.and_then(|invocations| {
+                over_budget("invocations", invocations, budget.max_invocations)
+            }),
+            over_budget("bytes of VRAM", cost.vram_bytes, budget.max_vram_bytes),
+        ];
+
+        if let Some(err) = budget_errors.into_iter().flatten().next() {
+            return Err(err);
+        }
+
+        if let Some(invocations) = cost.invocations
+            && invocations > INVOCATION_WARN_THRESHOLD
+            && budget.max_invocations.is_none()
+        {
+            log::warn!(
+                "The plan runs {invocations} invocations and may take hours, \
+                 pass --max-invocations to refuse plans like this"
+            );
+        }
+
+        Ok(cost)
+    }
+
+    /// Renders the passes as a Graphviz graph, annotating the compute pass with `elapsed`.
+    pub fn graph(&self, elapsed: Option<Duration>) -> Graph<'_> {
+        Graph {
+            plan: self,
+            elapsed,
+        }
+    }
+}
+
+/// A plan as a DOT graph, with passes as nodes and the buffers passed between them as edges.
+pub struct Graph<'a> {
+    plan: &'a Plan,
+    elapsed: Option<Duration>,
+}
+
+impl Display for Graph<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let plan = self.plan;
+        let entry_point = plan
+            .entry_point
+            .as_ref()
+            .map_or("<ambiguous>", |entry_point| entry_point.name.as_str());
+
+        let edge = |f: &mut fmt::Formatter<'_>, from: &str, to: &str, label: &str, size: u64| {
+            writeln!(f, "  {from} -> {to} [label=\"{label}\\n{size} bytes\"];")
+        };
+
+        writeln!(f, "digraph plan {{")?;
+        writeln!(f, "  rankdir=LR;")?;
+        writeln!(f, "  node [shape=box];")?;
+
+        for (index, input) in plan.inputs.iter().enumerate() {
+            let size = input.contents.len() as u64;
+            writeln!(
+                f,
+                "  upload{index} [label=\"upload\\nhost -> {}\"];",
+                input.label()
+            )?;
+            edge(
+                f,
+                &format!("upload{index}"),
+                "compute",
+                &input.label(),
+                size,
+            )?;
+        }
+        if let Some(params) = &plan.params {
+            let size = params.contents.len() as u64;
+            writeln!(
+                f,
+                "  params [label=\"upload\\nhost -> {PARAMS_BUFFER_LABEL}\"];"
+            )?;
+            edge(f, "params", "compute", PARAMS_BUFFER_LABEL, size)?;
+        }
+
+        match plan.init {
+            Some(BufferInit::Zero) => writeln!(f, "  init [label=\"clear\"];")?,
+            Some(init) => writeln!(
+                f,
+                "  init [label=\"copy\\n{STAGING_BUFFER_LABEL} ({init})\"];"
+            )?,
+            None => {}
+        }
+        if plan.init.is_some() {
+            edge(f, "init", "compute", WORKING_BUFFER_LABEL, plan.output_size)?;
+        }
+
+        write!(
+            f,
+            "  compute [label=\"compute `{entry_point}`\\n{} workgroups",
+            plan.dispatch
+        )?;
+        let slices = plan.slices().len();
+        if slices > 1 {
+            write!(f, "\\nin {slices} submissions")?;
+        }
+        if let Some(elapsed) = self.elapsed {
+            write!(f, "\\n{:.3} ms", elapsed.as_secs_f64() * 1000.0)?;
+        }
+        writeln!(f, "\", shape=ellipse];")?;
+
+        writeln!(f, "  readback [label=\"readback\"];")?;
+        if plan.output_buffer().is_none() {
+            edge(
+                f,
+                "compute",
+                "readback",
+                WORKING_BUFFER_LABEL,
+                plan.output_size,
+            )?;
+        } else {
+            writeln!(f, "  copy [label=\"copy\"];")?;
+            edge(f, "compute", "copy", WORKING_BUFFER_LABEL, plan.output_size)?;
+            edge(f, "copy", "readback", OUTPUT_BUFFER_LABEL, plan.output_size)?;
+        }
+
+        writeln!(f, "}}")
+    }
+}
+
+impl Display for Cost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Estimated cost: {} bytes of VRAM, {} bytes transferred",
+            self.vram_bytes, self.transfer_bytes
+        )?;
+
+        match self.invocations {
+            Some(invocations) => writeln!(f, ", {invocations} invocations"),
+            None => writeln!(f),
+        }
+    }
+}
+
+impl Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (entry_point, [x, y, z]) = match &self.entry_point {
+            Some(entry_point) => (entry_point.name.as_str(), entry_point.workgroup_size),
+            None => ("<ambiguous>", [0, 0, 0]),
+        };
+
+        writeln!(f, "Buffers:")?;
+        for buffer in self.buffers() {
+            writeln!(f, "  {buffer}")?;
+        }
+
+        let access = if self.working_read_only {
+            "read"
+        } else {
+            "read_write"
+        };
+        writeln!(f, "Bind group {}:", WORKING_BINDING.group)?;
+        writeln!(
+            f,
+            "  binding {}: {WORKING_BUFFER_LABEL} as var<storage, {access}>",
+            WORKING_BINDING.binding
+        )?;
+        if let Some(stage_input) = &self.stage_input {
+            let access = if stage_input.read_only {
+                "read"
+            } else {
+                "read_write"
+            };
+            writeln!(
+                f,
+                "  binding {}: the previous stage's {WORKING_BUFFER_LABEL} as var<storage, {access}>",
+                stage_input.binding.binding
+            )?;
+        }
+        for input in &self.inputs {
+            writeln!(
+                f,
+                "  binding {}: {} as var<storage, {}>",
+                input.binding.binding,
+                input.label(),
+                input.access()
+            )?;
+        }
+        if let Some(params) = &self.params {
+            writeln!(
+                f,
+                "  binding {}: {PARAMS_BUFFER_LABEL} as var<uniform>",
+                params.binding.binding
+            )?;
+        }
+        if let Some(binding) = &self.slice_offset {
+            writeln!(
+                f,
+                "  binding {}: {SLICE_OFFSET_BUFFER_LABEL} as var<uniform>, with a dynamic offset",
+                binding.binding
+            )?;
+        }
+        if let Some(telemetry) = &self.telemetry {
+            writeln!(
+                f,
+                "  binding {}: {TELEMETRY_BUFFER_LABEL} as var<storage, read_write>",
+                telemetry.binding.binding
+            )?;
+        }
+
+        let slice_offsets = self.slice_offset_buffer();
+        if !self.inputs.is_empty() || self.params.is_some() || slice_offsets.is_some() {
+            writeln!(f, "Uploads:")?;
+        }
+        for input in &self.inputs {
+            let size = input.contents.len();
+            writeln!(f, "  host -> {}, {size} bytes", input.label())?;
+        }
+        if let Some(params) = &self.params {
+            let size = params.contents.len();
+            writeln!(f, "  host -> {PARAMS_BUFFER_LABEL}, {size} bytes")?;
+        }
+        if let Some(slice_offsets) = slice_offsets {
+            writeln!(
+                f,
+                "  host -> {SLICE_OFFSET_BUFFER_LABEL}, {} bytes",
+                slice_offsets.size
+            )?;
+        }
+
+        match self.init {
+            Some(BufferInit::Zero) => writeln!(f, "Init:\n  clear {WORKING_BUFFER_LABEL}")?,
+            Some(init) => writeln!(
+                f,
+                "Init:\n  {STAGING_BUFFER_LABEL} ({init}) -> {WORKING_BUFFER_LABEL}, {} bytes",
+                self.output_size
+            )?,
+            None => {}
+        }
+
+        if !self.constants.is_empty() {
+            writeln!(f, "Constants:")?;
+        }
+        for (key, value) in &self.constants {
+            writeln!(f, "  override {key} = {value}")?;
+        }
+
+        if let Some(push_constants) = &self.push_constants {
+            writeln!(
+                f,
+                "Push constants:\n  {} bytes, set on every pass, or bound to binding {} as {PUSH_CONSTANT_BUFFER_LABEL} without device support",
+                push_constants.contents.len(),
+                self.push_constant_fallback_binding().binding
+            )?;
+        }
+
+        writeln!(f, "Passes:")?;
+        write!(
+            f,
+            "  1. compute `{entry_point}` ({x}x{y}x{z}): dispatch {} workgroups",
+            self.dispatch
+        )?;
+        match self.invocations() {
+            Some(invocations) => writeln!(f, ", {invocations} invocations")?,
+            None => writeln!(f)?,
+        }
+        let slices = self.slices();
+        if self.is_empty() {
+            writeln!(f, "     skipped, as an input or the output is empty")?;
+        } else if slices.len() > 1 {
+            writeln!(
+                f,
+                "     in {} submissions of up to {} workgroups in x, each waited on",
+                slices.len(),
+                slices[0].1
+            )?;
+        }
+
+        if self.output_buffer().is_some() {
+            writeln!(f, "Copies:")?;
+            writeln!(
+                f,
+                "  {WORKING_BUFFER_LABEL} -> {OUTPUT_BUFFER_LABEL}, {} bytes",
+                self.output_size
+            )?;
+        }
+        write!(
+            f,
+            "Readback: {} bytes from {}",
+            self.output_size,
+            self.readback_label()
+        )?;
+        match self.readback_chunk {
+            Some(chunk) => writeln!(f, ", in chunks of up to {chunk} bytes")?,
+            None => writeln!(f)?,
+        }
+        if let Some(telemetry) = &self.telemetry {
+            writeln!(
+                f,
+                "Telemetry: {} bytes from {TELEMETRY_BUFFER_LABEL} after every submission: {}",
+                telemetry.size,
+                telemetry.counters.join(", ")
+            )?;
+        }
+
+        write!(f, "{}", self.cost())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Budget, BufferInit, DispatchSize, Plan, PlanError};
+    use crate::reflect::Reflection;
+
+    const SHADER: &str = "@group(0) @binding(0) var<storage, read_write> out: array<u32>;
+        @compute @workgroup_size(64)
+        fn main(@builtin(global_invocation_id) id: vec3<u32>) { out[id.x] = id.x; }";
+
+    #[test]
+    fn dispatch_size() {
+        let parse = |value: &str| value.parse::<DispatchSize>();
+        assert_eq!(parse("64"), Ok(DispatchSize { x: 64, y: 1, z: 1 }));
+        assert_eq!(parse("2x3"), Ok(DispatchSize { x: 2, y: 3, z: 1 }));
+        assert_eq!(parse("2x3x4"), Ok(DispatchSize { x: 2, y: 3, z: 4 }));
+        assert_eq!(parse("2x3x4").unwrap().to_string(), "2x3x4");
+
+        for invalid in ["", "0", "2x0", "ax1", "-1", "1x2x3x4"] {
+            assert!(parse(invalid).is_err(), "{invalid:?}");
+        }
+    }
+
+    #[test]
+    fn buffer_init() {
+        let parse = |value: &str| value.parse::<BufferInit>();
+        assert!(matches!(parse("zero"), Ok(BufferInit::Zero)));
+        assert!(matches!(parse("iota"), Ok(BufferInit::Iota)));
+        assert!(matches!(parse("fill:7"), Ok(BufferInit::Fill(7))));
+
+        for value in ["zero", "iota", "fill:7"] {
+            assert_eq!(parse(value).unwrap().to_string(), value);
+        }
+        for invalid in ["", "ones", "fill:", "fill:x", "fill:-1"] {
+            assert!(parse(invalid).is_err(), "{invalid:?}");
+        }
+    }
+
+    #[test]
+    fn chunked_output_limit() {
+        let reflection = Reflection::new(SHADER).unwrap();
+        let limits = wgpu::Limits::default();
+        let mut plan = Plan::new(&reflection, limits.max_buffer_size + 4);
+        plan.readback_chunk = Some(1024);
+        assert!(matches!(
+            plan.check(&limits, &Budget::default()),
+            Err(PlanError::ChunkedOutputExceedsLimit { .. })
+        ));
+
+        plan.output_size = 4096;
+        assert!(plan.check(&limits, &Budget::default()).is_ok());
+    }
+
+    #[test]
+    fn inputs_skip_telemetry() {
+        let reflection = Reflection::new(
+            "@group(0) @binding(0) var<storage, read_write> out: array<u32>;
+            @group(0) @binding(1) var<storage, read_write> telemetry: array<u32, 2>;
+            @group(0) @binding(2) var<storage, read> input: array<u32>;
+            @compute @workgroup_size(64)
+            fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+                out[id.x] = input[id.x];
+                telemetry[0] += 1u;
+            }",
+        )
+        .unwrap();
+        let mut plan = Plan::new(&reflection, 256);
+        plan.add_input(&reflection, vec![0; 256]);
+        assert_eq!(plan.inputs[0].binding.binding, 2);
+
+        let mut limits = wgpu::Limits::default();
+        assert!(plan.check(&limits, &Budget::default()).is_ok());
+        limits.max_storage_buffers_per_shader_stage = 2;
+        assert!(matches!(
+            plan.check(&limits, &Budget::default()),
+            Err(PlanError::ExceedsLimit {
+                what: "storage buffers",
+                required: 3,
+                ..
+            })
+        ));
+    }
+}
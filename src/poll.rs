@@ -0,0 +1,157 @@
+//! How to wait for the GPU, trading the CPU time spent waiting against how soon finished work is
+//! noticed.
+
+use std::time::{Duration, Instant};
+
+/// Waits in three phases: spinning on non-blocking polls, then sleeping between polls with an
+/// exponential backoff, then blocking until the GPU finishes.
+///
+/// Spinning notices finished work soonest but keeps a core busy, while backing off frees the
+/// core at the cost of up to [`Self::max_backoff`] of latency.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PollStrategy {
+    /// How long to spin for before backing off.
+    pub spin: Duration,
+    /// How long to sleep after the first poll that backs off, doubling after each one.
+    pub min_backoff: Duration,
+    /// The longest to sleep between polls.
+    pub max_backoff: Duration,
+    /// How long to back off for before blocking, or [`Duration::MAX`] to never block.
+    pub backoff_for: Duration,
+}
+
+impl PollStrategy {
+    /// Blocks straight away, leaving the wait to the driver.
+    pub const BLOCK: Self = Self {
+        spin: Duration::ZERO,
+        min_backoff: Duration::ZERO,
+        max_backoff: Duration::ZERO,
+        backoff_for: Duration::ZERO,
+    };
+
+    /// Spins briefly then backs off quickly, for interactive use where short kernels should be
+    /// noticed as soon as they finish.
+    pub const LOW_LATENCY: Self = Self {
+        spin: Duration::from_micros(500),
+        min_backoff: Duration::from_micros(20),
+        max_backoff: Duration::from_micros(500),
+        backoff_for: Duration::from_millis(50),
+    };
+
+    /// Never spins and backs off up to 10ms, for long-running processes that should not keep a
+    /// core busy while the GPU works.
+    pub const LOW_CPU: Self = Self {
+        spin: Duration::ZERO,
+        min_backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(10),
+        backoff_for: Duration::MAX,
+    };
+
+    /// The name of every preset, as [`PollStrategy::from_name`] accepts them.
+    pub const NAMES: &[&str] = &["block", "low-latency", "low-cpu"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "block" => Some(Self::BLOCK),
+            "low-latency" => Some(Self::LOW_LATENCY),
+            "low-cpu" => Some(Self::LOW_CPU),
+            _ => None,
+        }
+    }
+
+    /// Polls `device` until `done` returns true, blocking with `block` once backing off is over.
+    pub(crate) fn wait(
+        &self,
+        device: &wgpu::Device,
+        block: wgpu::PollType,
+        mut done: impl FnMut() -> bool,
+    ) -> Result<(), wgpu::PollError> {
+        for sleep in Phases::new(self) {
+            device.poll(wgpu::PollType::Poll)?;
+            if done() {
+                return Ok(());
+            }
+
+            match sleep {
+                Some(sleep) => std::thread::sleep(sleep),
+                None => std::hint::spin_loop(),
+            }
+        }
+
+        device.poll(block)?;
+        Ok(())
+    }
+
+    /// Like [`Self::wait`], but sleeps on the tokio runtime and blocks on a blocking thread, so
+    /// the runtime can make progress on other tasks in the meantime.
+    pub(crate) async fn wait_async(
+        &self,
+        device: &wgpu::Device,
+        block: wgpu::PollType,
+        mut done: impl FnMut() -> bool,
+    ) -> Result<(), wgpu::PollError> {
+        for sleep in Phases::new(self) {
+            device.poll(wgpu::PollType::Poll)?;
+            if done() {
+                return Ok(());
+            }
+
+            match sleep {
+                Some(sleep) => tokio::time::sleep(sleep).await,
+                None => tokio::task::yield_now().await,
+            }
+        }
+
+        let device = device.clone();
+        let poll = tokio::task::spawn_blocking(move || device.poll(block));
+        poll.await.expect("polling the device should not panic")?;
+        Ok(())
+    }
+}
+
+impl Default for PollStrategy {
+    fn default() -> Self {
+        Self::LOW_LATENCY
+    }
+}
+
+/// Steps through the phases of a [`PollStrategy`], yielding how long to sleep after each poll,
+/// or `None` to spin, until it is time to block.
+struct Phases<'a> {
+    strategy: &'a PollStrategy,
+    start: Instant,
+    backoff: Duration,
+}
+
+impl<'a> Phases<'a> {
+    fn new(strategy: &'a PollStrategy) -> Self {
+        Self {
+            strategy,
+            start: Instant::now(),
+            backoff: strategy.min_backoff,
+        }
+    }
+}
+
+impl Iterator for Phases<'_> {
+    type Item = Option<Duration>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let elapsed = self.start.elapsed();
+        if elapsed < self.strategy.spin {
+            return Some(None);
+        }
+
+        let backoff_end = self.strategy.spin.saturating_add(self.strategy.backoff_for);
+        if elapsed >= backoff_end {
+            return None;
+        }
+
+        let sleep = self.backoff;
+        self.backoff = self
+            .backoff
+            .saturating_mul(2)
+            .min(self.strategy.max_backoff);
+        Some(Some(sleep))
+    }
+}
@@ -0,0 +1,108 @@
+//! Recycles buffers between runs, so dispatching the same kernel over and over does not allocate
+//! a new output buffer every time.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use crate::buffer::BufferSpec;
+
+/// How many free buffers of each size and usage are kept, beyond which released ones are dropped.
+const MAX_FREE_PER_CLASS: usize = 4;
+
+/// The size and usages of a buffer's spec, which a free buffer must match exactly to be reused.
+///
+/// Sizes are not rounded up, as working buffers are bound whole and readback buffers are mapped
+/// whole, so a larger one would change what the shader sees and what is read back.
+type SizeClass = (u64, wgpu::BufferUsages);
+
+fn size_class(spec: &BufferSpec) -> SizeClass {
+    (spec.size, spec.usages())
+}
+
+/// Free buffers, keyed by their size class.
+#[derive(Clone, Default)]
+pub(crate) struct BufferPool(Arc<Mutex<HashMap<SizeClass, Vec<wgpu::Buffer>>>>);
+
+impl BufferPool {
+    /// Takes a free buffer matching `spec`, or creates one if there is none.
+    ///
+    /// A reused buffer keeps the label and contents it had when released.
+    pub(crate) fn acquire(&self, device: &wgpu::Device, spec: &BufferSpec) -> wgpu::Buffer {
+        let mut free = self.0.lock().unwrap();
+        match free.get_mut(&size_class(spec)).and_then(Vec::pop) {
+            Some(buffer) => {
+                log::debug!("Reusing a {} byte buffer for {}", spec.size, spec.label);
+                buffer
+            }
+            None => device.create_buffer(&spec.descriptor()),
+        }
+    }
+
+    /// Returns `buffer`, acquired with `spec`, to the pool once nothing will read or write it
+    /// again.
+    pub(crate) fn release(&self, spec: &BufferSpec, buffer: wgpu::Buffer) {
+        let mut free = self.0.lock().unwrap();
+        let class = free.entry(size_class(spec)).or_default();
+        if class.len() < MAX_FREE_PER_CLASS {
+            class.push(buffer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BufferPool, MAX_FREE_PER_CLASS};
+    use crate::buffer::{BufferRole, BufferSpec};
+
+    fn device() -> wgpu::Device {
+        wgpu::Device::noop(&wgpu::DeviceDescriptor::default()).0
+    }
+
+    fn free(pool: &BufferPool) -> usize {
+        pool.0.lock().unwrap().values().map(Vec::len).sum()
+    }
+
+    #[test]
+    fn reuses_matching_buffers() {
+        let device = device();
+        let pool = BufferPool::default();
+        let spec = BufferSpec::new("output", 64).role(BufferRole::Readback);
+
+        let buffer = pool.acquire(&device, &spec);
+        pool.release(&spec, buffer.clone());
+        assert_eq!(pool.acquire(&device, &spec), buffer);
+        assert_eq!(free(&pool), 0);
+
+        // Only the same size and usages match, whatever the label.
+        pool.release(&spec, buffer.clone());
+        let larger = BufferSpec::new("output", 128).role(BufferRole::Readback);
+        assert_ne!(pool.acquire(&device, &larger), buffer);
+        let other_usages = BufferSpec::new("output", 64).role(BufferRole::Output);
+        assert_ne!(pool.acquire(&device, &other_usages), buffer);
+        let relabeled = BufferSpec::new("renamed", 64).role(BufferRole::Readback);
+        assert_eq!(pool.acquire(&device, &relabeled), buffer);
+    }
+
+    #[test]
+    fn caps_free_buffers() {
+        let device = device();
+        let pool = BufferPool::default();
+        let spec = BufferSpec::new("output", 64).role(BufferRole::Readback);
+
+        let buffers: Vec<_> = (0..MAX_FREE_PER_CLASS + 2)
+            .map(|_| pool.acquire(&device, &spec))
+            .collect();
+        for buffer in &buffers {
+            pool.release(&spec, buffer.clone());
+        }
+        assert_eq!(free(&pool), MAX_FREE_PER_CLASS);
+
+        // Other size classes have a cap of their own.
+        let other = BufferSpec::new("output", 128).role(BufferRole::Readback);
+        let buffer = pool.acquire(&device, &other);
+        pool.release(&other, buffer);
+        assert_eq!(free(&pool), MAX_FREE_PER_CLASS + 1);
+    }
+}
@@ -0,0 +1,287 @@
+//! Post-processing expressions evaluated over the decoded output, such as `mean(out)`.
+//!
+//! Expressions are arithmetic (`+ - * /`, parentheses, and number literals) over reductions of
+//! the output array `out`: `len`, `sum`, `mean`, `min`, `max`, `argmin`, `argmax`, and `std`.
+
+use std::fmt::{self, Display};
+
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid post-processing expression `{expression}` at column {}: {message}", position + 1)]
+pub struct PostError {
+    expression: String,
+    position: usize,
+    message: String,
+}
+
+#[derive(Clone, Copy)]
+pub enum Reduction {
+    Len,
+    Sum,
+    Mean,
+    Min,
+    Max,
+    ArgMin,
+    ArgMax,
+    Std,
+}
+
+impl Reduction {
+    const ALL: [(&str, Self); 8] = [
+        ("len", Self::Len),
+        ("sum", Self::Sum),
+        ("mean", Self::Mean),
+        ("min", Self::Min),
+        ("max", Self::Max),
+        ("argmin", Self::ArgMin),
+        ("argmax", Self::ArgMax),
+        ("std", Self::Std),
+    ];
+
+    pub fn apply(self, values: &[f64]) -> f64 {
+        let len = values.len() as f64;
+        let sum = || values.iter().sum::<f64>();
+        // Returns the index of the first value that `prefer` picks over all others, or NaN if empty.
+        let arg_by = |prefer: fn(f64, f64) -> bool| {
+            let mut best: Option<(usize, f64)> = None;
+            for (index, &value) in values.iter().enumerate() {
+                if best.is_none_or(|(_, best)| prefer(value, best)) {
+                    best = Some((index, value));
+                }
+            }
+
+            best.map_or(f64::NAN, |(index, _)| index as f64)
+        };
+
+        match self {
+            Self::Len => len,
+            Self::Sum => sum(),
+            Self::Mean => sum() / len,
+            Self::Min => values.iter().copied().reduce(f64::min).unwrap_or(f64::NAN),
+            Self::Max => values.iter().copied().reduce(f64::max).unwrap_or(f64::NAN),
+            Self::ArgMin => arg_by(|value, best| value < best),
+            Self::ArgMax => arg_by(|value, best| value > best),
+            Self::Std => {
+                let mean = sum() / len;
+                let variance = values
+                    .iter()
+                    .map(|value| (value - mean).powi(2))
+                    .sum::<f64>()
+                    / len;
+                variance.sqrt()
+            }
+        }
+    }
+}
+
+enum Node {
+    Number(f64),
+    Reduce(Reduction),
+    Negate(Box<Node>),
+    Binary(Box<Node>, u8, Box<Node>),
+}
+
+impl Node {
+    fn evaluate(&self, values: &[f64]) -> f64 {
+        match self {
+            Self::Number(value) => *value,
+            Self::Reduce(reduction) => reduction.apply(values),
+            Self::Negate(node) => -node.evaluate(values),
+            Self::Binary(left, operator, right) => {
+                let (left, right) = (left.evaluate(values), right.evaluate(values));
+                match operator {
+                    b'+' => left + right,
+                    b'-' => left - right,
+                    b'*' => left * right,
+                    b'/' => left / right,
+                    _ => unreachable!("parser only produces arithmetic operators"),
+                }
+            }
+        }
+    }
+}
+
+struct Parser<'a> {
+    expression: &'a str,
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: impl Into<String>) -> PostError {
+        PostError {
+            expression: self.expression.to_owned(),
+            position: self.position,
+            message: message.into(),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.expression[self.position..];
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.expression.as_bytes().get(self.position).copied()
+    }
+
+    fn expect(&mut self, expected: u8) -> Result<(), PostError> {
+        if self.peek() != Some(expected) {
+            return Err(self.error(format!("expected `{}`", expected as char)));
+        }
+
+        self.position += 1;
+        Ok(())
+    }
+
+    fn take_while(&mut self, predicate: impl Fn(u8) -> bool) -> &str {
+        let start = self.position;
+        let rest = &self.expression.as_bytes()[start..];
+        self.position += rest.iter().take_while(|&&byte| predicate(byte)).count();
+        &self.expression[start..self.position]
+    }
+
+    fn binary(
+        &mut self,
+        operators: &[u8],
+        operand: fn(&mut Self) -> Result<Node, PostError>,
+    ) -> Result<Node, PostError> {
+        let mut node = operand(self)?;
+        while let Some(operator) = self.peek().filter(|op| operators.contains(op)) {
+            self.position += 1;
+            node = Node::Binary(Box::new(node), operator, Box::new(operand(self)?));
+        }
+
+        Ok(node)
+    }
+
+    fn expression(&mut self) -> Result<Node, PostError> {
+        self.binary(b"+-", |parser| parser.binary(b"*/", Self::factor))
+    }
+
+    fn factor(&mut self) -> Result<Node, PostError> {
+        match self.peek() {
+            Some(b'-') => {
+                self.position += 1;
+                Ok(Node::Negate(Box::new(self.factor()?)))
+            }
+            Some(b'(') => {
+                self.position += 1;
+                let node = self.expression()?;
+                self.expect(b')')?;
+                Ok(node)
+            }
+            Some(byte) if byte.is_ascii_digit() || byte == b'.' => {
+                let start = self.position;
+                let literal = self.take_while(|byte| byte.is_ascii_digit() || byte == b'.');
+                match literal.parse() {
+                    Ok(value) => Ok(Node::Number(value)),
+                    Err(_) => {
+                        self.position = start;
+                        Err(self.error("invalid number"))
+                    }
+                }
+            }
+            Some(byte) if byte.is_ascii_alphabetic() => {
+                let start = self.position;
+                let name = self.take_while(|byte| byte.is_ascii_alphanumeric());
+                let Some(&(_, reduction)) = Reduction::ALL.iter().find(|(n, _)| *n == name) else {
+                    self.position = start;
+                    let names: Vec<_> = Reduction::ALL.iter().map(|(name, _)| *name).collect();
+                    return Err(self.error(format!("expected one of {}", names.join(", "))));
+                };
+
+                self.expect(b'(')?;
+                self.skip_whitespace();
+                let argument = self.position;
+                if self.take_while(|byte| byte.is_ascii_alphanumeric()) != "out" {
+                    self.position = argument;
+                    return Err(self.error("reductions can only be applied to `out`"));
+                }
+                self.expect(b')')?;
+
+                Ok(Node::Reduce(reduction))
+            }
+            Some(_) => Err(self.error("unexpected character")),
+            None => Err(self.error("unexpected end of expression")),
+        }
+    }
+}
+
+/// A parsed post-processing expression.
+pub struct PostExpression {
+    source: String,
+    root: Node,
+}
+
+impl PostExpression {
+    pub fn parse(expression: &str) -> Result<Self, PostError> {
+        let mut parser = Parser {
+            expression,
+            position: 0,
+        };
+
+        let root = parser.expression()?;
+        if parser.peek().is_some() {
+            return Err(parser.error("unexpected trailing input"));
+        }
+
+        Ok(Self {
+            source: expression.to_owned(),
+            root,
+        })
+    }
+
+    pub fn evaluate(&self, values: &[f64]) -> f64 {
+        self.root.evaluate(values)
+    }
+}
+
+impl Display for PostExpression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PostExpression;
+
+    fn evaluate(expression: &str, values: &[f64]) -> f64 {
+        PostExpression::parse(expression).unwrap().evaluate(values)
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(evaluate("1 + 2 * 3", &[]), 7.0);
+        assert_eq!(evaluate("(1 + 2) * 3", &[]), 9.0);
+        assert_eq!(evaluate("10 - 4 - 3", &[]), 3.0);
+        assert_eq!(evaluate("8 / 4 / 2", &[]), 1.0);
+        assert_eq!(evaluate("1 + 6 / 2 - 1", &[]), 3.0);
+    }
+
+    #[test]
+    fn unary_minus() {
+        assert_eq!(evaluate("-2 * 3", &[]), -6.0);
+        assert_eq!(evaluate("2 * -3", &[]), -6.0);
+        assert_eq!(evaluate("--1", &[]), 1.0);
+        assert_eq!(evaluate("-(1 + 2)", &[]), -3.0);
+        assert_eq!(evaluate("1 - -1", &[]), 2.0);
+    }
+
+    #[test]
+    fn reductions() {
+        let values = [1.0, 5.0, 3.0];
+        assert_eq!(evaluate("len(out)", &values), 3.0);
+        assert_eq!(evaluate("mean(out)", &values), 3.0);
+        assert_eq!(evaluate("max(out) - min(out)", &values), 4.0);
+        assert_eq!(evaluate("argmax(out) * 10 + argmin(out)", &values), 10.0);
+        assert!(evaluate("min(out)", &[]).is_nan());
+    }
+
+    #[test]
+    fn errors() {
+        for expression in ["", "1 +", "(1", "1 2", "median(out)", "sum(x)", "1.2.3"] {
+            assert!(PostExpression::parse(expression).is_err(), "{expression}");
+        }
+    }
+}
@@ -0,0 +1,140 @@
+//! Measures the energy a GPU uses, from the hwmon sensors Linux GPU drivers expose.
+//!
+//! Drivers either report a cumulative energy counter, which is read before and after, or only the
+//! current power draw, which is sampled on a background thread and integrated.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+const HWMON_ROOT: &str = "/sys/class/hwmon";
+
+/// The hwmon names of GPU drivers, which report power for the whole card.
+const GPU_DRIVERS: &[&str] = &["amdgpu", "i915", "xe", "nouveau"];
+
+/// How often a power sensor is sampled while measuring.
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(10);
+
+enum Reading {
+    /// A counter of microjoules used since boot.
+    Energy(PathBuf),
+    /// The current power draw, in microwatts.
+    Power(PathBuf),
+}
+
+/// A GPU's hwmon sensor.
+pub struct Sensor {
+    /// The driver the sensor belongs to.
+    pub driver: String,
+    reading: Reading,
+}
+
+fn read_micro(path: &Path) -> Option<u64> {
+    std::fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+impl Sensor {
+    /// Finds the first GPU sensor that reports energy or power.
+    pub fn find() -> Option<Self> {
+        let mut sensors: Vec<_> = std::fs::read_dir(HWMON_ROOT)
+            .ok()?
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .collect();
+        sensors.sort();
+
+        sensors.into_iter().find_map(|dir| {
+            let driver = std::fs::read_to_string(dir.join("name")).ok()?;
+            let driver = driver.trim();
+            if !GPU_DRIVERS.contains(&driver) {
+                return None;
+            }
+
+            let reading = ["energy1_input", "power1_average", "power1_input"]
+                .into_iter()
+                .map(|file| dir.join(file))
+                .find(|path| read_micro(path).is_some())?;
+
+            let reading = match reading.file_name().and_then(|name| name.to_str()) {
+                Some("energy1_input") => Reading::Energy(reading),
+                _ => Reading::Power(reading),
+            };
+
+            Some(Self {
+                driver: driver.to_owned(),
+                reading,
+            })
+        })
+    }
+
+    /// Starts measuring the energy used until [`Meter::stop`].
+    pub fn start(&self) -> Meter {
+        match &self.reading {
+            Reading::Energy(path) => Meter::Energy {
+                path: path.clone(),
+                start: read_micro(path).unwrap_or_default(),
+            },
+            Reading::Power(path) => {
+                let path = path.clone();
+                let stop = Arc::new(AtomicBool::new(false));
+                let sampler = std::thread::spawn({
+                    let stop = Arc::clone(&stop);
+                    move || integrate_power(&path, &stop)
+                });
+
+                Meter::Power { stop, sampler }
+            }
+        }
+    }
+}
+
+/// Samples the power at `path` until `stop` is set, returning the joules used meanwhile.
+fn integrate_power(path: &Path, stop: &AtomicBool) -> f64 {
+    let mut joules = 0.0;
+    let mut last = Instant::now();
+    while !stop.load(Ordering::Relaxed) {
+        std::thread::sleep(SAMPLE_INTERVAL);
+
+        let now = Instant::now();
+        if let Some(micro_watts) = read_micro(path) {
+            joules += micro_watts as f64 / 1e6 * (now - last).as_secs_f64();
+        }
+
+        last = now;
+    }
+
+    joules
+}
+
+/// An energy measurement in progress.
+pub enum Meter {
+    Energy {
+        path: PathBuf,
+        start: u64,
+    },
+    Power {
+        stop: Arc<AtomicBool>,
+        sampler: JoinHandle<f64>,
+    },
+}
+
+impl Meter {
+    /// Stops measuring, returning the joules used since [`Sensor::start`].
+    pub fn stop(self) -> f64 {
+        match self {
+            Self::Energy { path, start } => {
+                let end = read_micro(&path).unwrap_or(start);
+                end.saturating_sub(start) as f64 / 1e6
+            }
+            Self::Power { stop, sampler } => {
+                stop.store(true, Ordering::Relaxed);
+                sampler.join().expect("sampling power should not panic")
+            }
+        }
+    }
+}
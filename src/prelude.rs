@@ -0,0 +1,10 @@
+//! The types most programs need, for glob importing with `use gpu_scratch::prelude::*`.
+
+pub use crate::{
+    AdapterFilter, AdapterSelection, ComputeError, Event, GpuContext, InitializeError, MathProfile,
+    RunError, ShaderRun, compute, compute_file,
+    pipeline::Pipeline,
+    plan::{Budget, BufferInit, DispatchSize, Plan, PlanError},
+    reflect::{ReflectError, Reflection},
+    shader::{Language, Shader},
+};
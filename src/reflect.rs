@@ -0,0 +1,430 @@
+//! Static analysis of shader modules via naga reflection.
+
+use naga::valid::{Capabilities, ModuleInfo, ValidationError, ValidationFlags, Validator};
+
+use crate::{cache::ArtifactCache, shader::Shader};
+
+/// The kind of artifact cache entry recording that a module passed validation.
+const VALIDATED_KIND: &str = "validated";
+
+/// Private memory per invocation above which a kernel is likely to spill registers.
+///
+/// Not exposed by any backend, so this is a conservative guess that holds for most desktop GPUs.
+const PRIVATE_MEMORY_WARN_BYTES: u32 = 1024;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReflectError {
+    #[error("Unable to parse shader: {0}")]
+    Parse(#[from] Box<naga::front::wgsl::ParseError>),
+    #[error("Unable to parse SPIR-V shader: {0}")]
+    ParseSpirV(#[from] naga::front::spv::Error),
+    #[error("Unable to parse GLSL shader: {0}")]
+    ParseGlsl(#[from] naga::front::glsl::ParseErrors),
+    #[error("Unable to validate shader: {0}")]
+    Validation(#[from] Box<naga::WithSpan<ValidationError>>),
+}
+
+/// Memory used by a compute entry point, in bytes.
+pub struct EntryPointMemory {
+    pub name: String,
+    pub workgroup_size: [u32; 3],
+    /// `var<workgroup>` storage shared by each workgroup.
+    pub workgroup_bytes: u32,
+    /// `var<private>` and function-local storage of each invocation.
+    ///
+    /// This is an estimate of register pressure, as locals of called functions are not counted.
+    pub private_bytes: u32,
+}
+
+/// How an entry point's workgroups map onto the adapter's subgroups (warps or wavefronts).
+pub struct SubgroupOccupancy {
+    pub subgroup_size: u32,
+    pub subgroups_per_workgroup: u32,
+    /// The fraction of subgroup lanes that run an invocation, which is below 1 when the
+    /// workgroup size is not a multiple of the subgroup size.
+    pub lane_utilization: f64,
+}
+
+/// The kind of resource a shader binding declares.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BindingKind {
+    Storage {
+        read_only: bool,
+    },
+    Uniform,
+    /// A texture, sampler, or other resource that plans cannot bind, named for error messages.
+    Other(&'static str),
+}
+
+/// A resource the shader declares with `@group` and `@binding`.
+pub struct ShaderBinding {
+    pub name: String,
+    pub binding: naga::ResourceBinding,
+    pub kind: BindingKind,
+    /// The size of the declared type in bytes, or `None` if it ends in a runtime-sized array.
+    pub size: Option<u64>,
+}
+
+/// A pipeline-overridable constant the shader declares with `override`.
+pub struct ShaderOverride {
+    pub name: String,
+    /// What wgpu looks its value up by: the `@id` if it has one, or else its name.
+    pub key: String,
+    /// Whether the shader gives it a default value, so that it does not need to be set.
+    pub has_default: bool,
+}
+
+/// What wgpu looks the value of `constant` up by, or `None` if it has neither an `@id` nor a name.
+fn override_key(constant: &naga::Override) -> Option<String> {
+    match (constant.id, &constant.name) {
+        (Some(id), _) => Some(id.to_string()),
+        (None, name) => name.clone(),
+    }
+}
+
+/// The `var<push_constant>` a shader declares, of which it can only have one.
+pub struct ShaderPushConstant {
+    pub name: String,
+    /// The size of the declared type in bytes.
+    pub size: u32,
+}
+
+pub struct Reflection {
+    module: naga::Module,
+    info: ModuleInfo,
+}
+
+impl Reflection {
+    pub fn new<'a>(shader: impl Into<Shader<'a>>) -> Result<Self, ReflectError> {
+        Self::validate(&shader.into(), ValidationFlags::all())
+    }
+
+    /// Like [`Reflection::new`], but skips naga's validation checks if `cache` records that the
+    /// shader already passed them, recording it otherwise.
+    ///
+    /// The module is still analyzed, which reflection needs, and wgpu validates it again when
+    /// creating the shader module.
+    pub fn with_cache<'a>(
+        shader: impl Into<Shader<'a>>,
+        cache: &ArtifactCache,
+    ) -> Result<Self, ReflectError> {
+        let shader = shader.into();
+        let key = ArtifactCache::module_key(&shader);
+        if cache.load(VALIDATED_KIND, &key).is_some() {
+            log::debug!("Skipping validation of a cached module");
+            return Self::validate(&shader, ValidationFlags::empty());
+        }
+
+        let reflection = Self::validate(&shader, ValidationFlags::all())?;
+        if let Err(err) = cache.store(VALIDATED_KIND, &key, &[]) {
+            log::warn!("Unable to record shader validation: {err}");
+        }
+
+        Ok(reflection)
+    }
+
+    fn validate(shader: &Shader, flags: ValidationFlags) -> Result<Self, ReflectError> {
+        let module = shader.parse()?;
+        let info = Validator::new(flags, Capabilities::all())
+            .validate(&module)
+            .map_err(Box::new)?;
+
+        Ok(Self { module, info })
+    }
+
+    /// Returns whether the storage buffer at `binding` is declared as `var<storage, read>`, or
+    /// `None` if there is no storage buffer at `binding`.
+    ///
+    /// wgpu requires the layout to match the declared access exactly, so this is what the
+    /// `read_only` flag of the binding's layout entry must be set to.
+    pub fn storage_read_only(&self, binding: &naga::ResourceBinding) -> Option<bool> {
+        self.module.global_variables.iter().find_map(|(_, global)| {
+            let naga::AddressSpace::Storage { access } = global.space else {
+                return None;
+            };
+
+            (global.binding.as_ref() == Some(binding))
+                .then_some(!access.contains(naga::StorageAccess::STORE))
+        })
+    }
+
+    /// Lists every resource the shader declares, in declaration order.
+    pub fn bindings(&self) -> Vec<ShaderBinding> {
+        let globals = self.module.global_variables.iter();
+        globals
+            .filter_map(|(_, global)| {
+                let kind = match global.space {
+                    naga::AddressSpace::Storage { access } => BindingKind::Storage {
+                        read_only: !access.contains(naga::StorageAccess::STORE),
+                    },
+                    naga::AddressSpace::Uniform => BindingKind::Uniform,
+                    naga::AddressSpace::Handle => BindingKind::Other(self.resource_name(global.ty)),
+                    _ => return None,
+                };
+
+                let size = self.module.types[global.ty]
+                    .inner
+                    .size(self.module.to_ctx());
+                Some(ShaderBinding {
+                    name: global
+                        .name
+                        .clone()
+                        .unwrap_or_else(|| String::from("<unnamed>")),
+                    binding: global.binding?,
+                    kind,
+                    size: (!self.runtime_sized(global.ty)).then_some(size.into()),
+                })
+            })
+            .collect()
+    }
+
+    /// Lists every pipeline-overridable constant the shader declares, in declaration order.
+    pub fn overrides(&self) -> Vec<ShaderOverride> {
+        let overrides = self.module.overrides.iter();
+        overrides
+            .filter_map(|(_, constant)| {
+                Some(ShaderOverride {
+                    key: override_key(constant)?,
+                    name: constant.name.clone()?,
+                    has_default: constant.init.is_some(),
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the `var<push_constant>` the shader declares, if any.
+    pub fn push_constant(&self) -> Option<ShaderPushConstant> {
+        let (_, global) = (self.module.global_variables.iter())
+            .find(|(_, global)| global.space == naga::AddressSpace::PushConstant)?;
+
+        Some(ShaderPushConstant {
+            name: (global.name.clone()).unwrap_or_else(|| String::from("<unnamed>")),
+            size: self.module.types[global.ty]
+                .inner
+                .size(self.module.to_ctx()),
+        })
+    }
+
+    /// Returns the workgroup size of the entry point called `name`, with any dimensions given by
+    /// `override` constants evaluated using `constants`, by key, or else their defaults.
+    pub fn workgroup_size(&self, name: &str, constants: &[(String, f64)]) -> Option<[u32; 3]> {
+        let entry_point = (self.module.entry_points.iter()).find(|e| e.name == name)?;
+        Some(self.resolve_workgroup_size(entry_point, constants))
+    }
+
+    /// Dimensions given by expressions that cannot be evaluated keep the size naga reports for
+    /// them, which is 1.
+    fn resolve_workgroup_size(
+        &self,
+        entry_point: &naga::EntryPoint,
+        constants: &[(String, f64)],
+    ) -> [u32; 3] {
+        let mut workgroup_size = entry_point.workgroup_size;
+        let Some(overrides) = entry_point.workgroup_size_overrides else {
+            return workgroup_size;
+        };
+
+        for (size, expression) in workgroup_size.iter_mut().zip(overrides) {
+            if let Some(value) = expression.and_then(|e| self.evaluate_u32(e, constants)) {
+                *size = value;
+            }
+        }
+
+        workgroup_size
+    }
+
+    /// Evaluates the global expression `expression` as a `u32`, if it is built from integer
+    /// literals, constants, and overrides with basic arithmetic.
+    fn evaluate_u32(
+        &self,
+        expression: naga::Handle<naga::Expression>,
+        constants: &[(String, f64)],
+    ) -> Option<u32> {
+        match self.module.global_expressions[expression] {
+            naga::Expression::Literal(naga::Literal::U32(value)) => Some(value),
+            naga::Expression::Literal(naga::Literal::I32(value)) => value.try_into().ok(),
+            naga::Expression::Literal(naga::Literal::AbstractInt(value)) => value.try_into().ok(),
+            naga::Expression::Constant(constant) => {
+                self.evaluate_u32(self.module.constants[constant].init, constants)
+            }
+            naga::Expression::Override(constant) => {
+                let constant = &self.module.overrides[constant];
+                let key = override_key(constant)?;
+                match constants.iter().find(|(k, _)| *k == key) {
+                    Some(&(_, value)) => (value.fract() == 0.0
+                        && (0.0..=f64::from(u32::MAX)).contains(&value))
+                    .then_some(value as u32),
+                    None => self.evaluate_u32(constant.init?, constants),
+                }
+            }
+            naga::Expression::Binary { op, left, right } => {
+                let left = self.evaluate_u32(left, constants)?;
+                let right = self.evaluate_u32(right, constants)?;
+                match op {
+                    naga::BinaryOperator::Add => left.checked_add(right),
+                    naga::BinaryOperator::Subtract => left.checked_sub(right),
+                    naga::BinaryOperator::Multiply => left.checked_mul(right),
+                    naga::BinaryOperator::Divide => left.checked_div(right),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the member names of the struct declared at `binding`, if it is a struct.
+    pub fn member_names(&self, binding: &naga::ResourceBinding) -> Option<Vec<String>> {
+        let (_, global) = (self.module.global_variables.iter())
+            .find(|(_, global)| global.binding.as_ref() == Some(binding))?;
+        let naga::TypeInner::Struct { members, .. } = &self.module.types[global.ty].inner else {
+            return None;
+        };
+
+        let names = members.iter().enumerate().map(|(index, member)| {
+            (member.name.clone()).unwrap_or_else(|| format!("member {index}"))
+        });
+        Some(names.collect())
+    }
+
+    fn resource_name(&self, ty: naga::Handle<naga::Type>) -> &'static str {
+        match self.module.types[ty].inner {
+            naga::TypeInner::Image {
+                class: naga::ImageClass::Storage { .. },
+                ..
+            } => "storage texture",
+            naga::TypeInner::Image { .. } => "texture",
+            naga::TypeInner::Sampler { .. } => "sampler",
+            naga::TypeInner::AccelerationStructure { .. } => "acceleration structure",
+            naga::TypeInner::BindingArray { .. } => "binding array",
+            _ => "resource",
+        }
+    }
+
+    fn runtime_sized(&self, ty: naga::Handle<naga::Type>) -> bool {
+        match &self.module.types[ty].inner {
+            naga::TypeInner::Array {
+                size: naga::ArraySize::Dynamic,
+                ..
+            } => true,
+            naga::TypeInner::Struct { members, .. } => {
+                (members.last()).is_some_and(|member| self.runtime_sized(member.ty))
+            }
+            _ => false,
+        }
+    }
+
+    /// Logs a warning for every `read_write` storage buffer that is never written to, as it
+    /// could be declared `read` to let drivers optimize around it.
+    pub fn warn_on_unwritten_storage(&self) {
+        for (handle, global) in self.module.global_variables.iter() {
+            let naga::AddressSpace::Storage { access } = global.space else {
+                continue;
+            };
+
+            let written = (0..self.module.entry_points.len()).any(|index| {
+                let function_info = self.info.get_entry_point(index);
+                function_info[handle].contains(naga::valid::GlobalUse::WRITE)
+            });
+
+            if access.contains(naga::StorageAccess::STORE) && !written {
+                let name = global.name.as_deref().unwrap_or("<unnamed>");
+                log::warn!(
+                    "Storage buffer `{name}` is declared read_write but never written, \
+                     consider declaring it as var<storage, read>"
+                );
+            }
+        }
+    }
+
+    /// Reports the memory used by every compute entry point in the module.
+    pub fn memory_usage(&self) -> Vec<EntryPointMemory> {
+        let mut layouter = naga::proc::Layouter::default();
+        layouter
+            .update(self.module.to_ctx())
+            .expect("validated module should have a valid layout");
+
+        let entry_points = self.module.entry_points.iter().enumerate();
+        let compute_entry_points =
+            entry_points.filter(|(_, entry_point)| entry_point.stage == naga::ShaderStage::Compute);
+
+        compute_entry_points
+            .map(|(index, entry_point)| {
+                let function_info = self.info.get_entry_point(index);
+
+                let (mut workgroup_bytes, mut private_bytes) = (0, 0);
+                for (handle, global) in self.module.global_variables.iter() {
+                    if function_info[handle].is_empty() {
+                        continue;
+                    }
+
+                    let size = layouter[global.ty].size;
+                    match global.space {
+                        naga::AddressSpace::WorkGroup => workgroup_bytes += size,
+                        naga::AddressSpace::Private => private_bytes += size,
+                        _ => {}
+                    }
+                }
+
+                for (_, local) in entry_point.function.local_variables.iter() {
+                    private_bytes += layouter[local.ty].size;
+                }
+
+                EntryPointMemory {
+                    name: entry_point.name.clone(),
+                    workgroup_size: self.resolve_workgroup_size(entry_point, &[]),
+                    workgroup_bytes,
+                    private_bytes,
+                }
+            })
+            .collect()
+    }
+}
+
+impl EntryPointMemory {
+    /// Estimates subgroup occupancy from the subgroup sizes reported by the adapter.
+    ///
+    /// Returns `None` on backends that do not report subgroup sizes, such as GL.
+    pub fn subgroup_occupancy(&self, adapter_limits: &wgpu::Limits) -> Option<SubgroupOccupancy> {
+        // Drivers may pick any size in the range, so assume the widest for a worst case estimate.
+        let subgroup_size = adapter_limits.max_subgroup_size;
+        if subgroup_size == 0 {
+            return None;
+        }
+
+        let invocations: u32 = self.workgroup_size.iter().product();
+        let subgroups_per_workgroup = invocations.div_ceil(subgroup_size);
+        let lanes = subgroups_per_workgroup * subgroup_size;
+
+        Some(SubgroupOccupancy {
+            subgroup_size,
+            subgroups_per_workgroup,
+            lane_utilization: f64::from(invocations) / f64::from(lanes),
+        })
+    }
+
+    /// Logs a warning if this entry point's memory footprint will limit occupancy on a device
+    /// with `limits`.
+    pub fn warn_on_low_occupancy(&self, limits: &wgpu::Limits) {
+        let workgroup_limit = limits.max_compute_workgroup_storage_size;
+
+        // Compute units usually have about as much shared memory as a single workgroup may use,
+        // so past half of the limit at most one workgroup can be resident at a time.
+        if self.workgroup_bytes > workgroup_limit / 2 {
+            log::warn!(
+                "Entry point `{}` uses {} of {workgroup_limit} bytes of workgroup memory, \
+                 so likely only one workgroup can run per compute unit",
+                self.name,
+                self.workgroup_bytes,
+            );
+        }
+
+        if self.private_bytes > PRIVATE_MEMORY_WARN_BYTES {
+            log::warn!(
+                "Entry point `{}` uses {} bytes of private memory per invocation, \
+                 which will likely spill out of registers",
+                self.name,
+                self.private_bytes,
+            );
+        }
+    }
+}
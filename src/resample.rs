@@ -0,0 +1,81 @@
+//! Resamples grids of `f32`s to new resolutions on the GPU, interpolating linearly along each
+//! axis, so 2D grids are filtered bilinearly and 3D grids trilinearly.
+//!
+//! Grids are stored x-fastest, then y, then z. A 1D or 2D grid is a 3D grid with a size of 1
+//! along the axes it lacks, such as `[width, height, 1]`.
+
+use crate::{
+    GpuContext, MathProfile, RunError,
+    plan::{Budget, DispatchSize, Plan, PlanError},
+    reflect::{ReflectError, Reflection},
+};
+
+const SOURCE: &str = include_str!("resample.wgsl");
+
+/// The workgroup size of the kernel along every axis.
+const WORKGROUP_SIZE: u32 = 4;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ResampleError {
+    #[error("Grid sizes must be at least 1 along every axis, but got {0:?}")]
+    EmptyGrid([u32; 3]),
+    #[error("A {size:?} grid holds {expected} values, but {actual} were given")]
+    InputSize {
+        size: [u32; 3],
+        expected: u64,
+        actual: usize,
+    },
+    #[error(transparent)]
+    Reflect(#[from] ReflectError),
+    #[error(transparent)]
+    Plan(#[from] PlanError),
+    #[error(transparent)]
+    Run(#[from] RunError),
+}
+
+fn cells(size: [u32; 3]) -> u64 {
+    size.iter().map(|&n| u64::from(n)).product()
+}
+
+impl GpuContext {
+    /// Resamples `input`, a grid of `source` cells along x, y, and z, to `target` cells.
+    ///
+    /// The centers of the source and target cells are aligned, and samples past the edges of
+    /// the source grid are clamped to them.
+    pub async fn resample(
+        &self,
+        input: &[f32],
+        source: [u32; 3],
+        target: [u32; 3],
+    ) -> Result<Vec<f32>, ResampleError> {
+        for size in [source, target] {
+            if size.contains(&0) {
+                return Err(ResampleError::EmptyGrid(size));
+            }
+        }
+        if input.len() as u64 != cells(source) {
+            return Err(ResampleError::InputSize {
+                size: source,
+                expected: cells(source),
+                actual: input.len(),
+            });
+        }
+
+        let reflection = Reflection::new(SOURCE)?;
+        let mut plan = Plan::new(&reflection, cells(target) * size_of::<f32>() as u64);
+        plan.add_input(&reflection, bytemuck::cast_slice(input).to_vec());
+        // The kernel's `Sizes` uniform, with each `vec3u` padded to 16 bytes.
+        let [sx, sy, sz] = source;
+        let [tx, ty, tz] = target;
+        plan.set_params(&reflection, &[sx, sy, sz, 0, tx, ty, tz, 0]);
+        plan.bind_remaining(&reflection)?;
+
+        let [x, y, z] = target.map(|n| n.div_ceil(WORKGROUP_SIZE));
+        plan.dispatch = DispatchSize { x, y, z };
+        plan.zero_copy = self.supports_zero_copy();
+        plan.check(&self.device.limits(), &Budget::default())?;
+
+        let shader_run = self.run_shader(SOURCE, &plan, MathProfile::Strict, None)?;
+        Ok(self.read_back_async(&shader_run.output).await?)
+    }
+}
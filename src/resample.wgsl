@@ -0,0 +1,45 @@
+// Resamples a grid of f32s stored x-fastest to a new resolution, interpolating linearly along
+// every axis. 1D and 2D grids are 3D grids with a size of 1 along their missing axes.
+
+struct Sizes {
+    input: vec3u,
+    output: vec3u,
+}
+
+@group(0) @binding(0)
+var<storage, read_write> output: array<f32>;
+
+@group(0) @binding(1)
+var<storage, read> input: array<f32>;
+
+@group(0) @binding(2)
+var<uniform> sizes: Sizes;
+
+fn sample(position: vec3u) -> f32 {
+    let index = (position.z * sizes.input.y + position.y) * sizes.input.x + position.x;
+    return input[index];
+}
+
+@compute @workgroup_size(4, 4, 4)
+fn main(@builtin(global_invocation_id) id: vec3u) {
+    if any(id >= sizes.output) {
+        return;
+    }
+
+    // Align the centers of the source and target cells, clamping at the edges.
+    let scale = vec3f(sizes.input) / vec3f(sizes.output);
+    let last = vec3f(sizes.input - 1u);
+    let position = clamp((vec3f(id) + 0.5) * scale - 0.5, vec3f(0.0), last);
+
+    let low = vec3u(floor(position));
+    let high = min(low + 1u, sizes.input - 1u);
+    let t = position - vec3f(low);
+
+    let c00 = mix(sample(low), sample(vec3u(high.x, low.y, low.z)), t.x);
+    let c10 = mix(sample(vec3u(low.x, high.y, low.z)), sample(vec3u(high.x, high.y, low.z)), t.x);
+    let c01 = mix(sample(vec3u(low.x, low.y, high.z)), sample(vec3u(high.x, low.y, high.z)), t.x);
+    let c11 = mix(sample(vec3u(low.x, high.y, high.z)), sample(high), t.x);
+    let value = mix(mix(c00, c10, t.y), mix(c01, c11, t.y), t.z);
+
+    output[(id.z * sizes.output.y + id.y) * sizes.output.x + id.x] = value;
+}
@@ -0,0 +1,181 @@
+//! `selftest`, which runs tiny built-in kernels on the selected adapter and reports which
+//! capabilities work, to rule out the driver or setup before debugging a shader.
+
+use std::error::Error;
+
+use gpu_scratch::{
+    GpuContext, MathProfile,
+    plan::{Budget, DispatchSize, Plan},
+    reflect::Reflection,
+    texture::TexelEncoding,
+};
+use wgpu::util::DeviceExt as _;
+
+use crate::{CheckError, adapter_selection, cli};
+
+/// The number of words the copy and add kernels run over.
+const WORDS: u32 = 64;
+
+/// The number of workgroups the atomic count kernel dispatches, of 64 invocations each.
+const COUNT_WORKGROUPS: u32 = 4;
+
+const COPY_SOURCE: &str = "
+@group(0) @binding(0) var<storage, read_write> output: array<u32>;
+@group(0) @binding(1) var<storage, read> input: array<u32>;
+
+@compute @workgroup_size(64)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    output[id.x] = input[id.x];
+}
+";
+
+const ADD_SOURCE: &str = "
+@group(0) @binding(0) var<storage, read_write> output: array<u32>;
+@group(0) @binding(1) var<storage, read> a: array<u32>;
+@group(0) @binding(2) var<storage, read> b: array<u32>;
+
+@compute @workgroup_size(64)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    output[id.x] = a[id.x] + b[id.x];
+}
+";
+
+const COUNT_SOURCE: &str = "
+@group(0) @binding(0) var<storage, read_write> count: atomic<u32>;
+
+@compute @workgroup_size(64)
+fn main() {
+    atomicAdd(&count, 1u);
+}
+";
+
+/// Runs the WGSL `source` over `inputs` with `workgroups` workgroups in x, returning its
+/// `output_size` bytes of output as words.
+async fn run_kernel(
+    gpu: &GpuContext,
+    source: &str,
+    inputs: &[Vec<u32>],
+    output_size: u64,
+    workgroups: u32,
+) -> Result<Vec<u32>, Box<dyn Error>> {
+    let reflection = Reflection::new(source)?;
+    let mut plan = Plan::new(&reflection, output_size);
+    plan.set_entry_point(&reflection, None)?;
+    for input in inputs {
+        plan.add_input(&reflection, bytemuck::cast_slice(input).to_vec());
+    }
+    plan.bind_remaining(&reflection)?;
+    plan.dispatch = DispatchSize {
+        x: workgroups,
+        ..DispatchSize::ONE
+    };
+    plan.check(&gpu.device.limits(), &Budget::default())?;
+
+    let shader_run = gpu.run_shader(source, &plan, MathProfile::Strict, None)?;
+    let words = gpu.read_back_async(&shader_run.output).await?;
+    gpu.recycle(shader_run);
+    Ok(words)
+}
+
+/// Fails unless `actual` is `expected`, describing the first difference.
+fn expect<T: PartialEq + std::fmt::Debug>(
+    expected: &[T],
+    actual: &[T],
+) -> Result<(), Box<dyn Error>> {
+    if actual.len() != expected.len() {
+        return Err(format!("expected {} values, got {}", expected.len(), actual.len()).into());
+    }
+
+    match (expected.iter().zip(actual)).position(|(expected, actual)| expected != actual) {
+        Some(index) => Err(format!(
+            "value {index} is {:?}, expected {:?}",
+            actual[index], expected[index]
+        )
+        .into()),
+        None => Ok(()),
+    }
+}
+
+async fn check_copy(gpu: &GpuContext) -> Result<(), Box<dyn Error>> {
+    let input: Vec<u32> = (0..WORDS).map(|word| word * 7 + 1).collect();
+    let output = run_kernel(
+        gpu,
+        COPY_SOURCE,
+        std::slice::from_ref(&input),
+        (WORDS * 4).into(),
+        1,
+    )
+    .await?;
+    expect(&input, &output)
+}
+
+async fn check_add(gpu: &GpuContext) -> Result<(), Box<dyn Error>> {
+    let a: Vec<u32> = (0..WORDS).collect();
+    let b: Vec<u32> = (0..WORDS).map(|word| 1000 - word * 3).collect();
+    let expected: Vec<u32> = a.iter().zip(&b).map(|(a, b)| a + b).collect();
+
+    let output = run_kernel(gpu, ADD_SOURCE, &[a, b], (WORDS * 4).into(), 1).await?;
+    expect(&expected, &output)
+}
+
+async fn check_count(gpu: &GpuContext) -> Result<(), Box<dyn Error>> {
+    let output = run_kernel(gpu, COUNT_SOURCE, &[], 4, COUNT_WORKGROUPS).await?;
+    expect(&[COUNT_WORKGROUPS * 64], &output)
+}
+
+/// Writes texels to a storage texture with a shader, then loads them back into a buffer.
+async fn check_texture(gpu: &GpuContext) -> Result<(), Box<dyn Error>> {
+    const SIZE: [u32; 2] = [8, 8];
+
+    let texels: Vec<f32> = (0..SIZE[0] * SIZE[1]).map(|i| i as f32 * 0.5).collect();
+    let buffer = gpu
+        .device
+        .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("selftest-texels"),
+            contents: bytemuck::cast_slice(&texels),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+
+    let format = wgpu::TextureFormat::R32Float;
+    let texture = gpu.buffer_to_texture(&buffer, SIZE, format, TexelEncoding::F32)?;
+    let loaded = gpu.texture_to_buffer(&texture, TexelEncoding::F32)?;
+
+    let mut data = Vec::new();
+    gpu.read_chunked(&loaded, loaded.size(), &mut data).await?;
+    expect(&texels, &bytemuck::pod_collect_to_vec(&data))
+}
+
+/// Runs every check on the adapter the flags select, printing whether each passed, and fails if
+/// any did not.
+pub async fn selftest(matches: &cli::Matches) -> Result<(), Box<dyn Error>> {
+    let gpu = GpuContext::with_selection(&adapter_selection(matches)?).await?;
+    let info = gpu.adapter.get_info();
+    println!("Self-test on {} ({:?})", info.name, info.backend);
+
+    // Each check runs even if an earlier one failed, so every broken capability is reported.
+    let results = [
+        ("copy", check_copy(&gpu).await),
+        ("add", check_add(&gpu).await),
+        ("atomic count", check_count(&gpu).await),
+        ("texture write", check_texture(&gpu).await),
+    ];
+
+    let mut failed = 0;
+    for (name, result) in &results {
+        match result {
+            Ok(()) => println!("  pass  {name}"),
+            Err(err) => {
+                println!("  FAIL  {name}: {err}");
+                failed += 1;
+            }
+        }
+    }
+
+    let total = results.len();
+    println!("{} of {total} checks passed", total - failed);
+    if failed > 0 {
+        return Err(CheckError::SelfTestFailed { failed, total }.into());
+    }
+
+    Ok(())
+}
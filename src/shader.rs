@@ -0,0 +1,167 @@
+//! Shader code in any of the languages the library compiles, so kernels built by other
+//! toolchains can run without being ported to WGSL.
+
+use std::{borrow::Cow, io, path::Path};
+
+use crate::{RunError, reflect::ReflectError};
+
+/// The language of a [`Shader`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Language {
+    Wgsl,
+    SpirV,
+    /// A GLSL compute shader, translated by naga's GLSL front-end.
+    Glsl,
+}
+
+impl Language {
+    /// The name of every language, as [`Language::from_name`] accepts them.
+    pub const NAMES: &[&str] = &["wgsl", "spirv", "glsl"];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "wgsl" => Some(Self::Wgsl),
+            "spirv" => Some(Self::SpirV),
+            "glsl" => Some(Self::Glsl),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Wgsl => "wgsl",
+            Self::SpirV => "spirv",
+            Self::Glsl => "glsl",
+        }
+    }
+
+    /// The language implied by the extension of `path`, which is WGSL unless it is `.spv` or
+    /// `.comp`.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("spv") => Self::SpirV,
+            Some("comp") => Self::Glsl,
+            _ => Self::Wgsl,
+        }
+    }
+}
+
+/// The code of a shader, borrowed or owned.
+///
+/// Anything taking `impl Into<Shader>` also takes WGSL as a `&str`, or a `&Shader` to borrow it.
+#[derive(Clone, Debug)]
+pub enum Shader<'a> {
+    Wgsl(Cow<'a, str>),
+    /// A SPIR-V binary, as compiled by `glslc` or `dxc -spirv`.
+    SpirV(Cow<'a, [u8]>),
+    /// The source of a GLSL compute shader, like a `.comp` file.
+    Glsl(Cow<'a, str>),
+}
+
+impl Shader<'static> {
+    /// Reads the shader at `path` as `language`, or if `None`, the language its extension implies.
+    pub fn read(path: &Path, language: Option<Language>) -> io::Result<Self> {
+        let shader = match language.unwrap_or_else(|| Language::from_path(path)) {
+            Language::Wgsl => Self::Wgsl(Cow::Owned(std::fs::read_to_string(path)?)),
+            Language::SpirV => Self::SpirV(Cow::Owned(std::fs::read(path)?)),
+            Language::Glsl => Self::Glsl(Cow::Owned(std::fs::read_to_string(path)?)),
+        };
+
+        Ok(shader)
+    }
+}
+
+impl Shader<'_> {
+    pub fn language(&self) -> Language {
+        match self {
+            Self::Wgsl(_) => Language::Wgsl,
+            Self::SpirV(_) => Language::SpirV,
+            Self::Glsl(_) => Language::Glsl,
+        }
+    }
+
+    /// The shader's code as it was given, for hashing.
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Self::Wgsl(source) | Self::Glsl(source) => source.as_bytes(),
+            Self::SpirV(binary) => binary,
+        }
+    }
+
+    pub(crate) fn parse(&self) -> Result<naga::Module, ReflectError> {
+        Ok(match self {
+            Self::Wgsl(source) => naga::front::wgsl::parse_str(source).map_err(Box::new)?,
+            Self::SpirV(binary) => {
+                naga::front::spv::parse_u8_slice(binary, &naga::front::spv::Options::default())?
+            }
+            Self::Glsl(source) => naga::front::glsl::Frontend::default()
+                .parse(&naga::ShaderStage::Compute.into(), source)?,
+        })
+    }
+
+    /// The source to create the shader module from, with its `var<push_constant>` declared as
+    /// a `var<uniform>` at `binding` instead, for devices without push constants.
+    pub(crate) fn module_source_with_uniform_push_constants(
+        &self,
+        binding: naga::ResourceBinding,
+    ) -> Result<wgpu::ShaderSource<'static>, RunError> {
+        let mut module = self
+            .parse()
+            .map_err(|err| RunError::Compile(err.to_string()))?;
+        for (_, global) in module.global_variables.iter_mut() {
+            if global.space == naga::AddressSpace::PushConstant {
+                global.space = naga::AddressSpace::Uniform;
+                global.binding = Some(binding);
+            }
+        }
+
+        Ok(wgpu::ShaderSource::Naga(Cow::Owned(module)))
+    }
+
+    /// The source to create the shader module from.
+    ///
+    /// SPIR-V that is not a whole number of words is reported as [`RunError::Compile`], rather
+    /// than the panic wgpu would raise.
+    pub(crate) fn module_source(&self) -> Result<wgpu::ShaderSource<'_>, RunError> {
+        Ok(match self {
+            Self::Wgsl(source) => wgpu::ShaderSource::Wgsl(Cow::Borrowed(source)),
+            Self::SpirV(binary) => {
+                if !binary.len().is_multiple_of(size_of::<u32>()) {
+                    return Err(RunError::Compile(format!(
+                        "SPIR-V binary is {} bytes, which is not a whole number of words",
+                        binary.len()
+                    )));
+                }
+
+                wgpu::ShaderSource::SpirV(Cow::Owned(bytemuck::pod_collect_to_vec(binary)))
+            }
+            Self::Glsl(source) => wgpu::ShaderSource::Glsl {
+                shader: Cow::Borrowed(source),
+                stage: naga::ShaderStage::Compute,
+                defines: &[],
+            },
+        })
+    }
+}
+
+impl<'a> From<&'a str> for Shader<'a> {
+    fn from(source: &'a str) -> Self {
+        Self::Wgsl(Cow::Borrowed(source))
+    }
+}
+
+impl From<String> for Shader<'static> {
+    fn from(source: String) -> Self {
+        Self::Wgsl(Cow::Owned(source))
+    }
+}
+
+impl<'a> From<&'a Shader<'_>> for Shader<'a> {
+    fn from(shader: &'a Shader<'_>) -> Self {
+        match shader {
+            Shader::Wgsl(source) => Self::Wgsl(Cow::Borrowed(source)),
+            Shader::SpirV(binary) => Self::SpirV(Cow::Borrowed(binary)),
+            Shader::Glsl(source) => Self::Glsl(Cow::Borrowed(source)),
+        }
+    }
+}
@@ -0,0 +1,68 @@
+//! Destinations for the bytes read back from a run, so they can be routed without changing how
+//! the run itself reads them.
+
+use std::{
+    fs::File,
+    io::{self, Write as _},
+    net::TcpStream,
+    sync::mpsc,
+};
+
+/// Somewhere [`crate::GpuContext::read_into`] can send a buffer's contents.
+///
+/// This is implemented for files, stdout, sockets, `Vec<u8>`, channels, and closures, so custom
+/// sinks can usually be a closure.
+pub trait OutputSink {
+    /// Receives the whole contents of one buffer, or with
+    /// [`crate::GpuContext::read_chunked`], the next chunk of it.
+    fn write_output(&mut self, output: &[u8]) -> io::Result<()>;
+}
+
+impl OutputSink for File {
+    fn write_output(&mut self, output: &[u8]) -> io::Result<()> {
+        self.write_all(output)
+    }
+}
+
+impl OutputSink for io::Stdout {
+    fn write_output(&mut self, output: &[u8]) -> io::Result<()> {
+        let mut stdout = self.lock();
+        stdout.write_all(output)?;
+        stdout.flush()
+    }
+}
+
+impl OutputSink for TcpStream {
+    fn write_output(&mut self, output: &[u8]) -> io::Result<()> {
+        self.write_all(output)
+    }
+}
+
+#[cfg(unix)]
+impl OutputSink for std::os::unix::net::UnixStream {
+    fn write_output(&mut self, output: &[u8]) -> io::Result<()> {
+        self.write_all(output)
+    }
+}
+
+/// Appends each output, keeping them in memory.
+impl OutputSink for Vec<u8> {
+    fn write_output(&mut self, output: &[u8]) -> io::Result<()> {
+        self.extend_from_slice(output);
+        Ok(())
+    }
+}
+
+/// Sends each output to the receiver, failing once it has been dropped.
+impl OutputSink for mpsc::Sender<Vec<u8>> {
+    fn write_output(&mut self, output: &[u8]) -> io::Result<()> {
+        self.send(output.to_vec())
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "the receiver was dropped"))
+    }
+}
+
+impl<F: FnMut(&[u8]) -> io::Result<()>> OutputSink for F {
+    fn write_output(&mut self, output: &[u8]) -> io::Result<()> {
+        self(output)
+    }
+}
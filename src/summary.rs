@@ -0,0 +1,69 @@
+//! `--summarize`, which prints statistics and a histogram of the decoded output, to check at a
+//! glance that a kernel produced sensible data.
+
+use std::fmt::Write as _;
+
+use crate::post::Reduction;
+
+/// The number of histogram buckets the range of the output is split into.
+const BUCKETS: usize = 10;
+
+/// The width of the longest histogram bar, in characters.
+const BAR_WIDTH: usize = 40;
+
+/// Describes `values` with their count, min, max, mean, and standard deviation, then a histogram
+/// with one line per bucket.
+///
+/// NaN and infinite values are counted, but left out of the statistics and histogram.
+pub fn summarize(values: &[f64]) -> String {
+    let finite: Vec<f64> = values.iter().copied().filter(|v| v.is_finite()).collect();
+    let mut summary = format!("Summary: {} values", values.len());
+    if finite.len() != values.len() {
+        let _ = write!(
+            summary,
+            ", {} NaN or infinite and left out",
+            values.len() - finite.len()
+        );
+    }
+
+    if finite.is_empty() {
+        summary.push('\n');
+        return summary;
+    }
+
+    let [min, max, mean, std] = [
+        Reduction::Min,
+        Reduction::Max,
+        Reduction::Mean,
+        Reduction::Std,
+    ]
+    .map(|reduction| reduction.apply(&finite));
+    let _ = writeln!(summary, ", min {min}, max {max}, mean {mean}, std {std}");
+
+    // A constant output fills a single bucket.
+    let buckets = if min == max { 1 } else { BUCKETS };
+    let width = (max - min) / buckets as f64;
+    let mut counts = [0_usize; BUCKETS];
+    for value in &finite {
+        let bucket = if width > 0.0 {
+            (((value - min) / width) as usize).min(buckets - 1)
+        } else {
+            0
+        };
+        counts[bucket] += 1;
+    }
+
+    let largest = counts.iter().copied().max().unwrap_or(0);
+    for (bucket, &count) in counts[..buckets].iter().enumerate() {
+        let start = min + width * bucket as f64;
+        let end = if bucket == buckets - 1 {
+            max
+        } else {
+            start + width
+        };
+        let bar = "#".repeat((count * BAR_WIDTH).div_ceil(largest));
+        let _ = writeln!(summary, "  {start:>12.4} .. {end:<12.4} {count:>8}  {bar}");
+    }
+
+    summary
+}
@@ -0,0 +1,221 @@
+//! `sweep`, which runs a variant of the shader for every combination of `override` constant
+//! values, and tabulates how fast each one was.
+
+use std::{error::Error, str::FromStr, time::Duration};
+
+use gpu_scratch::{
+    GpuContext, MathProfile, RunError, ShaderRun,
+    hash::{Sha256, to_hex},
+    plan::{Budget, DispatchSize, Plan},
+    reflect::Reflection,
+    shader::Shader,
+};
+
+use crate::{
+    adapter_selection, cli, math_profile, output_size, read_inputs, read_params, set_constants,
+    shader_source,
+};
+
+/// The number of times `sweep` dispatches each variant by default.
+const DEFAULT_SWEEP_REPEAT: u32 = 3;
+
+/// The number of hex digits of each variant's output hash shown in the table.
+const HASH_DIGITS: usize = 12;
+
+/// A `--constant` to sweep, like `tile=8,16,32`.
+struct SweptConstant {
+    name: String,
+    values: Vec<f64>,
+}
+
+impl FromStr for SweptConstant {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (name, values) = value
+            .split_once('=')
+            .ok_or("expected `<name>=<values>`, like `tile=8,16,32`")?;
+
+        let values = values
+            .split(',')
+            .map(|value| value.trim().parse::<f64>().map_err(|err| err.to_string()))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            name: name.to_owned(),
+            values,
+        })
+    }
+}
+
+/// Every combination of one value from each of `constants`, in odometer order.
+fn combinations(constants: &[SweptConstant]) -> Vec<Vec<f64>> {
+    constants
+        .iter()
+        .fold(vec![Vec::new()], |combinations, constant| {
+            let extended = combinations.iter().flat_map(|combination| {
+                constant.values.iter().map(|&value| {
+                    let mut combination = combination.clone();
+                    combination.push(value);
+                    combination
+                })
+            });
+
+            extended.collect()
+        })
+}
+
+/// How a variant performed: the fastest of its runs, and the hash of that run's output.
+struct VariantResult {
+    elapsed: Duration,
+    gpu_elapsed: Option<Duration>,
+    hash: String,
+}
+
+/// Compiles the shader following `plan`, then runs it `repeat` times, keeping the fastest run.
+fn run_variant(
+    gpu: &GpuContext,
+    source: &Shader,
+    plan: &Plan,
+    profile: MathProfile,
+    repeat: u32,
+) -> Result<VariantResult, RunError> {
+    let prepared = gpu.prepare(source, plan, profile, None)?;
+
+    let mut fastest = None;
+    for _ in 0..repeat {
+        let shader_run = gpu.run_prepared(&prepared)?;
+        if fastest
+            .as_ref()
+            .is_none_or(|fastest: &ShaderRun| shader_run.elapsed < fastest.elapsed)
+        {
+            if let Some(slower) = fastest.replace(shader_run) {
+                gpu.recycle(slower);
+            }
+        } else {
+            gpu.recycle(shader_run);
+        }
+    }
+
+    let fastest = fastest.expect("--repeat is at least 1");
+    let mut hasher = Sha256::default();
+    hasher.update(&gpu.read_buffer(&fastest.output)?);
+    Ok(VariantResult {
+        elapsed: fastest.elapsed,
+        gpu_elapsed: fastest.gpu_elapsed,
+        hash: to_hex(&hasher.finish()),
+    })
+}
+
+fn millis(duration: Duration) -> String {
+    format!("{:.3}", duration.as_secs_f64() * 1000.0)
+}
+
+/// Runs the shader with every combination of the `--constant` values, printing a table of the
+/// fastest wall-clock and GPU times of each variant, then which variant was fastest.
+///
+/// Variants that fail to compile or run are reported in the table, and only fail the sweep if
+/// every variant did.
+pub async fn sweep(matches: &cli::Matches) -> Result<(), Box<dyn Error>> {
+    let profile = math_profile(matches)?;
+    let constants: Vec<SweptConstant> = matches.parse_values("constant")?;
+    let repeat: u32 = matches
+        .parse_value("repeat")?
+        .unwrap_or(DEFAULT_SWEEP_REPEAT);
+    if repeat == 0 {
+        return Err(cli::CliError::Unparsable {
+            name: String::from("`--repeat`"),
+            value: repeat.to_string(),
+            reason: String::from("must be at least 1"),
+        }
+        .into());
+    }
+
+    let gpu = GpuContext::with_selection(&adapter_selection(matches)?).await?;
+    let source = shader_source(matches)?;
+    let reflection = Reflection::new(&source)?;
+    let mut plan = Plan::new(&reflection, output_size(matches)?);
+    plan.set_entry_point(&reflection, matches.value("entry-point"))?;
+    // Swept constants replace any fixed `--const` of the same name in each variant.
+    set_constants(matches, &reflection, &mut plan)?;
+    plan.init = matches.parse_value("init")?;
+    for contents in read_inputs(matches)? {
+        plan.add_input(&reflection, contents);
+    }
+    if let Some(params) = read_params(matches)? {
+        plan.set_params_bytes(&reflection, params);
+    }
+    plan.bind_remaining(&reflection)?;
+    plan.dispatch = matches
+        .parse_value("dispatch")?
+        .unwrap_or(DispatchSize::ONE);
+    plan.check(&gpu.device.limits(), &Budget::default())?;
+
+    if constants.is_empty() {
+        log::warn!("No `--constant` was given, so only the shader's defaults will be run");
+    }
+
+    let combinations = combinations(&constants);
+    eprintln!(
+        "Sweeping {} variants, fastest of {repeat} runs each",
+        combinations.len()
+    );
+
+    let widths: Vec<_> = (constants.iter())
+        .map(|constant| constant.name.len().max(8))
+        .collect();
+    for (constant, width) in constants.iter().zip(&widths) {
+        print!("{:>width$}  ", constant.name);
+    }
+    println!("{:>10}  {:>10}  sha256", "wall ms", "gpu ms");
+
+    let mut fastest: Option<(Vec<f64>, Duration)> = None;
+    let mut first_error = None;
+    for combination in combinations {
+        for (constant, &value) in constants.iter().zip(&combination) {
+            plan.set_constant(&reflection, &constant.name, value)?;
+        }
+
+        for (value, width) in combination.iter().zip(&widths) {
+            print!("{value:>width$}  ");
+        }
+
+        match run_variant(&gpu, &source, &plan, profile, repeat) {
+            Ok(result) => {
+                let gpu_elapsed = result.gpu_elapsed.map_or(String::from("-"), millis);
+                println!(
+                    "{:>10}  {gpu_elapsed:>10}  {}",
+                    millis(result.elapsed),
+                    &result.hash[..HASH_DIGITS]
+                );
+
+                if fastest
+                    .as_ref()
+                    .is_none_or(|(_, elapsed)| result.elapsed < *elapsed)
+                {
+                    fastest = Some((combination, result.elapsed));
+                }
+            }
+            Err(err) => {
+                println!("failed: {err}");
+                first_error.get_or_insert(err);
+            }
+        }
+    }
+
+    let Some((combination, elapsed)) = fastest else {
+        return match first_error {
+            Some(err) => Err(err.into()),
+            None => Ok(()),
+        };
+    };
+
+    if !constants.is_empty() {
+        let values: Vec<_> = (constants.iter().zip(&combination))
+            .map(|(constant, value)| format!("{}={value}", constant.name))
+            .collect();
+        println!("Fastest: {} at {} ms", values.join(" "), millis(elapsed));
+    }
+
+    Ok(())
+}
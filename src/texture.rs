@@ -0,0 +1,402 @@
+//! Repacks linear buffers into 2D textures and back, so buffer-based and texture-based kernels
+//! can be mixed without writing the copy math by hand.
+//!
+//! Buffers hold texels row by row with no padding between rows. Copies between buffers and
+//! textures need rows padded to [`wgpu::COPY_BYTES_PER_ROW_ALIGNMENT`], so unless rows already
+//! line up with it, each row is copied on its own.
+
+use crate::{
+    GpuContext, RunError,
+    buffer::{BufferRole, BufferSpec, BufferSpecError},
+    scoped,
+};
+
+const STORE_SOURCE: &str = include_str!("texture_store.wgsl");
+const LOAD_SOURCE: &str = include_str!("texture_load.wgsl");
+
+/// The workgroup size of the conversion kernels along x and y.
+const WORKGROUP_SIZE: u32 = 8;
+
+/// How texels are laid out in a buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TexelEncoding {
+    /// The bytes of the texture's format, copied as they are.
+    Raw,
+    /// One `f32` per channel of the texture's format, converted by a shader.
+    ///
+    /// This works with the float formats WGSL can name as storage textures, and writing to a
+    /// texture also needs the adapter to support its format as one.
+    F32,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TextureError {
+    #[error("Textures must be at least 1x1, but got {0}x{1}")]
+    Empty(u32, u32),
+    #[error("{0:?} textures cannot be copied to or from buffers texel by texel")]
+    UnsupportedFormat(wgpu::TextureFormat),
+    #[error("{0:?} textures cannot be converted to or from `f32` channels on this adapter")]
+    UnsupportedConversion(wgpu::TextureFormat),
+    #[error("A {width}x{height} texture needs {expected} bytes, but the buffer is {actual}")]
+    BufferSize {
+        width: u32,
+        height: u32,
+        expected: u64,
+        actual: u64,
+    },
+    #[error(transparent)]
+    Buffer(#[from] BufferSpecError),
+    #[error(transparent)]
+    Run(#[from] RunError),
+}
+
+/// The WGSL name of each format [`TexelEncoding::F32`] converts, and its number of channels.
+const FLOAT_FORMATS: &[(wgpu::TextureFormat, &str, u32)] = &[
+    (wgpu::TextureFormat::R32Float, "r32float", 1),
+    (wgpu::TextureFormat::Rg32Float, "rg32float", 2),
+    (wgpu::TextureFormat::Rgba32Float, "rgba32float", 4),
+    (wgpu::TextureFormat::Rgba16Float, "rgba16float", 4),
+    (wgpu::TextureFormat::Rgba8Unorm, "rgba8unorm", 4),
+    (wgpu::TextureFormat::Rgba8Snorm, "rgba8snorm", 4),
+    (wgpu::TextureFormat::Bgra8Unorm, "bgra8unorm", 4),
+];
+
+fn float_format(format: wgpu::TextureFormat) -> Result<(&'static str, u32), TextureError> {
+    let (_, name, channels) = (FLOAT_FORMATS.iter())
+        .find(|(candidate, ..)| *candidate == format)
+        .ok_or(TextureError::UnsupportedConversion(format))?;
+
+    Ok((name, *channels))
+}
+
+/// The bytes one texel takes in a buffer encoded as `encoding`.
+fn texel_size(format: wgpu::TextureFormat, encoding: TexelEncoding) -> Result<u32, TextureError> {
+    match encoding {
+        TexelEncoding::Raw if format.block_dimensions() == (1, 1) => format
+            .block_copy_size(None)
+            .ok_or(TextureError::UnsupportedFormat(format)),
+        TexelEncoding::Raw => Err(TextureError::UnsupportedFormat(format)),
+        TexelEncoding::F32 => Ok(float_format(format)?.1 * size_of::<f32>() as u32),
+    }
+}
+
+/// Where a texture's rows are in a buffer holding them unpadded, and how to copy them.
+struct RowLayout {
+    width: u32,
+    height: u32,
+    bytes_per_row: u32,
+}
+
+impl RowLayout {
+    fn new(width: u32, height: u32, texel_size: u32) -> Self {
+        Self {
+            width,
+            height,
+            bytes_per_row: width * texel_size,
+        }
+    }
+
+    fn size(&self) -> u64 {
+        u64::from(self.bytes_per_row) * u64::from(self.height)
+    }
+
+    /// The copies to make, as the buffer layout, the first row, and the number of rows of each.
+    ///
+    /// A single copy of one row does not need its bytes per row aligned.
+    fn copies(&self) -> Vec<(wgpu::TexelCopyBufferLayout, u32, u32)> {
+        let aligned = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        if self.bytes_per_row.is_multiple_of(aligned) {
+            let layout = wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(self.bytes_per_row),
+                rows_per_image: None,
+            };
+            return vec![(layout, 0, self.height)];
+        }
+
+        (0..self.height)
+            .map(|row| {
+                let layout = wgpu::TexelCopyBufferLayout {
+                    offset: u64::from(row) * u64::from(self.bytes_per_row),
+                    bytes_per_row: None,
+                    rows_per_image: None,
+                };
+                (layout, row, 1)
+            })
+            .collect()
+    }
+
+    fn texture_copy<'a>(texture: &'a wgpu::Texture, row: u32) -> wgpu::TexelCopyTextureInfo<'a> {
+        wgpu::TexelCopyTextureInfo {
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d { x: 0, y: row, z: 0 },
+            aspect: wgpu::TextureAspect::All,
+        }
+    }
+
+    fn extent(&self, rows: u32) -> wgpu::Extent3d {
+        wgpu::Extent3d {
+            width: self.width,
+            height: rows,
+            depth_or_array_layers: 1,
+        }
+    }
+}
+
+impl GpuContext {
+    /// Creates a `width` by `height` texture of `format`, filled from `buffer` as `encoding`.
+    ///
+    /// The texture can be copied to and from, and bound as a sampled texture, or as a storage
+    /// texture if the adapter supports it for `format`. `buffer` must hold at least the texture's
+    /// texels, and have `COPY_SRC` usage for [`TexelEncoding::Raw`], or `STORAGE` usage for
+    /// [`TexelEncoding::F32`].
+    pub fn buffer_to_texture(
+        &self,
+        buffer: &wgpu::Buffer,
+        [width, height]: [u32; 2],
+        format: wgpu::TextureFormat,
+        encoding: TexelEncoding,
+    ) -> Result<wgpu::Texture, TextureError> {
+        if width == 0 || height == 0 {
+            return Err(TextureError::Empty(width, height));
+        }
+
+        let layout = RowLayout::new(width, height, texel_size(format, encoding)?);
+        if buffer.size() < layout.size() {
+            return Err(TextureError::BufferSize {
+                width,
+                height,
+                expected: layout.size(),
+                actual: buffer.size(),
+            });
+        }
+
+        let features = self.adapter.get_texture_format_features(format);
+        let storage = (features.allowed_usages).contains(wgpu::TextureUsages::STORAGE_BINDING);
+        if encoding == TexelEncoding::F32 && !storage {
+            return Err(TextureError::UnsupportedConversion(format));
+        }
+
+        let mut usage = wgpu::TextureUsages::COPY_SRC
+            | wgpu::TextureUsages::COPY_DST
+            | wgpu::TextureUsages::TEXTURE_BINDING;
+        if storage {
+            usage |= wgpu::TextureUsages::STORAGE_BINDING;
+        }
+
+        let (texture, error) = scoped(&self.device, || {
+            let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("texture"),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage,
+                view_formats: &[],
+            });
+
+            let mut encoder = self.device.create_command_encoder(&Default::default());
+            match encoding {
+                TexelEncoding::Raw => {
+                    for (buffer_layout, row, rows) in layout.copies() {
+                        encoder.copy_buffer_to_texture(
+                            wgpu::TexelCopyBufferInfo {
+                                buffer,
+                                layout: buffer_layout,
+                            },
+                            RowLayout::texture_copy(&texture, row),
+                            layout.extent(rows),
+                        );
+                    }
+                }
+                TexelEncoding::F32 => {
+                    let (name, channels) = float_format(format)?;
+                    let source = STORE_SOURCE.replace("FORMAT", name);
+                    self.encode_conversion(
+                        &mut encoder,
+                        &source,
+                        channels,
+                        wgpu::BindingType::StorageTexture {
+                            access: wgpu::StorageTextureAccess::WriteOnly,
+                            format,
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                        },
+                        &texture,
+                        buffer,
+                    );
+                }
+            }
+
+            self.queue.submit([encoder.finish()]);
+            Ok(texture)
+        });
+
+        match error {
+            Some(err) => Err(RunError::from(err).into()),
+            None => texture,
+        }
+    }
+
+    /// Copies the first mip level of the 2D `texture` into a new buffer, as `encoding`.
+    ///
+    /// The buffer holds the rows unpadded, rounded up to a multiple of
+    /// [`wgpu::COPY_BUFFER_ALIGNMENT`] bytes, and can be bound as a storage buffer or copied
+    /// from. `texture` needs `COPY_SRC` usage for [`TexelEncoding::Raw`], or `TEXTURE_BINDING`
+    /// usage for [`TexelEncoding::F32`].
+    pub fn texture_to_buffer(
+        &self,
+        texture: &wgpu::Texture,
+        encoding: TexelEncoding,
+    ) -> Result<wgpu::Buffer, TextureError> {
+        let format = texture.format();
+        let layout = RowLayout::new(
+            texture.width(),
+            texture.height(),
+            texel_size(format, encoding)?,
+        );
+        let size = layout.size().next_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT);
+        let spec = BufferSpec::new("texels", size)
+            .role(BufferRole::Input)
+            .role(BufferRole::Output);
+        spec.validate()?;
+
+        let (buffer, error) = scoped(&self.device, || {
+            let buffer = self.device.create_buffer(&spec.descriptor());
+            let mut encoder = self.device.create_command_encoder(&Default::default());
+            match encoding {
+                TexelEncoding::Raw => {
+                    for (buffer_layout, row, rows) in layout.copies() {
+                        encoder.copy_texture_to_buffer(
+                            RowLayout::texture_copy(texture, row),
+                            wgpu::TexelCopyBufferInfo {
+                                buffer: &buffer,
+                                layout: buffer_layout,
+                            },
+                            layout.extent(rows),
+                        );
+                    }
+                }
+                TexelEncoding::F32 => {
+                    let (_, channels) = float_format(format)?;
+                    self.encode_conversion(
+                        &mut encoder,
+                        LOAD_SOURCE,
+                        channels,
+                        wgpu::BindingType::Texture {
+                            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                            view_dimension: wgpu::TextureViewDimension::D2,
+                            multisampled: false,
+                        },
+                        texture,
+                        &buffer,
+                    );
+                }
+            }
+
+            self.queue.submit([encoder.finish()]);
+            Ok(buffer)
+        });
+
+        match error {
+            Some(err) => Err(RunError::from(err).into()),
+            None => buffer,
+        }
+    }
+
+    /// Encodes a pass of one of the conversion kernels, which bind `texture` at `@binding(0)` as
+    /// `texture_binding` and the buffer of `f32` channels at `@binding(1)`.
+    ///
+    /// The buffer is only written to when the texture is read from, rather than a storage
+    /// texture being written.
+    fn encode_conversion(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        source: &str,
+        channels: u32,
+        texture_binding: wgpu::BindingType,
+        texture: &wgpu::Texture,
+        buffer: &wgpu::Buffer,
+    ) {
+        let read_only = matches!(texture_binding, wgpu::BindingType::StorageTexture { .. });
+        let entries = [
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: texture_binding,
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ];
+
+        let bind_group_layout =
+            (self.device).create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some("texel-conversion"),
+                entries: &entries,
+            });
+        let pipeline_layout =
+            (self.device).create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("texel-conversion"),
+                bind_group_layouts: &[&bind_group_layout],
+                push_constant_ranges: &[],
+            });
+
+        let module = self
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("texel-conversion"),
+                source: wgpu::ShaderSource::Wgsl(source.into()),
+            });
+        let constants = [("CHANNELS", f64::from(channels))];
+        let pipeline = (self.device).create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("texel-conversion"),
+            layout: Some(&pipeline_layout),
+            module: &module,
+            entry_point: None,
+            compilation_options: wgpu::PipelineCompilationOptions {
+                constants: &constants,
+                ..Default::default()
+            },
+            cache: None,
+        });
+
+        let view = texture.create_view(&Default::default());
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("texel-conversion"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        let mut pass = encoder.begin_compute_pass(&Default::default());
+        pass.set_pipeline(&pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.dispatch_workgroups(
+            texture.width().div_ceil(WORKGROUP_SIZE),
+            texture.height().div_ceil(WORKGROUP_SIZE),
+            1,
+        );
+    }
+}
@@ -0,0 +1,23 @@
+// Converts a float texture into a buffer of f32 channels, stored row by row.
+
+override CHANNELS: u32;
+
+@group(0) @binding(0)
+var texture: texture_2d<f32>;
+
+@group(0) @binding(1)
+var<storage, read_write> texels: array<f32>;
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) id: vec3u) {
+    let size = textureDimensions(texture);
+    if any(id.xy >= size) {
+        return;
+    }
+
+    let base = (id.y * size.x + id.x) * CHANNELS;
+    let texel = textureLoad(texture, id.xy, 0);
+    for (var channel = 0u; channel < CHANNELS; channel++) {
+        texels[base + channel] = texel[channel];
+    }
+}
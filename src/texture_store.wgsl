@@ -0,0 +1,27 @@
+// Converts a buffer of f32 channels, stored row by row, into a storage texture of any float
+// format. `FORMAT` is replaced with the texture's format before compiling.
+
+override CHANNELS: u32;
+
+@group(0) @binding(0)
+var texture: texture_storage_2d<FORMAT, write>;
+
+@group(0) @binding(1)
+var<storage, read> texels: array<f32>;
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) id: vec3u) {
+    let size = textureDimensions(texture);
+    if any(id.xy >= size) {
+        return;
+    }
+
+    // Missing channels take the defaults texture reads would return.
+    let base = (id.y * size.x + id.x) * CHANNELS;
+    var texel = vec4f(0.0, 0.0, 0.0, 1.0);
+    for (var channel = 0u; channel < CHANNELS; channel++) {
+        texel[channel] = texels[base + channel];
+    }
+
+    textureStore(texture, id.xy, texel);
+}
@@ -0,0 +1,62 @@
+//! `run --watch`, which reruns the shader every time its file changes, so a kernel can be
+//! iterated on without restarting the process.
+
+use std::{
+    error::Error,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use crate::{cli, read_shader, run, run_context};
+
+/// How often the shader file is checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// When the file at `path` was last modified, or `None` if it cannot be read, such as while an
+/// editor is replacing it.
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+/// Runs the `--shader` file, then reruns it on the same device every time it is modified, until
+/// the process is interrupted.
+///
+/// Errors from a version of the shader are printed rather than returned, and the last version
+/// that ran stays the latest output until the file is fixed.
+pub async fn watch(matches: &cli::Matches) -> Result<(), Box<dyn Error>> {
+    matches.check_requires("watch", "shader")?;
+    matches.check_conflict("watch", "dry-run")?;
+    let path = matches.value("shader").expect("--shader is required");
+
+    let gpu = run_context(matches).await?;
+    let mut last_modified = None;
+    let mut last_working = None;
+    loop {
+        let modified = modified(Path::new(path));
+        if modified.is_none() || modified == last_modified {
+            std::thread::sleep(POLL_INTERVAL);
+            continue;
+        }
+
+        last_modified = modified;
+        eprintln!("Running {path}");
+        let result = match read_shader(matches, path) {
+            Ok(source) => run(matches, &gpu, &source).await,
+            Err(err) => Err(err.into()),
+        };
+
+        match result {
+            Ok(hash) => last_working = hash,
+            Err(err) => {
+                eprintln!("Error: {err}");
+                if let Some(hash) = &last_working {
+                    eprintln!("Keeping the last working version, with output sha256 {hash}");
+                }
+            }
+        }
+
+        eprintln!("Watching {path} for changes");
+    }
+}